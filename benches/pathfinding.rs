@@ -0,0 +1,70 @@
+// Times the pathfinding queries over the arcade maze.
+//
+//   cargo bench --bench pathfinding
+//
+// Plain timing loops, so it runs on stable.
+
+extern crate pacman;
+
+use std::time::Instant;
+
+use pacman::maze::{Maze, TilePos};
+use pacman::pathfinding::{find_path, DistanceMap, FlowField};
+
+const ITERATIONS: u32 = 1_000;
+
+fn bench<F: FnMut()>(name: &str, mut f: F) {
+    // Warm up before timing.
+    for _ in 0..ITERATIONS / 10 {
+        f();
+    }
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    let elapsed = start.elapsed();
+    println!("{:<24} {:>10.2?} per iteration", name, elapsed / ITERATIONS);
+}
+
+fn walkable(maze: &Maze) -> Vec<TilePos> {
+    maze.tiles()
+        .map(|(pos, _)| pos)
+        .filter(|&pos| maze.is_walkable(pos))
+        .collect()
+}
+
+fn main() {
+    let maze = Maze::arcade();
+    let tiles = walkable(&maze);
+    let corner = TilePos::new(1, 1);
+    let far_corner = TilePos::new(26, 29);
+
+    bench("distance map", || {
+        DistanceMap::new(&maze, &[corner]);
+    });
+
+    bench("a* corner to corner", || {
+        find_path(&maze, corner, far_corner).unwrap();
+    });
+
+    let mut i = 0;
+    bench("a* varied", || {
+        i = (i + 37) % tiles.len();
+        find_path(&maze, corner, tiles[i]);
+    });
+
+    // A flow field that is asked every frame but whose target only changes
+    // every eighth frame, like one following Pac-Man.
+    let mut field = FlowField::new();
+    let mut frame = 0;
+    bench("flow field per frame", || {
+        frame += 1;
+        field.update(&maze, tiles[(frame / 8) % tiles.len()]);
+    });
+
+    let map = DistanceMap::new(&maze, &[corner]);
+    bench("path from distance map", || {
+        map.path(&maze, far_corner).unwrap();
+    });
+}
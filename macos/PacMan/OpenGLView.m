@@ -44,6 +44,63 @@ uint64_t getPerformanceFrequency() {
     return (uint64_t)(timebaseInfo.denom * 1000000000.0 / timebaseInfo.numer);
 }
 
+const char *getDataPath() {
+    static char path[PATH_MAX];
+    @autoreleasepool {
+        NSURL *url = [[NSFileManager defaultManager] URLForDirectory:NSApplicationSupportDirectory
+                                                            inDomain:NSUserDomainMask
+                                                   appropriateForURL:nil
+                                                              create:YES
+                                                               error:nil];
+        if (url == nil) {
+            return NULL;
+        }
+        url = [url URLByAppendingPathComponent:@"PacMan" isDirectory:YES];
+        strlcpy(path, [url fileSystemRepresentation], sizeof(path));
+    }
+    return path;
+}
+
+static int translateKey(unsigned short keyCode) {
+    switch (keyCode) {
+        case 126: return PLATFORM_KEY_UP;
+        case 125: return PLATFORM_KEY_DOWN;
+        case 123: return PLATFORM_KEY_LEFT;
+        case 124: return PLATFORM_KEY_RIGHT;
+        case 36: return PLATFORM_KEY_ENTER;
+        case 49: return PLATFORM_KEY_SPACE;
+        case 53: return PLATFORM_KEY_ESCAPE;
+        case 51: return PLATFORM_KEY_BACKSPACE;
+        case 122: return PLATFORM_KEY_F1;
+        case 120: return PLATFORM_KEY_F2;
+        case 99: return PLATFORM_KEY_F3;
+        case 118: return PLATFORM_KEY_F4;
+        case 96: return PLATFORM_KEY_F5;
+        case 97: return PLATFORM_KEY_F6;
+        case 98: return PLATFORM_KEY_F7;
+        case 100: return PLATFORM_KEY_F8;
+        case 101: return PLATFORM_KEY_F9;
+        case 109: return PLATFORM_KEY_F10;
+        case 103: return PLATFORM_KEY_F11;
+        case 111: return PLATFORM_KEY_F12;
+        case 18: return PLATFORM_KEY_NUM1;
+        case 19: return PLATFORM_KEY_NUM2;
+        case 13: return PLATFORM_KEY_W;
+        case 0: return PLATFORM_KEY_A;
+        case 1: return PLATFORM_KEY_S;
+        case 2: return PLATFORM_KEY_D;
+        case 34: return PLATFORM_KEY_I;
+        case 38: return PLATFORM_KEY_J;
+        case 40: return PLATFORM_KEY_K;
+        case 37: return PLATFORM_KEY_L;
+        case 17: return PLATFORM_KEY_T;
+        case 3: return PLATFORM_KEY_F;
+        case 5: return PLATFORM_KEY_G;
+        case 4: return PLATFORM_KEY_H;
+        default: return 0;
+    }
+}
+
 @implementation OpenGLView {
     CVDisplayLinkRef displayLink;
 }
@@ -89,6 +146,7 @@ uint64_t getPerformanceFrequency() {
     PLATFORM.swap_gl_buffers = &swapGlBuffers;
     PLATFORM.get_performance_counter = &getPerformanceCounter;
     PLATFORM.get_performance_frequency = &getPerformanceFrequency;
+    PLATFORM.get_data_path = &getDataPath;
     game_load(&PLATFORM);
     
     // Create a display link capable of being used with all active displays
@@ -125,6 +183,92 @@ uint64_t getPerformanceFrequency() {
     game_on_platform_event(&event);
 }
 
+- (BOOL)acceptsFirstResponder {
+    return YES;
+}
+
+- (void)keyDown:(NSEvent *)nsEvent {
+    int key = translateKey([nsEvent keyCode]);
+    if (key == 0 || [nsEvent isARepeat]) {
+        return;
+    }
+
+    PlatformEvent event;
+    event.kind = PLATFORM_EVENT_KEY_DOWN;
+    event.data.key.key = key;
+    game_on_platform_event(&event);
+}
+
+- (void)keyUp:(NSEvent *)nsEvent {
+    int key = translateKey([nsEvent keyCode]);
+    if (key == 0) {
+        return;
+    }
+
+    PlatformEvent event;
+    event.kind = PLATFORM_EVENT_KEY_UP;
+    event.data.key.key = key;
+    game_on_platform_event(&event);
+}
+
+// Sends a mouse event in pixels from the top left, which is what the game
+// expects on every platform.
+- (void)sendMouseEvent:(int)kind button:(int)button at:(NSEvent *)nsEvent {
+    NSPoint point = [self convertPoint:[nsEvent locationInWindow] fromView:nil];
+
+    PlatformEvent event;
+    event.kind = kind;
+    event.data.mouse.x = point.x;
+    event.data.mouse.y = [self frame].size.height - point.y;
+    event.data.mouse.button = button;
+    game_on_platform_event(&event);
+}
+
+- (void)viewDidMoveToWindow {
+    [super viewDidMoveToWindow];
+    [[self window] setAcceptsMouseMovedEvents:YES];
+}
+
+- (void)mouseDown:(NSEvent *)nsEvent {
+    [self sendMouseEvent:PLATFORM_EVENT_MOUSE_DOWN button:PLATFORM_MOUSE_BUTTON_LEFT at:nsEvent];
+}
+
+- (void)mouseUp:(NSEvent *)nsEvent {
+    [self sendMouseEvent:PLATFORM_EVENT_MOUSE_UP button:PLATFORM_MOUSE_BUTTON_LEFT at:nsEvent];
+}
+
+- (void)rightMouseDown:(NSEvent *)nsEvent {
+    [self sendMouseEvent:PLATFORM_EVENT_MOUSE_DOWN button:PLATFORM_MOUSE_BUTTON_RIGHT at:nsEvent];
+}
+
+- (void)rightMouseUp:(NSEvent *)nsEvent {
+    [self sendMouseEvent:PLATFORM_EVENT_MOUSE_UP button:PLATFORM_MOUSE_BUTTON_RIGHT at:nsEvent];
+}
+
+- (void)otherMouseDown:(NSEvent *)nsEvent {
+    [self sendMouseEvent:PLATFORM_EVENT_MOUSE_DOWN button:PLATFORM_MOUSE_BUTTON_MIDDLE at:nsEvent];
+}
+
+- (void)otherMouseUp:(NSEvent *)nsEvent {
+    [self sendMouseEvent:PLATFORM_EVENT_MOUSE_UP button:PLATFORM_MOUSE_BUTTON_MIDDLE at:nsEvent];
+}
+
+- (void)mouseMoved:(NSEvent *)nsEvent {
+    [self sendMouseEvent:PLATFORM_EVENT_MOUSE_MOVE button:0 at:nsEvent];
+}
+
+- (void)mouseDragged:(NSEvent *)nsEvent {
+    [self sendMouseEvent:PLATFORM_EVENT_MOUSE_MOVE button:0 at:nsEvent];
+}
+
+- (void)rightMouseDragged:(NSEvent *)nsEvent {
+    [self sendMouseEvent:PLATFORM_EVENT_MOUSE_MOVE button:0 at:nsEvent];
+}
+
+- (void)otherMouseDragged:(NSEvent *)nsEvent {
+    [self sendMouseEvent:PLATFORM_EVENT_MOUSE_MOVE button:0 at:nsEvent];
+}
+
 - (void)dealloc {
     OPENGL_VIEW = NULL;
 
@@ -0,0 +1,514 @@
+use failure::{err_msg, Error};
+
+use collision::{Body, HITBOX_SIZE};
+use ghost::Ghost;
+use level::Fruit;
+use maze::{Direction, TilePos, TILE_SIZE};
+use state::{Decode, Encode, StateReader, StateWriter};
+use world::{steps, Player};
+
+// Refers to one actor. Once the actor is despawned the handle never matches
+// anything again, even when its slot is reused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Handle {
+    index: u32,
+    generation: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ActorKind {
+    // Points shown where a ghost was eaten.
+    Points(u32),
+    // The bonus fruit below the ghost house.
+    Fruit(Fruit),
+    PacMan(Player),
+    Ghost(Ghost),
+}
+
+impl ActorKind {
+    fn size(&self) -> (i32, i32) {
+        match *self {
+            // Just for show, nothing runs into them.
+            ActorKind::Points(_) => (0, 0),
+            ActorKind::Fruit(_) => (8, 8),
+            ActorKind::PacMan(_) | ActorKind::Ghost(_) => (HITBOX_SIZE, HITBOX_SIZE),
+        }
+    }
+
+    // Pac-Man and the ghosts move by the arcade's cornering, house and
+    // targeting rules, which `World` applies. Everything else just drifts.
+    pub fn is_character(&self) -> bool {
+        matches!(*self, ActorKind::PacMan(_) | ActorKind::Ghost(_))
+    }
+}
+
+// Where an actor is and where it's headed. Kept apart from the kind so that
+// Pac-Man and the ghosts can move it while looking at their own state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Motion {
+    pub x: i32,
+    pub y: i32,
+    pub dir: Direction,
+    // Same units as the level speeds, 0 stands still.
+    pub speed: u32,
+    progress: u32,
+}
+
+impl Motion {
+    pub fn tile(&self) -> TilePos {
+        TilePos::from_pixel(self.x, self.y)
+    }
+
+    pub fn at_tile_center(&self) -> bool {
+        self.x.rem_euclid(TILE_SIZE) == TILE_SIZE / 2 && self.y.rem_euclid(TILE_SIZE) == TILE_SIZE / 2
+    }
+
+    // Pixels to move this tick at the current speed.
+    pub fn steps(&mut self) -> u32 {
+        steps(&mut self.progress, self.speed)
+    }
+}
+
+// Something in the maze: Pac-Man, a ghost, the fruit or points on show.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Actor {
+    pub kind: ActorKind,
+    pub motion: Motion,
+    // Width and height of the collision box, centered on the position.
+    pub size: (i32, i32),
+    // Ticks since spawning, drives the animation.
+    pub age: u32,
+    // Ticks left before it despawns by itself.
+    pub lifetime: Option<u32>,
+}
+
+impl Actor {
+    pub fn new(kind: ActorKind, x: i32, y: i32) -> Actor {
+        Actor {
+            size: kind.size(),
+            kind,
+            motion: Motion {
+                x,
+                y,
+                dir: Direction::Left,
+                speed: 0,
+                progress: 0,
+            },
+            age: 0,
+            lifetime: None,
+        }
+    }
+
+    pub fn with_dir(mut self, dir: Direction) -> Actor {
+        self.motion.dir = dir;
+        self
+    }
+
+    pub fn with_lifetime(mut self, ticks: u32) -> Actor {
+        self.lifetime = Some(ticks);
+        self
+    }
+
+    pub fn tile(&self) -> TilePos {
+        self.motion.tile()
+    }
+
+    pub fn body(&self) -> Body {
+        Body::new(self.motion.x, self.motion.y, self.size)
+    }
+
+    pub fn as_pacman(&self) -> Option<&Player> {
+        match self.kind {
+            ActorKind::PacMan(ref pacman) => Some(pacman),
+            _ => None,
+        }
+    }
+
+    pub fn as_ghost(&self) -> Option<&Ghost> {
+        match self.kind {
+            ActorKind::Ghost(ref ghost) => Some(ghost),
+            _ => None,
+        }
+    }
+
+    // The motion together with the Pac-Man's own state, to move it by the
+    // Pac-Man rules.
+    pub fn as_pacman_mut(&mut self) -> Option<(&mut Motion, &mut Player)> {
+        match *self {
+            Actor {
+                ref mut motion,
+                kind: ActorKind::PacMan(ref mut pacman),
+                ..
+            } => Some((motion, pacman)),
+            _ => None,
+        }
+    }
+
+    pub fn as_ghost_mut(&mut self) -> Option<(&mut Motion, &mut Ghost)> {
+        match *self {
+            Actor {
+                ref mut motion,
+                kind: ActorKind::Ghost(ref mut ghost),
+                ..
+            } => Some((motion, ghost)),
+            _ => None,
+        }
+    }
+
+    // Moves and ages by one tick, returns false once its time is up.
+    // Characters are left to `World`.
+    fn tick(&mut self) -> bool {
+        if self.kind.is_character() {
+            return true;
+        }
+
+        let (dx, dy) = self.motion.dir.delta();
+        let pixels = self.motion.steps() as i32;
+        self.motion.x += dx * pixels;
+        self.motion.y += dy * pixels;
+        self.age += 1;
+
+        match self.lifetime {
+            Some(ref mut ticks) => {
+                *ticks = ticks.saturating_sub(1);
+                *ticks > 0
+            }
+            None => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Slot {
+    generation: u32,
+    actor: Option<Actor>,
+}
+
+// Actors in slots that are reused after a despawn. Iteration is in slot
+// order and the slot picked for a spawn only depends on earlier spawns and
+// despawns, so the same game always sees actors in the same order.
+//
+// To spawn or despawn while going through the actors, loop over `handles()`
+// and look each one up: despawned actors are skipped and new ones wait for
+// the next pass.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Actors {
+    slots: Vec<Slot>,
+    free: Vec<u32>,
+}
+
+impl Actors {
+    pub fn new() -> Actors {
+        Actors::default()
+    }
+
+    pub fn spawn(&mut self, actor: Actor) -> Handle {
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    actor: None,
+                });
+                self.slots.len() as u32 - 1
+            }
+        };
+
+        let slot = &mut self.slots[index as usize];
+        slot.actor = Some(actor);
+        Handle {
+            index,
+            generation: slot.generation,
+        }
+    }
+
+    pub fn despawn(&mut self, handle: Handle) -> Option<Actor> {
+        let slot = self.slots.get_mut(handle.index as usize)?;
+        if slot.generation != handle.generation {
+            return None;
+        }
+        let actor = slot.actor.take()?;
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(handle.index);
+        Some(actor)
+    }
+
+    pub fn clear(&mut self) {
+        for handle in self.handles() {
+            self.despawn(handle);
+        }
+    }
+
+    pub fn get(&self, handle: Handle) -> Option<&Actor> {
+        self.slots
+            .get(handle.index as usize)
+            .filter(|slot| slot.generation == handle.generation)
+            .and_then(|slot| slot.actor.as_ref())
+    }
+
+    pub fn get_mut(&mut self, handle: Handle) -> Option<&mut Actor> {
+        self.slots
+            .get_mut(handle.index as usize)
+            .filter(|slot| slot.generation == handle.generation)
+            .and_then(|slot| slot.actor.as_mut())
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Handle, &Actor)> {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            let handle = Handle {
+                index: index as u32,
+                generation: slot.generation,
+            };
+            slot.actor.as_ref().map(|actor| (handle, actor))
+        })
+    }
+
+    pub fn handles(&self) -> Vec<Handle> {
+        self.iter().map(|(handle, _)| handle).collect()
+    }
+
+    // Moves and ages everyone, despawning those whose lifetime ran out.
+    pub fn tick(&mut self) {
+        self.tick_if(|_| true);
+    }
+
+    // Like `tick`, but everyone else stays just as they are.
+    pub fn tick_if<F: Fn(&Actor) -> bool>(&mut self, filter: F) {
+        for handle in self.handles() {
+            let alive = self
+                .get_mut(handle)
+                .is_some_and(|actor| !filter(actor) || actor.tick());
+            if !alive {
+                self.despawn(handle);
+            }
+        }
+    }
+}
+
+impl Encode for Actor {
+    fn encode(&self, w: &mut StateWriter) {
+        match self.kind {
+            ActorKind::Points(points) => {
+                w.write_u8(0);
+                w.write_u32(points);
+            }
+            ActorKind::Fruit(fruit) => {
+                w.write_u8(1);
+                w.write(&fruit);
+            }
+            ActorKind::PacMan(ref pacman) => {
+                w.write_u8(2);
+                w.write(pacman);
+            }
+            ActorKind::Ghost(ref ghost) => {
+                w.write_u8(3);
+                w.write(ghost);
+            }
+        }
+        w.write_i32(self.motion.x);
+        w.write_i32(self.motion.y);
+        w.write(&self.motion.dir);
+        w.write_u32(self.motion.speed);
+        w.write_u32(self.motion.progress);
+        w.write_i32(self.size.0);
+        w.write_i32(self.size.1);
+        w.write_u32(self.age);
+        w.write(&self.lifetime);
+    }
+}
+
+impl Decode for Actor {
+    fn decode(r: &mut StateReader) -> Result<Actor, Error> {
+        let kind = match r.read_u8()? {
+            0 => ActorKind::Points(r.read_u32()?),
+            1 => ActorKind::Fruit(r.read()?),
+            2 => ActorKind::PacMan(r.read()?),
+            3 => ActorKind::Ghost(r.read()?),
+            kind => return Err(err_msg(format!("Invalid actor kind {}", kind))),
+        };
+        Ok(Actor {
+            kind,
+            motion: Motion {
+                x: r.read_i32()?,
+                y: r.read_i32()?,
+                dir: r.read()?,
+                speed: r.read_u32()?,
+                progress: r.read_u32()?,
+            },
+            size: (r.read_i32()?, r.read_i32()?),
+            age: r.read_u32()?,
+            lifetime: r.read()?,
+        })
+    }
+}
+
+impl Encode for Handle {
+    fn encode(&self, w: &mut StateWriter) {
+        w.write_u32(self.index);
+        w.write_u32(self.generation);
+    }
+}
+
+impl Decode for Handle {
+    fn decode(r: &mut StateReader) -> Result<Handle, Error> {
+        Ok(Handle {
+            index: r.read_u32()?,
+            generation: r.read_u32()?,
+        })
+    }
+}
+
+impl Encode for Actors {
+    fn encode(&self, w: &mut StateWriter) {
+        w.write_u32(self.slots.len() as u32);
+        for slot in self.slots.iter() {
+            w.write_u32(slot.generation);
+            w.write(&slot.actor);
+        }
+        w.write_u32(self.free.len() as u32);
+        for &index in self.free.iter() {
+            w.write_u32(index);
+        }
+    }
+}
+
+impl Decode for Actors {
+    fn decode(r: &mut StateReader) -> Result<Actors, Error> {
+        let count = r.read_u32()?;
+        let mut slots = Vec::new();
+        for _ in 0..count {
+            slots.push(Slot {
+                generation: r.read_u32()?,
+                actor: r.read()?,
+            });
+        }
+
+        let count = r.read_u32()?;
+        let mut free = Vec::new();
+        for _ in 0..count {
+            let index = r.read_u32()?;
+            if slots.get(index as usize).is_none_or(|slot| slot.actor.is_some()) || free.contains(&index) {
+                return Err(err_msg(format!("Invalid free slot {}", index)));
+            }
+            free.push(index);
+        }
+        Ok(Actors { slots, free })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ghost::GhostKind;
+    use maze::Maze;
+
+    fn points(points: u32) -> Actor {
+        Actor::new(ActorKind::Points(points), 0, 0)
+    }
+
+    fn kinds(actors: &Actors) -> Vec<ActorKind> {
+        actors.iter().map(|(_, actor)| actor.kind.clone()).collect()
+    }
+
+    #[test]
+    fn handles_go_stale_on_despawn() {
+        let mut actors = Actors::new();
+        let a = actors.spawn(points(100));
+        let b = actors.spawn(points(200));
+        assert_eq!(actors.despawn(a).map(|actor| actor.kind), Some(ActorKind::Points(100)));
+        assert!(actors.get(a).is_none());
+        assert!(actors.despawn(a).is_none());
+
+        // The slot is reused, the old handle still doesn't match.
+        let c = actors.spawn(points(300));
+        assert!(actors.get(a).is_none());
+        assert_eq!(actors.get(c).unwrap().kind, ActorKind::Points(300));
+        assert_eq!(actors.get(b).unwrap().kind, ActorKind::Points(200));
+        assert_eq!(actors.len(), 2);
+    }
+
+    #[test]
+    fn spawning_while_iterating() {
+        let mut actors = Actors::new();
+        actors.spawn(points(1));
+        actors.spawn(points(2));
+
+        for handle in actors.handles() {
+            if actors.get(handle).unwrap().kind == ActorKind::Points(1) {
+                actors.despawn(handle);
+                actors.spawn(points(3));
+            }
+        }
+        assert_eq!(kinds(&actors), vec![ActorKind::Points(3), ActorKind::Points(2)]);
+    }
+
+    #[test]
+    fn characters_are_left_to_the_world() {
+        let mut actors = Actors::new();
+        let mut pacman = Player::actor(0, (20, 20));
+        pacman.motion.speed = 80;
+        let pacman = actors.spawn(pacman);
+        actors.tick();
+        assert_eq!(actors.get(pacman).unwrap().motion.x, 20);
+        assert!(actors.get(pacman).unwrap().as_pacman().is_some());
+        assert!(actors.get(pacman).unwrap().as_ghost().is_none());
+    }
+
+    #[test]
+    fn tick_moves_and_expires() {
+        let mut actors = Actors::new();
+        let mut moving = points(1).with_dir(Direction::Right);
+        moving.motion.speed = 80;
+        let moving = actors.spawn(moving);
+        let expiring = actors.spawn(points(2).with_lifetime(2));
+
+        actors.tick();
+        assert!(actors.get(expiring).is_some());
+        actors.tick();
+        assert!(actors.get(expiring).is_none());
+        assert_eq!(actors.get(moving).unwrap().motion.x, 2);
+        assert_eq!(actors.get(moving).unwrap().age, 2);
+
+        // Left out actors don't age or expire.
+        let waiting = actors.spawn(points(3).with_lifetime(1));
+        actors.tick_if(|actor| actor.kind != ActorKind::Points(3));
+        assert_eq!(actors.get(waiting).unwrap().lifetime, Some(1));
+        assert_eq!(actors.get(moving).unwrap().age, 3);
+    }
+
+    #[test]
+    fn round_trips_through_save_state() {
+        let mut actors = Actors::new();
+        let a = actors.spawn(points(1).with_lifetime(10));
+        actors.spawn(points(2));
+        actors.despawn(a);
+        let maze = Maze::arcade();
+        actors.spawn(Player::actor(1, maze.start_position()));
+        actors.spawn(Ghost::actor(GhostKind::Inky, maze.house()));
+
+        let data = ::state::encode(|w| w.write(&actors));
+        let mut r = ::state::decode(&data).unwrap();
+        assert_eq!(r.read::<Actors>().unwrap(), actors);
+        r.finish().unwrap();
+    }
+
+    #[test]
+    fn free_slots_are_checked() {
+        let mut actors = Actors::new();
+        let a = actors.spawn(points(1));
+        actors.spawn(points(2));
+        actors.despawn(a);
+        actors.free.push(a.index);
+
+        let data = ::state::encode(|w| w.write(&actors));
+        assert!(::state::decode(&data).unwrap().read::<Actors>().is_err());
+    }
+}
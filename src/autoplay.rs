@@ -0,0 +1,268 @@
+use std::collections::VecDeque;
+
+use ghost::GhostState;
+use maze::{Direction, Maze, Tile, TilePos};
+use multiplayer::MAX_PLAYERS;
+use pathfinding::{self, DistanceMap};
+use replay::Replay;
+use world::World;
+use Buttons;
+
+// Where the buttons for each tick come from.
+pub trait InputSource {
+    // The buttons held during the next tick of `world`, None once the source
+    // has nothing more to give.
+    fn next(&mut self, world: &World) -> Option<Buttons>;
+
+    // The buttons of every player for the next tick. Sources that only
+    // steer one player steer the first.
+    fn next_players(&mut self, world: &World) -> Option<[Buttons; MAX_PLAYERS]> {
+        let mut buttons = [Buttons::empty(); MAX_PLAYERS];
+        buttons[0] = self.next(world)?;
+        Some(buttons)
+    }
+}
+
+// Keys reported by the platform. Keys pressed and released between two
+// ticks still count as held for one tick, so quick taps aren't lost.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Keyboard {
+    held: Buttons,
+    latched: Buttons,
+}
+
+impl Keyboard {
+    pub fn new() -> Keyboard {
+        Keyboard::default()
+    }
+
+    pub fn press(&mut self, button: Buttons) {
+        self.held.insert(button);
+        self.latched.insert(button);
+    }
+
+    pub fn release(&mut self, button: Buttons) {
+        self.held.remove(button);
+    }
+
+    pub fn take(&mut self) -> Buttons {
+        let buttons = self.held | self.latched;
+        self.latched = Buttons::empty();
+        buttons
+    }
+}
+
+impl InputSource for Keyboard {
+    fn next(&mut self, _world: &World) -> Option<Buttons> {
+        Some(self.take())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayInput {
+    replay: Replay,
+    tick: usize,
+}
+
+impl ReplayInput {
+    pub fn new(replay: Replay) -> ReplayInput {
+        ReplayInput { replay, tick: 0 }
+    }
+
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    pub fn tick(&self) -> usize {
+        self.tick
+    }
+}
+
+impl InputSource for ReplayInput {
+    fn next(&mut self, _world: &World) -> Option<Buttons> {
+        let buttons = self.replay.get(self.tick)?;
+        self.tick += 1;
+        Some(buttons)
+    }
+
+    fn next_players(&mut self, _world: &World) -> Option<[Buttons; MAX_PLAYERS]> {
+        let buttons = self.replay.get_players(self.tick)?;
+        self.tick += 1;
+        Some(buttons)
+    }
+}
+
+// Ghosts closer than this many tiles along the maze make the bot run.
+pub const DEFAULT_DANGER_RADIUS: u32 = 4;
+
+// Plays Pac-Man well enough for the attract demo and soak tests. It heads
+// for the nearest pellet it can reach before any ghost can and runs from
+// ghosts that get within the danger radius. It only looks at the world, so
+// it has no state of its own and stays deterministic.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bot {
+    danger_radius: u32,
+}
+
+impl Bot {
+    pub fn new(danger_radius: u32) -> Bot {
+        Bot { danger_radius }
+    }
+
+    pub fn direction(&self, world: &World) -> Option<Direction> {
+        let maze = world.maze();
+        let pacman = world.pacman().tile();
+        if !maze.contains(pacman) {
+            return None;
+        }
+
+        let threats: Vec<TilePos> = world
+            .ghosts()
+            .filter(|&(_, ghost)| {
+                !ghost.frightened
+                    && matches!(ghost.state, GhostState::Active | GhostState::LeavingHouse)
+            })
+            .map(|(actor, _)| actor.tile())
+            .collect();
+        let danger = DistanceMap::new(maze, &threats);
+        let threat_distance = |pos: TilePos| danger.get(pos).unwrap_or(u32::MAX);
+
+        let pellet = nearest_pellet(maze, pacman, &danger);
+        let here = threat_distance(pacman);
+        if here > self.danger_radius {
+            return pellet;
+        }
+
+        // Too close: move away, towards the pellet if that is away too.
+        let away = |dir: &Direction| threat_distance(pathfinding::neighbor(maze, pacman, *dir)) > here;
+        if let Some(dir) = pellet.filter(|dir| away(dir)) {
+            return Some(dir);
+        }
+        pathfinding::neighbors(maze, pacman)
+            .max_by_key(|&(_, next)| threat_distance(next))
+            .map(|(dir, _)| dir)
+    }
+}
+
+impl Default for Bot {
+    fn default() -> Bot {
+        Bot::new(DEFAULT_DANGER_RADIUS)
+    }
+}
+
+impl InputSource for Bot {
+    fn next(&mut self, world: &World) -> Option<Buttons> {
+        Some(match self.direction(world) {
+            Some(Direction::Up) => Buttons::UP,
+            Some(Direction::Down) => Buttons::DOWN,
+            Some(Direction::Left) => Buttons::LEFT,
+            Some(Direction::Right) => Buttons::RIGHT,
+            None => Buttons::empty(),
+        })
+    }
+}
+
+// The first step towards the closest pellet that can be reached without
+// passing a tile a ghost could get to first.
+fn nearest_pellet(maze: &Maze, from: TilePos, danger: &DistanceMap) -> Option<Direction> {
+    let len = (maze.width() * maze.height()) as usize;
+    let index = |pos: TilePos| (pos.y * maze.width() + pos.x) as usize;
+    let mut first_step = vec![None; len];
+    let mut distance = vec![None; len];
+    let mut queue = VecDeque::new();
+    distance[index(from)] = Some(0);
+    queue.push_back(from);
+
+    while let Some(pos) = queue.pop_front() {
+        let i = index(pos);
+        if pos != from && matches!(maze.get(pos), Tile::Pellet | Tile::Energizer) {
+            return first_step[i];
+        }
+
+        let next_distance = distance[i].unwrap() + 1;
+        for (dir, next) in pathfinding::neighbors(maze, pos) {
+            let j = index(next);
+            if distance[j].is_some() || danger.get(next).is_some_and(|danger| danger <= next_distance) {
+                continue;
+            }
+            distance[j] = Some(next_distance);
+            first_step[j] = first_step[i].or(Some(dir));
+            queue.push_back(next);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use level::LevelTable;
+    use phase::Phase;
+    use Input;
+
+    fn world() -> World {
+        World::new(LevelTable::arcade(), Maze::arcade(), 1)
+    }
+
+    #[test]
+    fn replay_input_runs_out() {
+        let mut replay = Replay::new(0);
+        replay.push(Buttons::START);
+        let mut input = ReplayInput::new(replay);
+        let world = world();
+        assert_eq!(input.next(&world), Some(Buttons::START));
+        assert_eq!(input.next(&world), None);
+        assert_eq!(input.tick(), 1);
+    }
+
+    #[test]
+    fn replay_input_steers_every_player() {
+        let mut replay = Replay::new(0);
+        replay.push_players(&[Buttons::UP, Buttons::START2]);
+        let mut input = ReplayInput::new(replay);
+        let world = world();
+        let buttons = input.next_players(&world).unwrap();
+        assert_eq!(buttons[..2], [Buttons::UP, Buttons::START2]);
+        assert_eq!(input.next_players(&world), None);
+
+        // Everyone else stands still for a source with one player.
+        let mut keyboard = Keyboard::new();
+        keyboard.press(Buttons::LEFT);
+        let buttons = keyboard.next_players(&world).unwrap();
+        assert_eq!(buttons[0], Buttons::LEFT);
+        assert!(buttons[1..].iter().all(|buttons| buttons.is_empty()));
+    }
+
+    #[test]
+    fn keyboard_keeps_taps() {
+        let mut keyboard = Keyboard::new();
+        keyboard.press(Buttons::LEFT);
+        keyboard.release(Buttons::LEFT);
+        assert_eq!(keyboard.take(), Buttons::LEFT);
+        assert_eq!(keyboard.take(), Buttons::empty());
+    }
+
+    #[test]
+    fn bot_plays_better_than_standing_still() {
+        let mut world = world();
+        let mut phase = Phase::default();
+        let mut input = Input::new();
+        let mut bot = Bot::default();
+
+        input.update(Buttons::START);
+        phase.update(&mut world, &input);
+        for _ in 0..20 * 60 * 60 {
+            let buttons = match phase {
+                Phase::Playing(_) => bot.next(&world).unwrap(),
+                Phase::GameOver(_) => break,
+                _ => Buttons::empty(),
+            };
+            input.update(buttons);
+            world.clear_events();
+            phase.update(&mut world, &input);
+        }
+
+        // Standing still only scores the pellets Pac-Man starts next to.
+        assert!(world.score().score() >= 2000, "Scored {}", world.score().score());
+    }
+}
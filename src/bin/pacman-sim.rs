@@ -0,0 +1,172 @@
+// Runs the game without a window and prints where it ended up as JSON.
+//
+//   pacman-sim [--seed N] [--ticks N] [--party SETUP]
+//              [--replay FILE | --script FILE | --bot]
+//
+// Input comes from a replay file recorded by the game or a script (see
+// `Replay::parse_script`), for every player in it. Without either, START is
+// pressed once and the player stands still. `--party` makes START play a
+// party game like $PACMAN_PARTY does, "coop 3" or "versus 2". `--ticks`
+// defaults to the length of the input, any ticks past its end are run with
+// no buttons held.
+//
+// `--bot` soak tests the game: the autoplay bot plays game after game for
+// `--ticks`, an hour of game time by default.
+
+extern crate failure;
+extern crate pacman;
+
+use std::env;
+use std::fs;
+use std::process;
+
+use failure::{err_msg, Error};
+use pacman::autoplay::{Bot, InputSource, ReplayInput};
+use pacman::level::LevelTable;
+use pacman::maze::Maze;
+use pacman::multiplayer::Party;
+use pacman::phase::Phase;
+use pacman::replay::Replay;
+use pacman::simulation::Simulation;
+use pacman::world::{World, DEFAULT_SEED, TICKS_PER_SECOND};
+use pacman::{Buttons, Input};
+
+struct Options {
+    seed: u32,
+    ticks: Option<usize>,
+    party: Option<Party>,
+    replay: Option<String>,
+    script: Option<String>,
+    bot: bool,
+}
+
+fn parse_args() -> Result<Options, Error> {
+    let mut options = Options {
+        seed: DEFAULT_SEED,
+        ticks: None,
+        party: None,
+        replay: None,
+        script: None,
+        bot: false,
+    };
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| err_msg(format!("{} needs a value", arg)));
+        match arg.as_str() {
+            "--seed" => options.seed = value()?.parse()?,
+            "--ticks" => options.ticks = Some(value()?.parse()?),
+            "--party" => options.party = Some(value()?.parse()?),
+            "--replay" => options.replay = Some(value()?),
+            "--script" => options.script = Some(value()?),
+            "--bot" => options.bot = true,
+            _ => return Err(err_msg(format!("Unknown argument {}", arg))),
+        }
+    }
+
+    let sources = options.replay.is_some() as u32 + options.script.is_some() as u32 + options.bot as u32;
+    if sources > 1 {
+        return Err(err_msg("Only one of --replay, --script and --bot can be used"));
+    }
+    Ok(options)
+}
+
+// The arcade game, with the party setup from the options.
+fn simulation(options: &Options, seed: u32) -> Simulation {
+    let mut world = World::new(LevelTable::arcade(), Maze::arcade(), seed);
+    world.set_party_setup(options.party.clone());
+    Simulation::new(world)
+}
+
+// Plays with the bot and taps START whenever there is no game to play, which
+// also gets through entering initials.
+struct Soak {
+    bot: Bot,
+    tick: usize,
+}
+
+impl Soak {
+    fn next(&mut self, simulation: &Simulation) -> Buttons {
+        self.tick += 1;
+        match *simulation.phase() {
+            Phase::Playing(_) => self.bot.next(simulation.world()).unwrap_or_default(),
+            Phase::Attract(_) | Phase::Demo(_) | Phase::GameOver(_) if self.tick.is_multiple_of(2) => Buttons::START,
+            _ => Buttons::empty(),
+        }
+    }
+}
+
+fn soak(options: &Options) -> Result<(), Error> {
+    let mut simulation = simulation(options, options.seed);
+    let mut soak = Soak {
+        bot: Bot::default(),
+        tick: 0,
+    };
+
+    let ticks = options.ticks.unwrap_or(60 * 60 * TICKS_PER_SECOND as usize);
+    let mut input = Input::new();
+    for _ in 0..ticks {
+        input.update(soak.next(&simulation));
+        simulation.update(&input);
+    }
+
+    report(&simulation, ticks);
+    Ok(())
+}
+
+fn run(options: &Options) -> Result<(), Error> {
+    if options.bot {
+        return soak(options);
+    }
+
+    let replay = match (&options.replay, &options.script) {
+        (Some(path), _) => Replay::load(path)?,
+        (_, Some(path)) => Replay::parse_script(options.seed, &fs::read_to_string(path)?)?,
+        _ => {
+            let mut replay = Replay::new(options.seed);
+            replay.push(Buttons::START);
+            replay
+        }
+    };
+
+    let mut simulation = simulation(options, replay.seed());
+    if let Some(state) = replay.start_state() {
+        simulation.load_state(state)?;
+    }
+    if replay.setup_hash().is_some_and(|hash| hash != simulation.setup_hash()) {
+        return Err(err_msg("The replay was recorded with another maze, level table or settings"));
+    }
+
+    let ticks = options.ticks.unwrap_or_else(|| replay.len());
+    let mut source = ReplayInput::new(replay);
+    let mut input = Input::new();
+    for _ in 0..ticks {
+        input.update_players(&source.next_players(simulation.world()).unwrap_or_default());
+        simulation.update(&input);
+    }
+
+    report(&simulation, ticks);
+    Ok(())
+}
+
+fn report(simulation: &Simulation, ticks: usize) {
+    let world = simulation.world();
+    println!(
+        "{{\"ticks\":{},\"seed\":{},\"score\":{},\"lives\":{},\"level\":{},\"phase\":\"{}\",\"state_hash\":\"{:08x}\"}}",
+        ticks,
+        simulation.seed(),
+        world.score().score(),
+        world.score().lives(),
+        world.level(),
+        simulation.phase().name(),
+        simulation.state_hash()
+    );
+}
+
+fn main() {
+    let result = parse_args().and_then(|options| run(&options));
+    if let Err(e) = result {
+        eprintln!("pacman-sim: {}", e);
+        process::exit(1);
+    }
+}
@@ -12,6 +12,54 @@ enum PlatformEventKind {
     PLATFORM_EVENT_RENDER = 2,
     PLATFORM_EVENT_CLOSE = 3,
     PLATFORM_EVENT_RESIZE = 4,
+    PLATFORM_EVENT_KEY_DOWN = 5,
+    PLATFORM_EVENT_KEY_UP = 6,
+    PLATFORM_EVENT_MOUSE_MOVE = 7,
+    PLATFORM_EVENT_MOUSE_DOWN = 8,
+    PLATFORM_EVENT_MOUSE_UP = 9,
+};
+
+enum PlatformMouseButton {
+    PLATFORM_MOUSE_BUTTON_LEFT = 1,
+    PLATFORM_MOUSE_BUTTON_RIGHT = 2,
+    PLATFORM_MOUSE_BUTTON_MIDDLE = 3,
+};
+
+enum PlatformKey {
+    PLATFORM_KEY_UP = 1,
+    PLATFORM_KEY_DOWN = 2,
+    PLATFORM_KEY_LEFT = 3,
+    PLATFORM_KEY_RIGHT = 4,
+    PLATFORM_KEY_ENTER = 5,
+    PLATFORM_KEY_SPACE = 6,
+    PLATFORM_KEY_ESCAPE = 7,
+    PLATFORM_KEY_BACKSPACE = 8,
+    PLATFORM_KEY_F1 = 9,
+    PLATFORM_KEY_F2 = 10,
+    PLATFORM_KEY_F3 = 11,
+    PLATFORM_KEY_F4 = 12,
+    PLATFORM_KEY_F5 = 13,
+    PLATFORM_KEY_F6 = 14,
+    PLATFORM_KEY_F7 = 15,
+    PLATFORM_KEY_F8 = 16,
+    PLATFORM_KEY_F9 = 17,
+    PLATFORM_KEY_F10 = 18,
+    PLATFORM_KEY_F11 = 19,
+    PLATFORM_KEY_F12 = 20,
+    PLATFORM_KEY_NUM1 = 21,
+    PLATFORM_KEY_NUM2 = 22,
+    PLATFORM_KEY_W = 23,
+    PLATFORM_KEY_A = 24,
+    PLATFORM_KEY_S = 25,
+    PLATFORM_KEY_D = 26,
+    PLATFORM_KEY_I = 27,
+    PLATFORM_KEY_J = 28,
+    PLATFORM_KEY_K = 29,
+    PLATFORM_KEY_L = 30,
+    PLATFORM_KEY_T = 31,
+    PLATFORM_KEY_F = 32,
+    PLATFORM_KEY_G = 33,
+    PLATFORM_KEY_H = 34,
 };
 
 typedef struct PlatformEvent {
@@ -21,6 +69,16 @@ typedef struct PlatformEvent {
             int width;
             int height;
         } resize;
+        struct {
+            int key;
+        } key;
+        // In window pixels from the top left, like resize. The button is 0
+        // for moves.
+        struct {
+            int x;
+            int y;
+            int button;
+        } mouse;
     } data;
 } PlatformEvent;
 
@@ -32,6 +90,9 @@ typedef struct Platform {
 
     uint64_t (*get_performance_counter)(void);
     uint64_t (*get_performance_frequency)(void);
+
+    // Directory for persistent files such as the high score table. May be NULL.
+    const char *(*get_data_path)(void);
 } Platform;
 
 extern void game_load(Platform *);
@@ -1,11 +1,56 @@
 use std::os::raw::*;
+use std::ptr;
 
 pub const PLATFORM_EVENT_UPDATE: c_int = 1;
 pub const PLATFORM_EVENT_RENDER: c_int = 2;
 pub const PLATFORM_EVENT_CLOSE: c_int = 3;
 pub const PLATFORM_EVENT_RESIZE: c_int = 4;
+pub const PLATFORM_EVENT_KEY_DOWN: c_int = 5;
+pub const PLATFORM_EVENT_KEY_UP: c_int = 6;
+pub const PLATFORM_EVENT_MOUSE_MOVE: c_int = 7;
+pub const PLATFORM_EVENT_MOUSE_DOWN: c_int = 8;
+pub const PLATFORM_EVENT_MOUSE_UP: c_int = 9;
 
-static mut PLATFORM: *mut Platform = 0 as *mut Platform;
+pub const PLATFORM_MOUSE_BUTTON_LEFT: c_int = 1;
+pub const PLATFORM_MOUSE_BUTTON_RIGHT: c_int = 2;
+pub const PLATFORM_MOUSE_BUTTON_MIDDLE: c_int = 3;
+
+pub const PLATFORM_KEY_UP: c_int = 1;
+pub const PLATFORM_KEY_DOWN: c_int = 2;
+pub const PLATFORM_KEY_LEFT: c_int = 3;
+pub const PLATFORM_KEY_RIGHT: c_int = 4;
+pub const PLATFORM_KEY_ENTER: c_int = 5;
+pub const PLATFORM_KEY_SPACE: c_int = 6;
+pub const PLATFORM_KEY_ESCAPE: c_int = 7;
+pub const PLATFORM_KEY_BACKSPACE: c_int = 8;
+pub const PLATFORM_KEY_F1: c_int = 9;
+pub const PLATFORM_KEY_F2: c_int = 10;
+pub const PLATFORM_KEY_F3: c_int = 11;
+pub const PLATFORM_KEY_F4: c_int = 12;
+pub const PLATFORM_KEY_F5: c_int = 13;
+pub const PLATFORM_KEY_F6: c_int = 14;
+pub const PLATFORM_KEY_F7: c_int = 15;
+pub const PLATFORM_KEY_F8: c_int = 16;
+pub const PLATFORM_KEY_F9: c_int = 17;
+pub const PLATFORM_KEY_F10: c_int = 18;
+pub const PLATFORM_KEY_F11: c_int = 19;
+pub const PLATFORM_KEY_F12: c_int = 20;
+pub const PLATFORM_KEY_NUM1: c_int = 21;
+pub const PLATFORM_KEY_NUM2: c_int = 22;
+pub const PLATFORM_KEY_W: c_int = 23;
+pub const PLATFORM_KEY_A: c_int = 24;
+pub const PLATFORM_KEY_S: c_int = 25;
+pub const PLATFORM_KEY_D: c_int = 26;
+pub const PLATFORM_KEY_I: c_int = 27;
+pub const PLATFORM_KEY_J: c_int = 28;
+pub const PLATFORM_KEY_K: c_int = 29;
+pub const PLATFORM_KEY_L: c_int = 30;
+pub const PLATFORM_KEY_T: c_int = 31;
+pub const PLATFORM_KEY_F: c_int = 32;
+pub const PLATFORM_KEY_G: c_int = 33;
+pub const PLATFORM_KEY_H: c_int = 34;
+
+static mut PLATFORM: *mut Platform = ptr::null_mut();
 
 #[repr(C)]
 pub struct PlatformEvent {
@@ -16,6 +61,8 @@ pub struct PlatformEvent {
 #[repr(C)]
 pub union PlatformEventData {
     pub resize: PlatformEventDataResize,
+    pub key: PlatformEventDataKey,
+    pub mouse: PlatformEventDataMouse,
 }
 
 #[derive(Copy, Clone)]
@@ -25,6 +72,20 @@ pub struct PlatformEventDataResize {
     pub height: c_int,
 }
 
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct PlatformEventDataKey {
+    pub key: c_int,
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct PlatformEventDataMouse {
+    pub x: c_int,
+    pub y: c_int,
+    pub button: c_int,
+}
+
 #[repr(C)]
 pub struct Platform {
     quit: unsafe extern "C" fn(),
@@ -34,6 +95,8 @@ pub struct Platform {
 
     get_performance_counter: unsafe extern "C" fn() -> u64,
     get_performance_frequency: unsafe extern "C" fn() -> u64,
+
+    get_data_path: Option<unsafe extern "C" fn() -> *const c_char>,
 }
 
 pub unsafe fn quit() {
@@ -56,6 +119,17 @@ pub unsafe fn get_performance_frequency() -> u64 {
     ((*PLATFORM).get_performance_frequency)()
 }
 
+pub unsafe fn get_data_path() -> *const c_char {
+    if PLATFORM.is_null() {
+        return ptr::null();
+    }
+
+    match (*PLATFORM).get_data_path {
+        Some(get_data_path) => get_data_path(),
+        None => ptr::null(),
+    }
+}
+
 pub unsafe fn init(platform: *mut Platform) {
     PLATFORM = platform;
 }
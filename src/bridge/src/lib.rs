@@ -1,12 +1,68 @@
+#![allow(clippy::missing_safety_doc)]
+
+use std::ffi::CStr;
+use std::path::PathBuf;
+
 pub mod bridge;
 
 pub use bridge::{get_gl_proc_address, swap_gl_buffers};
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Up,
+    Down,
+    Left,
+    Right,
+    Enter,
+    Space,
+    Escape,
+    Backspace,
+    F1,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    F8,
+    F9,
+    F10,
+    F11,
+    F12,
+    Num1,
+    Num2,
+    W,
+    A,
+    S,
+    D,
+    I,
+    J,
+    K,
+    L,
+    T,
+    F,
+    G,
+    H,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+}
+
+// Mouse positions are in window pixels from the top left.
 pub enum PlatformEvent {
     Update { dt: f32 },
     Render,
     Close,
-    Resized { width: i32, height: i32 }
+    Resized { width: i32, height: i32 },
+    KeyDown { key: Key },
+    KeyUp { key: Key },
+    MouseMove { x: i32, y: i32 },
+    MouseDown { x: i32, y: i32, button: MouseButton },
+    MouseUp { x: i32, y: i32, button: MouseButton },
 }
 
 pub trait Runner {
@@ -22,6 +78,17 @@ pub fn get_performance_frequency() -> u64 {
     unsafe { bridge::get_performance_frequency() }
 }
 
+pub fn get_data_path() -> Option<PathBuf> {
+    unsafe {
+        let path = bridge::get_data_path();
+        if path.is_null() {
+            None
+        } else {
+            Some(PathBuf::from(CStr::from_ptr(path).to_string_lossy().into_owned()))
+        }
+    }
+}
+
 pub fn quit() {
     unsafe { bridge::quit() }
 }
@@ -37,10 +104,73 @@ pub fn to_platform_event(event: &bridge::PlatformEvent) -> Option<PlatformEvent>
             width: unsafe { event.data.resize.width },
             height: unsafe { event.data.resize.height },
         }),
+        bridge::PLATFORM_EVENT_KEY_DOWN => to_key(unsafe { event.data.key.key }).map(|key| PlatformEvent::KeyDown { key }),
+        bridge::PLATFORM_EVENT_KEY_UP => to_key(unsafe { event.data.key.key }).map(|key| PlatformEvent::KeyUp { key }),
+        bridge::PLATFORM_EVENT_MOUSE_MOVE => {
+            let mouse = unsafe { event.data.mouse };
+            Some(PlatformEvent::MouseMove { x: mouse.x, y: mouse.y })
+        }
+        bridge::PLATFORM_EVENT_MOUSE_DOWN => {
+            let mouse = unsafe { event.data.mouse };
+            to_mouse_button(mouse.button).map(|button| PlatformEvent::MouseDown { x: mouse.x, y: mouse.y, button })
+        }
+        bridge::PLATFORM_EVENT_MOUSE_UP => {
+            let mouse = unsafe { event.data.mouse };
+            to_mouse_button(mouse.button).map(|button| PlatformEvent::MouseUp { x: mouse.x, y: mouse.y, button })
+        }
         _ => None
     }
 }
 
+fn to_mouse_button(button: std::os::raw::c_int) -> Option<MouseButton> {
+    match button {
+        bridge::PLATFORM_MOUSE_BUTTON_LEFT => Some(MouseButton::Left),
+        bridge::PLATFORM_MOUSE_BUTTON_RIGHT => Some(MouseButton::Right),
+        bridge::PLATFORM_MOUSE_BUTTON_MIDDLE => Some(MouseButton::Middle),
+        _ => None,
+    }
+}
+
+fn to_key(key: std::os::raw::c_int) -> Option<Key> {
+    match key {
+        bridge::PLATFORM_KEY_UP => Some(Key::Up),
+        bridge::PLATFORM_KEY_DOWN => Some(Key::Down),
+        bridge::PLATFORM_KEY_LEFT => Some(Key::Left),
+        bridge::PLATFORM_KEY_RIGHT => Some(Key::Right),
+        bridge::PLATFORM_KEY_ENTER => Some(Key::Enter),
+        bridge::PLATFORM_KEY_SPACE => Some(Key::Space),
+        bridge::PLATFORM_KEY_ESCAPE => Some(Key::Escape),
+        bridge::PLATFORM_KEY_BACKSPACE => Some(Key::Backspace),
+        bridge::PLATFORM_KEY_F1 => Some(Key::F1),
+        bridge::PLATFORM_KEY_F2 => Some(Key::F2),
+        bridge::PLATFORM_KEY_F3 => Some(Key::F3),
+        bridge::PLATFORM_KEY_F4 => Some(Key::F4),
+        bridge::PLATFORM_KEY_F5 => Some(Key::F5),
+        bridge::PLATFORM_KEY_F6 => Some(Key::F6),
+        bridge::PLATFORM_KEY_F7 => Some(Key::F7),
+        bridge::PLATFORM_KEY_F8 => Some(Key::F8),
+        bridge::PLATFORM_KEY_F9 => Some(Key::F9),
+        bridge::PLATFORM_KEY_F10 => Some(Key::F10),
+        bridge::PLATFORM_KEY_F11 => Some(Key::F11),
+        bridge::PLATFORM_KEY_F12 => Some(Key::F12),
+        bridge::PLATFORM_KEY_NUM1 => Some(Key::Num1),
+        bridge::PLATFORM_KEY_NUM2 => Some(Key::Num2),
+        bridge::PLATFORM_KEY_W => Some(Key::W),
+        bridge::PLATFORM_KEY_A => Some(Key::A),
+        bridge::PLATFORM_KEY_S => Some(Key::S),
+        bridge::PLATFORM_KEY_D => Some(Key::D),
+        bridge::PLATFORM_KEY_I => Some(Key::I),
+        bridge::PLATFORM_KEY_J => Some(Key::J),
+        bridge::PLATFORM_KEY_K => Some(Key::K),
+        bridge::PLATFORM_KEY_L => Some(Key::L),
+        bridge::PLATFORM_KEY_T => Some(Key::T),
+        bridge::PLATFORM_KEY_F => Some(Key::F),
+        bridge::PLATFORM_KEY_G => Some(Key::G),
+        bridge::PLATFORM_KEY_H => Some(Key::H),
+        _ => None,
+    }
+}
+
 #[macro_export]
 macro_rules! entry {
     ($t:ty) => {
@@ -55,7 +185,7 @@ macro_rules! entry {
 
         #[no_mangle]
         pub unsafe extern "C" fn game_quit() {
-            Box::from_raw(RUNNER);
+            drop(Box::from_raw(RUNNER));
             RUNNER = 0 as *mut $t;
         }
 
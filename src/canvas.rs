@@ -0,0 +1,386 @@
+use failure::Error;
+
+use actor::{Actor, ActorKind, Motion};
+use ghost::{GhostKind, GhostState};
+use level::Fruit;
+use maze::{Direction, Maze, Tile, TilePos, TILE_SIZE};
+use multiplayer::Party;
+use world::World;
+use tilemap::Tilemap;
+use walls;
+use {Rect, Renderer, Rgb, Texture, TilemapLayer};
+
+// The playfield is 28x36 tiles. The maze starts below the three HUD rows.
+pub const VIEW_WIDTH: i32 = 224;
+pub const VIEW_HEIGHT: i32 = 288;
+pub const MAZE_TOP: i32 = 3 * TILE_SIZE;
+
+const DOOR: Rect = Rect::new(111, 109, 8, 2);
+pub const PELLET: Rect = Rect::new(294, 81, 2, 2);
+// The whole tile around `PELLET`, for drawing it as part of a tilemap.
+const PELLET_TILE: Rect = Rect::new(291, 78, 8, 8);
+pub const ENERGIZER: Rect = Rect::new(300, 79, 8, 8);
+const ACTOR_SIZE: i32 = 16;
+const GLYPH_SIZE: i32 = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    White,
+    Red,
+    Yellow,
+}
+
+// Glyphs in the sprite sheet, by colour. Not every colour has every character.
+const WHITE_GLYPHS: &[(char, i32, i32)] = &[
+    ('1', 234, 219), ('U', 241, 219), ('P', 249, 219), ('H', 259, 219), ('I', 268, 219),
+    ('G', 275, 219), ('S', 295, 219), ('C', 303, 219), ('O', 311, 219), ('R', 319, 219),
+    ('E', 328, 219), ('0', 233, 231), ('1', 242, 231), ('2', 250, 231), ('3', 259, 231),
+    ('4', 267, 231), ('5', 275, 231), ('6', 283, 231), ('7', 290, 231), ('8', 298, 231),
+    ('9', 306, 231), ('C', 319, 231), ('R', 327, 231), ('E', 336, 231), ('D', 343, 231),
+    ('I', 352, 231), ('T', 360, 231),
+];
+const RED_GLYPHS: &[(char, i32, i32)] = &[
+    ('G', 233, 246), ('A', 241, 246), ('M', 249, 246), ('E', 258, 246), ('O', 281, 246),
+    ('V', 289, 246), ('R', 305, 246),
+];
+const YELLOW_GLYPHS: &[(char, i32, i32)] = &[
+    ('R', 320, 246), ('E', 329, 246), ('A', 336, 246), ('D', 344, 246), ('Y', 353, 246),
+    ('!', 361, 246),
+];
+
+fn glyph(c: char, color: Color) -> Option<Rect> {
+    let preferred = match color {
+        Color::White => WHITE_GLYPHS,
+        Color::Red => RED_GLYPHS,
+        Color::Yellow => YELLOW_GLYPHS,
+    };
+    // Fall back to another colour rather than dropping the character.
+    [preferred, WHITE_GLYPHS, RED_GLYPHS, YELLOW_GLYPHS]
+        .iter()
+        .flat_map(|glyphs| glyphs.iter())
+        .find(|&&(glyph, _, _)| glyph == c)
+        .map(|&(_, x, y)| Rect::new(x, y, GLYPH_SIZE, GLYPH_SIZE))
+}
+
+fn actor(x: i32, y: i32) -> Rect {
+    Rect::new(x, y, ACTOR_SIZE, ACTOR_SIZE)
+}
+
+pub fn pacman_sprite(dir: Direction, frame: u32) -> Rect {
+    // Closed, half open, wide open, half open.
+    let (half, wide) = match dir {
+        Direction::Left => (actor(252, 162), actor(237, 162)),
+        Direction::Right => (actor(284, 162), actor(301, 162)),
+        Direction::Up => (actor(268, 146), actor(268, 131)),
+        Direction::Down => (actor(268, 178), actor(268, 194)),
+    };
+    match frame % 4 {
+        0 => actor(268, 162),
+        2 => wide,
+        _ => half,
+    }
+}
+
+pub fn ghost_sprite(kind: GhostKind, dir: Direction) -> Rect {
+    let y = match kind {
+        GhostKind::Inky => 9,
+        GhostKind::Clyde => 25,
+        GhostKind::Pinky => 41,
+        GhostKind::Blinky => 58,
+    };
+    let x = match dir {
+        Direction::Left => 238,
+        Direction::Up => 255,
+        Direction::Down => 272,
+        Direction::Right => 289,
+    };
+    actor(x, y)
+}
+
+pub fn frightened_sprite(flashing: bool) -> Rect {
+    if flashing {
+        actor(255, 74)
+    } else {
+        actor(272, 74)
+    }
+}
+
+// The sheet has no Galaxian, bell or key, those levels borrow the melon.
+pub fn fruit_sprite(fruit: Fruit) -> Rect {
+    match fruit {
+        Fruit::Cherries => actor(275, 110),
+        Fruit::Strawberry => actor(259, 110),
+        Fruit::Peach => actor(291, 92),
+        Fruit::Apple => actor(259, 92),
+        Fruit::Melon | Fruit::Galaxian | Fruit::Bell | Fruit::Key => actor(243, 92),
+    }
+}
+
+// The maze kept on the GPU between frames, the walls from the wall atlas and
+// the pellets from the sheet. Only tiles that change from one frame to the
+// next are sent again, which is usually a pellet or two.
+pub struct MazeLayers {
+    walls: TilemapLayer,
+    pellets: TilemapLayer,
+    // The maze the walls were last worked out for.
+    built: Option<Maze>,
+}
+
+impl MazeLayers {
+    pub fn new(width: i32, height: i32) -> MazeLayers {
+        MazeLayers {
+            walls: TilemapLayer::new(Tilemap::new(width, height, TILE_SIZE)),
+            pellets: TilemapLayer::new(Tilemap::new(width, height, TILE_SIZE)),
+            built: None,
+        }
+    }
+
+    pub fn update(&mut self, maze: &Maze, show_energizers: bool) {
+        if (self.walls.map().width(), self.walls.map().height()) != (maze.width(), maze.height()) {
+            *self = MazeLayers::new(maze.width(), maze.height());
+        }
+
+        if !self.built.as_ref().is_some_and(|built| built.same_walls(maze)) {
+            for (pos, _) in maze.tiles() {
+                self.walls.set(pos.x, pos.y, None);
+            }
+            for (pos, src) in walls::tiles(maze) {
+                self.walls.set(pos.x, pos.y, Some(src));
+            }
+            self.built = Some(maze.clone());
+        }
+
+        for (pos, tile) in maze.tiles() {
+            let src = match tile {
+                Tile::Pellet => Some(PELLET_TILE),
+                Tile::Energizer if show_energizers => Some(ENERGIZER),
+                _ => None,
+            };
+            self.pellets.set(pos.x, pos.y, src);
+        }
+    }
+}
+
+// Draws sprites from the sheet in playfield pixels.
+pub struct Canvas<'a> {
+    renderer: &'a mut Renderer,
+    texture: &'a Texture,
+    // The atlas from `walls::atlas`.
+    walls: &'a Texture,
+    // Without these the maze is drawn a sprite at a time.
+    layers: Option<&'a mut MazeLayers>,
+    // The best score on the high score table. Not part of the game, the HUD
+    // shows it until a player beats it.
+    high_score: u32,
+}
+
+impl<'a> Canvas<'a> {
+    pub fn new(renderer: &'a mut Renderer, texture: &'a Texture, walls: &'a Texture) -> Canvas<'a> {
+        Canvas {
+            renderer,
+            texture,
+            walls,
+            layers: None,
+            high_score: 0,
+        }
+    }
+
+    pub fn with_layers(self, layers: &'a mut MazeLayers) -> Canvas<'a> {
+        Canvas {
+            layers: Some(layers),
+            ..self
+        }
+    }
+
+    pub fn with_high_score(self, high_score: u32) -> Canvas<'a> {
+        Canvas { high_score, ..self }
+    }
+
+    pub fn sprite(&mut self, src: Rect, x: i32, y: i32) -> Result<(), Error> {
+        self.renderer
+            .render_sprite(self.texture, src, Rect::new(x, y, src.w, src.h))
+    }
+
+    pub fn sprite_scaled(&mut self, src: Rect, dst: Rect) -> Result<(), Error> {
+        self.renderer.render_sprite(self.texture, src, dst)
+    }
+
+    // Draws a sprite centered on a maze pixel position. Actors in a tunnel
+    // are cut off at the sides of the maze rather than drawn past them.
+    pub fn actor(&mut self, src: Rect, x: i32, y: i32) -> Result<(), Error> {
+        let left = x - src.w / 2;
+        let clip_left = (-left).max(0);
+        let clip_right = (left + src.w - VIEW_WIDTH).max(0);
+        if clip_left + clip_right >= src.w {
+            return Ok(());
+        }
+        let clipped = Rect::new(src.x + clip_left, src.y, src.w - clip_left - clip_right, src.h);
+        self.sprite(clipped, left + clip_left, y - src.h / 2 + MAZE_TOP)
+    }
+
+    // Text is laid out on the 8 pixel tile grid. Characters missing from the
+    // sprite sheet are left blank.
+    pub fn text(&mut self, text: &str, col: i32, row: i32, color: Color) -> Result<(), Error> {
+        for (i, c) in text.chars().enumerate() {
+            if let Some(src) = glyph(c, color) {
+                self.sprite(src, (col + i as i32) * TILE_SIZE, row * TILE_SIZE)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Right aligns a number so its last digit is at `col`.
+    pub fn number(&mut self, value: u32, col: i32, row: i32) -> Result<(), Error> {
+        let text = if value == 0 { "00".to_string() } else { value.to_string() };
+        self.text(&text, col + 1 - text.len() as i32, row, Color::White)
+    }
+
+    pub fn maze(&mut self, maze: &Maze, wall_color: Rgb, show_energizers: bool) -> Result<(), Error> {
+        let layers = match self.layers {
+            Some(ref mut layers) => layers,
+            None => {
+                self.walls(maze, wall_color)?;
+                return self.pellets(maze, show_energizers);
+            }
+        };
+        layers.update(maze, show_energizers);
+        self.renderer.render_tilemap(&mut layers.walls, self.walls, 0, MAZE_TOP, wall_color)?;
+        self.renderer.render_tilemap(&mut layers.pellets, self.texture, 0, MAZE_TOP, Rgb::WHITE)?;
+        self.doors(maze)
+    }
+
+    // The walls are worked out from the tiles, so any maze can be drawn.
+    // The door keeps its colour.
+    pub fn walls(&mut self, maze: &Maze, color: Rgb) -> Result<(), Error> {
+        for (pos, src) in walls::tiles(maze) {
+            let dst = Rect::new(pos.x * TILE_SIZE, pos.y * TILE_SIZE + MAZE_TOP, TILE_SIZE, TILE_SIZE);
+            self.renderer.render_sprite_tinted(self.walls, src, dst, color)?;
+        }
+        self.doors(maze)
+    }
+
+    fn doors(&mut self, maze: &Maze) -> Result<(), Error> {
+        for (pos, tile) in maze.tiles() {
+            if tile == Tile::Door {
+                self.sprite(DOOR, pos.x * TILE_SIZE, pos.y * TILE_SIZE + MAZE_TOP + 5)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Just the pellets and energizers, without the walls.
+    pub fn pellets(&mut self, maze: &Maze, show_energizers: bool) -> Result<(), Error> {
+        for (pos, tile) in maze.tiles() {
+            let x = pos.x * TILE_SIZE;
+            let y = pos.y * TILE_SIZE + MAZE_TOP;
+            match tile {
+                Tile::Pellet => self.sprite(PELLET, x + 3, y + 3)?,
+                Tile::Energizer if show_energizers => self.sprite(ENERGIZER, x, y)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    // Marks each tile of a path with a large dot, for debugging.
+    pub fn path(&mut self, path: &[TilePos]) -> Result<(), Error> {
+        for pos in path {
+            let x = pos.x * TILE_SIZE + 2;
+            let y = pos.y * TILE_SIZE + 2 + MAZE_TOP;
+            self.sprite_scaled(PELLET, Rect::new(x, y, 4, 4))?;
+        }
+        Ok(())
+    }
+
+    // Maze, pellets and actors as they are during play.
+    pub fn world(&mut self, world: &World, ticks: u32) -> Result<(), Error> {
+        self.maze(world.maze(), world.level_spec().wall_color, (ticks / 10).is_multiple_of(2))?;
+
+        let flashing = world.is_fright_flashing();
+        for (actor, _) in world.ghosts() {
+            self.entity(actor, flashing)?;
+        }
+        for (_, actor) in world.actors().iter().filter(|&(_, actor)| !actor.kind.is_character()) {
+            self.entity(actor, flashing)?;
+        }
+
+        // Pac-Man hides while the points for an eaten ghost are shown.
+        if !world.is_frozen() {
+            for (actor, _) in world.pacmen() {
+                self.entity(actor, flashing)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Frightened ghosts are drawn white while `flashing`.
+    pub fn entity(&mut self, actor: &Actor, flashing: bool) -> Result<(), Error> {
+        let Motion { x, y, dir, .. } = actor.motion;
+        match actor.kind {
+            ActorKind::Points(points) => {
+                let col = x / TILE_SIZE - 1;
+                let row = (y + MAZE_TOP) / TILE_SIZE;
+                self.text(&points.to_string(), col, row, Color::White)
+            }
+            ActorKind::Fruit(fruit) => self.actor(fruit_sprite(fruit), x, y),
+            ActorKind::PacMan(ref pacman) => {
+                let frame = if pacman.moving { pacman.distance / 2 } else { 1 };
+                self.actor(pacman_sprite(dir, frame), x, y)
+            }
+            ActorKind::Ghost(ref ghost) => match ghost.state {
+                // The sheet has no eyes, returning ghosts are invisible.
+                GhostState::Eaten | GhostState::EnteringHouse => Ok(()),
+                _ if ghost.frightened => self.actor(frightened_sprite(flashing), x, y),
+                _ => self.actor(ghost_sprite(ghost.kind, dir), x, y),
+            },
+        }
+    }
+
+    pub fn hud(&mut self, world: &World, blink: bool) -> Result<(), Error> {
+        match world.party() {
+            Some(party) => self.party_scores(party)?,
+            None => self.scores(world, blink)?,
+        }
+
+        // The life being played isn't shown.
+        let spare = world.score().lives().saturating_sub(1).min(5) as i32;
+        for i in 0..spare {
+            self.sprite(pacman_sprite(Direction::Left, 1), (2 + 2 * i) * TILE_SIZE, VIEW_HEIGHT - 2 * TILE_SIZE)?;
+        }
+
+        // The fruit of the last seven levels, the current one rightmost.
+        let level = world.level();
+        for (i, level) in (level.saturating_sub(6).max(1)..=level).rev().enumerate() {
+            let fruit = world.levels().get(level).fruit;
+            self.sprite(fruit_sprite(fruit), (24 - 2 * i as i32) * TILE_SIZE, VIEW_HEIGHT - 2 * TILE_SIZE)?;
+        }
+        Ok(())
+    }
+
+    fn scores(&mut self, world: &World, blink: bool) -> Result<(), Error> {
+        // Only the label of the player who is up blinks.
+        let mut high_score = self.high_score;
+        for player in 0..world.players() {
+            let (label, col) = if player == 0 { ("1UP", 3) } else { ("2UP", 22) };
+            if blink || player != world.player() {
+                self.text(label, col, 0, Color::White)?;
+            }
+            if let Some(score) = world.player_score(player) {
+                self.number(score.score(), col + 3, 1)?;
+                high_score = high_score.max(score.score());
+            }
+        }
+        self.text("HIGH SCORE", 9, 0, Color::White)?;
+        self.number(high_score, 16, 1)
+    }
+
+    // Everyone's own points side by side, there's no room for the high score.
+    fn party_scores(&mut self, party: &Party) -> Result<(), Error> {
+        for player in 0..party.players() {
+            let col = 1 + 7 * player as i32;
+            self.text(&format!("{}UP", player + 1), col, 0, Color::White)?;
+            self.number(party.points(player), col + 5, 1)?;
+        }
+        Ok(())
+    }
+}
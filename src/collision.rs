@@ -0,0 +1,105 @@
+use actor::Handle;
+use maze::TilePos;
+use Rect;
+
+// Pac-Man and the ghosts are 16 pixels wide but only their middle counts
+// when hitboxes are compared.
+pub const HITBOX_SIZE: i32 = 6;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CollisionMode {
+    // Touching means standing in the same tile, like the arcade. When Pac-Man
+    // and a ghost swap tiles in the same tick they pass right through each
+    // other.
+    #[default]
+    Tile,
+    // Touching means overlapping hitboxes, which closes the gap.
+    Hitbox,
+}
+
+// What Pac-Man ran into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Collider {
+    Ghost(usize),
+    Actor(Handle),
+}
+
+// Something that can be touched: a position in maze pixels and the size of
+// its hitbox, centered on the position. Bodies with an empty hitbox never
+// touch anything.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Body {
+    pub x: i32,
+    pub y: i32,
+    pub size: (i32, i32),
+}
+
+impl Body {
+    pub fn new(x: i32, y: i32, size: (i32, i32)) -> Body {
+        Body { x, y, size }
+    }
+
+    pub fn tile(&self) -> TilePos {
+        TilePos::from_pixel(self.x, self.y)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size.0 <= 0 || self.size.1 <= 0
+    }
+
+    pub fn bounds(&self) -> Rect {
+        let (w, h) = self.size;
+        Rect::new(self.x - w / 2, self.y - h / 2, w, h)
+    }
+}
+
+pub fn overlaps(a: Rect, b: Rect) -> bool {
+    a.x < b.x + b.w && b.x < a.x + a.w && a.y < b.y + b.h && b.y < a.y + a.h
+}
+
+pub fn touches(mode: CollisionMode, a: &Body, b: &Body) -> bool {
+    if a.is_empty() || b.is_empty() {
+        return false;
+    }
+    match mode {
+        CollisionMode::Tile => a.tile() == b.tile(),
+        CollisionMode::Hitbox => overlaps(a.bounds(), b.bounds()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body(x: i32, y: i32) -> Body {
+        Body::new(x, y, (HITBOX_SIZE, HITBOX_SIZE))
+    }
+
+    #[test]
+    fn tiles_let_neighbours_swap_through() {
+        // Pac-Man at the right edge of tile 1 and a ghost at the left edge of
+        // tile 2, then the other way round a tick later.
+        let before = (body(15, 12), body(16, 12));
+        let after = (body(17, 12), body(14, 12));
+
+        for (a, b) in [before, after].iter() {
+            assert!(!touches(CollisionMode::Tile, a, b));
+            assert!(touches(CollisionMode::Hitbox, a, b));
+        }
+    }
+
+    #[test]
+    fn same_tile_always_touches_in_tile_mode() {
+        assert!(touches(CollisionMode::Tile, &body(8, 8), &body(15, 15)));
+        assert!(!touches(CollisionMode::Hitbox, &body(8, 8), &body(15, 15)));
+    }
+
+    #[test]
+    fn empty_bodies_never_touch() {
+        let empty = Body::new(12, 12, (0, 0));
+        assert!(!touches(CollisionMode::Tile, &empty, &body(12, 12)));
+        assert!(!touches(CollisionMode::Hitbox, &empty, &body(12, 12)));
+        assert!(overlaps(Rect::new(0, 0, 2, 2), Rect::new(1, 1, 2, 2)));
+        assert!(!overlaps(Rect::new(0, 0, 2, 2), Rect::new(2, 0, 2, 2)));
+    }
+}
@@ -0,0 +1,345 @@
+use std::path::Path;
+
+use failure::{err_msg, Error};
+
+use canvas::{self, Canvas, Color, ENERGIZER, MAZE_TOP, PELLET};
+use ghost::{Ghost, GhostKind};
+use level;
+use maze::{Direction, Maze, Problem, Tile, TilePos, Zone, TILE_SIZE};
+use storage;
+use world::Player;
+use Rect;
+
+// Single pixels of the sprite sheet, stretched to draw with.
+const BLUE: Rect = Rect::new(20, 8, 1, 1);
+const PINK: Rect = Rect::new(115, 109, 1, 1);
+const RED: Rect = Rect::new(235, 246, 1, 1);
+
+// The tool palette across the top of the screen, where the scores go
+// during play.
+const PALETTE_LEFT: i32 = 4;
+const PALETTE_SPACING: i32 = 3 * TILE_SIZE;
+const PALETTE_SLOT: i32 = 2 * TILE_SIZE;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tool {
+    Wall,
+    Floor,
+    Pellet,
+    Energizer,
+    Tunnel,
+    NoUpTurn,
+    House,
+    Start,
+}
+
+impl Tool {
+    pub const ALL: [Tool; 8] = [
+        Tool::Wall,
+        Tool::Floor,
+        Tool::Pellet,
+        Tool::Energizer,
+        Tool::Tunnel,
+        Tool::NoUpTurn,
+        Tool::House,
+        Tool::Start,
+    ];
+
+    // What dragging the tool across tiles turns them into. The house and
+    // the start are placed once per click instead.
+    fn brush(self) -> Option<(Tile, Zone)> {
+        match self {
+            Tool::Wall => Some((Tile::Wall, Zone::Normal)),
+            Tool::Floor => Some((Tile::Empty, Zone::Normal)),
+            Tool::Pellet => Some((Tile::Pellet, Zone::Normal)),
+            Tool::Energizer => Some((Tile::Energizer, Zone::Normal)),
+            Tool::Tunnel => Some((Tile::Empty, Zone::Tunnel)),
+            Tool::NoUpTurn => Some((Tile::Empty, Zone::NoUpTurn)),
+            Tool::House | Tool::Start => None,
+        }
+    }
+
+    fn index(self) -> usize {
+        Tool::ALL.iter().position(|&tool| tool == self).unwrap()
+    }
+}
+
+// Edits a maze with the mouse. The left button uses the selected tool, the
+// right one puts walls back. Positions are in view pixels.
+pub struct Editor {
+    maze: Maze,
+    tool: Tool,
+    cursor: Option<TilePos>,
+    // What the held button paints, None while no button is held.
+    brush: Option<(Tile, Zone)>,
+    problems: Vec<Problem>,
+}
+
+impl Editor {
+    pub fn new(maze: Maze) -> Editor {
+        let problems = maze.check();
+        Editor {
+            maze,
+            tool: Tool::Wall,
+            cursor: None,
+            brush: None,
+            problems,
+        }
+    }
+
+    pub fn maze(&self) -> &Maze {
+        &self.maze
+    }
+
+    // Everything keeping the maze from being played, checked after every change.
+    pub fn problems(&self) -> &[Problem] {
+        &self.problems
+    }
+
+    pub fn tool(&self) -> Tool {
+        self.tool
+    }
+
+    pub fn set_tool(&mut self, tool: Tool) {
+        self.tool = tool;
+    }
+
+    // Moves through the palette, wrapping around at either end.
+    pub fn cycle_tool(&mut self, step: i32) {
+        let len = Tool::ALL.len() as i32;
+        self.tool = Tool::ALL[(self.tool.index() as i32 + step).rem_euclid(len) as usize];
+    }
+
+    pub fn mouse_move(&mut self, x: i32, y: i32) {
+        self.cursor = self.tile_at(x, y);
+        if let (Some((tile, zone)), Some(pos)) = (self.brush, self.cursor) {
+            self.paint(pos, tile, zone);
+        }
+    }
+
+    pub fn mouse_down(&mut self, x: i32, y: i32, erase: bool) {
+        if y < MAZE_TOP {
+            if let Some(tool) = palette_tool(x, y) {
+                self.tool = tool;
+            }
+            return;
+        }
+
+        let pos = match self.tile_at(x, y) {
+            Some(pos) => pos,
+            None => return,
+        };
+        let brush = if erase { Some((Tile::Wall, Zone::Normal)) } else { self.tool.brush() };
+        match (brush, self.tool) {
+            (Some((tile, zone)), _) => {
+                self.brush = brush;
+                self.paint(pos, tile, zone);
+            }
+            (None, Tool::House) => {
+                self.maze.place_house(pos);
+                self.problems = self.maze.check();
+            }
+            (None, _) => {
+                self.maze.set_start(pos);
+                self.problems = self.maze.check();
+            }
+        }
+    }
+
+    pub fn mouse_up(&mut self) {
+        self.brush = None;
+    }
+
+    fn tile_at(&self, x: i32, y: i32) -> Option<TilePos> {
+        if x < 0 || y < MAZE_TOP {
+            return None;
+        }
+        Some(TilePos::from_pixel(x, y - MAZE_TOP)).filter(|&pos| self.maze.contains(pos))
+    }
+
+    fn paint(&mut self, pos: TilePos, tile: Tile, zone: Zone) {
+        if self.maze.get(pos) != tile || self.maze.zone(pos) != zone {
+            self.maze.set(pos, tile, zone);
+            self.problems = self.maze.check();
+        }
+    }
+
+    // Writes the maze in the format `Maze::parse` reads, but only once it
+    // can be played.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        if let Some(problem) = self.problems.first() {
+            return Err(err_msg(format!("{} problems, starting with: {}", self.problems.len(), problem)));
+        }
+        storage::write(path, self.maze.to_string().as_bytes())
+    }
+
+    pub fn render(&self, canvas: &mut Canvas) -> Result<(), Error> {
+        canvas.walls(&self.maze, level::DEFAULT_WALL_COLOR)?;
+        for (pos, tile) in self.maze.tiles() {
+            let x = pos.x * TILE_SIZE;
+            let y = pos.y * TILE_SIZE + MAZE_TOP;
+            match (tile, self.maze.zone(pos)) {
+                (Tile::Wall, _) | (Tile::Door, _) => {}
+                // Tunnels are drawn as a pipe, no up turns with a bar above.
+                (_, Zone::Tunnel) => {
+                    canvas.sprite_scaled(BLUE, Rect::new(x, y, TILE_SIZE, 1))?;
+                    canvas.sprite_scaled(BLUE, Rect::new(x, y + TILE_SIZE - 1, TILE_SIZE, 1))?;
+                }
+                (_, Zone::NoUpTurn) => canvas.sprite_scaled(PINK, Rect::new(x, y, TILE_SIZE, 1))?,
+                (_, Zone::Normal) => {}
+            }
+        }
+        canvas.pellets(&self.maze, true)?;
+
+        if self.maze.has_house() {
+            for &kind in GhostKind::ALL.iter() {
+                canvas.entity(&Ghost::actor(kind, self.maze.house()), false)?;
+            }
+        }
+        canvas.entity(&Player::actor(0, self.maze.start_position()), false)?;
+
+        for problem in &self.problems {
+            let pos = match *problem {
+                Problem::Unreachable(pos) => pos,
+                Problem::BlockedStart => self.maze.start(),
+                Problem::OneSidedTunnel(row) => {
+                    let left = TilePos::new(0, row);
+                    if self.maze.is_walkable(left) { left } else { TilePos::new(self.maze.width() - 1, row) }
+                }
+                _ => continue,
+            };
+            let x = pos.x * TILE_SIZE + 2;
+            let y = pos.y * TILE_SIZE + 2 + MAZE_TOP;
+            canvas.sprite_scaled(RED, Rect::new(x, y, 4, 4))?;
+        }
+
+        if let Some(pos) = self.cursor {
+            outline(canvas, PINK, Rect::new(pos.x * TILE_SIZE, pos.y * TILE_SIZE + MAZE_TOP, TILE_SIZE, TILE_SIZE))?;
+        }
+
+        self.palette(canvas)?;
+        if self.problems.is_empty() {
+            canvas.text("READY!", 11, canvas::VIEW_HEIGHT / TILE_SIZE - 2, Color::Yellow)?;
+        }
+        Ok(())
+    }
+
+    fn palette(&self, canvas: &mut Canvas) -> Result<(), Error> {
+        for (i, &tool) in Tool::ALL.iter().enumerate() {
+            let slot = palette_slot(i);
+            let (x, y) = (slot.x + 4, slot.y + 4);
+            let tile = Rect::new(x, y, TILE_SIZE, TILE_SIZE);
+            match tool {
+                Tool::Wall => canvas.sprite_scaled(BLUE, tile)?,
+                Tool::Floor => outline(canvas, BLUE, tile)?,
+                Tool::Pellet => canvas.sprite(PELLET, x + 3, y + 3)?,
+                Tool::Energizer => canvas.sprite(ENERGIZER, x, y)?,
+                Tool::Tunnel => {
+                    canvas.sprite_scaled(BLUE, Rect::new(x, y, TILE_SIZE, 1))?;
+                    canvas.sprite_scaled(BLUE, Rect::new(x, y + TILE_SIZE - 1, TILE_SIZE, 1))?;
+                }
+                Tool::NoUpTurn => canvas.sprite_scaled(PINK, Rect::new(x, y, TILE_SIZE, 1))?,
+                Tool::House => canvas.sprite(canvas::ghost_sprite(GhostKind::Blinky, Direction::Left), slot.x, slot.y)?,
+                Tool::Start => canvas.sprite(canvas::pacman_sprite(Direction::Left, 1), slot.x, slot.y)?,
+            }
+            if tool == self.tool {
+                canvas.sprite_scaled(PINK, Rect::new(slot.x, slot.y + slot.h + 1, slot.w, 2))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn palette_slot(index: usize) -> Rect {
+    Rect::new(PALETTE_LEFT + index as i32 * PALETTE_SPACING, 2, PALETTE_SLOT, PALETTE_SLOT)
+}
+
+fn palette_tool(x: i32, y: i32) -> Option<Tool> {
+    Tool::ALL.iter().enumerate().find_map(|(i, &tool)| {
+        let slot = palette_slot(i);
+        let inside = (slot.x..slot.x + slot.w).contains(&x) && (slot.y..slot.y + slot.h).contains(&y);
+        Some(tool).filter(|_| inside)
+    })
+}
+
+fn outline(canvas: &mut Canvas, color: Rect, rect: Rect) -> Result<(), Error> {
+    canvas.sprite_scaled(color, Rect::new(rect.x, rect.y, rect.w, 1))?;
+    canvas.sprite_scaled(color, Rect::new(rect.x, rect.y + rect.h - 1, rect.w, 1))?;
+    canvas.sprite_scaled(color, Rect::new(rect.x, rect.y, 1, rect.h))?;
+    canvas.sprite_scaled(color, Rect::new(rect.x + rect.w - 1, rect.y, 1, rect.h))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The view pixel in the middle of a maze tile.
+    fn at(x: i32, y: i32) -> (i32, i32) {
+        (x * TILE_SIZE + 4, y * TILE_SIZE + 4 + MAZE_TOP)
+    }
+
+    #[test]
+    fn dragging_paints_every_tile_passed() {
+        let mut editor = Editor::new(Maze::arcade());
+        editor.set_tool(Tool::Floor);
+        let (x, y) = at(0, 0);
+        editor.mouse_down(x, y, false);
+        for tile in 1..4 {
+            let (x, y) = at(tile, 0);
+            editor.mouse_move(x, y);
+        }
+        editor.mouse_up();
+        let (x, y) = at(5, 0);
+        editor.mouse_move(x, y);
+
+        for x in 0..4 {
+            assert_eq!(editor.maze().get(TilePos::new(x, 0)), Tile::Empty);
+        }
+        assert_eq!(editor.maze().get(TilePos::new(5, 0)), Tile::Wall);
+        assert!(!editor.problems().is_empty());
+    }
+
+    #[test]
+    fn palette_and_placement_tools() {
+        let mut editor = Editor::new(Maze::arcade());
+        let slot = palette_slot(Tool::Start.index());
+        editor.mouse_down(slot.x + 1, slot.y + 1, false);
+        assert_eq!(editor.tool(), Tool::Start);
+
+        let (x, y) = at(6, 5);
+        editor.mouse_down(x, y, false);
+        assert_eq!(editor.maze().start(), TilePos::new(6, 5));
+        assert!(editor.problems().is_empty());
+
+        editor.cycle_tool(-1);
+        assert_eq!(editor.tool(), Tool::House);
+        editor.cycle_tool(2);
+        assert_eq!(editor.tool(), Tool::Wall);
+    }
+
+    #[test]
+    fn only_playable_mazes_are_saved() {
+        let path = ::std::env::temp_dir().join(format!("pacman-editor-test-{}.txt", ::std::process::id()));
+        let mut editor = Editor::new(Maze::arcade());
+        editor.save(&path).unwrap();
+        let saved = String::from_utf8(storage::read(&path).unwrap()).unwrap();
+        assert_eq!(Maze::parse(&saved).unwrap(), Maze::arcade());
+
+        // Walling in Pac-Man cuts off the rest of the maze.
+        let start = editor.maze().start();
+        let pair = [start, start.step(Direction::Right)];
+        for tile in pair.iter() {
+            for &dir in Direction::ALL.iter() {
+                let next = tile.step(dir);
+                if !pair.contains(&next) {
+                    let (x, y) = at(next.x, next.y);
+                    editor.mouse_down(x, y, true);
+                    editor.mouse_up();
+                }
+            }
+        }
+        assert!(editor.problems().iter().any(|p| matches!(p, Problem::Unreachable(_))));
+        assert!(editor.save(&path).is_err());
+        ::std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,21 @@
+use collision::Collider;
+use level::Fruit;
+
+// Things that happened during an update, for rendering and audio to react to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    PelletEaten { points: u32 },
+    EnergizerEaten { points: u32 },
+    GhostEaten { points: u32 },
+    FruitEaten { fruit: Fruit, points: u32 },
+    ExtraLife { lives: u32 },
+    LifeLost { lives: u32 },
+    GameOver,
+    // Initials entered at game over for a score that made the high score
+    // table, for whoever keeps the table to put them on it.
+    HighScore { score: u32, initials: [u8; 3] },
+    // A Pac-Man touched something, reported before the game reacts to it.
+    Collision { pacman: usize, with: Collider },
+    PacManCaught,
+    LevelCleared,
+}
@@ -0,0 +1,183 @@
+use failure::Error;
+
+use actor::{Actor, ActorKind, Actors, Handle};
+use collision::Collider;
+use event::Event;
+use level::LevelSpec;
+use maze::{House, TILE_SIZE};
+use rng::Rng;
+use score::Score;
+use state::{Decode, Encode, StateReader, StateWriter};
+use world::TICKS_PER_SECOND;
+
+// Pellets eaten in a level before each fruit comes out.
+pub const FRUIT_DOTS: [u32; 2] = [70, 170];
+
+// Six tiles below the ghost house exit, halfway between two tiles like
+// Pac-Man's start.
+pub fn fruit_position(house: House) -> (i32, i32) {
+    let (x, y) = house.exit();
+    (x, y + 6 * TILE_SIZE)
+}
+
+// Each fruit stays for 9 seconds and a random part of another one.
+const FRUIT_TICKS: u32 = 9 * TICKS_PER_SECOND;
+const FRUIT_EXTRA_TICKS: u32 = TICKS_PER_SECOND;
+
+const POINTS_TICKS: u32 = 2 * TICKS_PER_SECOND;
+
+// Brings out the level's fruit as pellets get eaten and pays out when
+// Pac-Man runs into it. Everything it needs comes from the level table and
+// the events of the tick.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BonusFruit {
+    dots: u32,
+    shown: usize,
+    handle: Option<Handle>,
+}
+
+impl BonusFruit {
+    pub fn new() -> BonusFruit {
+        BonusFruit::default()
+    }
+
+    // Pellets and energizers eaten this level.
+    pub fn dots(&self) -> u32 {
+        self.dots
+    }
+
+    // The fruit on screen, if it hasn't been eaten or run out of time.
+    pub fn handle(&self, actors: &Actors) -> Option<Handle> {
+        self.handle.filter(|&handle| actors.get(handle).is_some())
+    }
+
+    // Returns the Pac-Man who ate the fruit, if one did.
+    pub fn update(
+        &mut self,
+        spec: &LevelSpec,
+        house: House,
+        actors: &mut Actors,
+        score: &mut Score,
+        rng: &mut Rng,
+        events: &mut Vec<Event>,
+    ) -> Option<usize> {
+        let mut eaten_by = None;
+        for event in events.iter() {
+            match *event {
+                Event::PelletEaten { .. } | Event::EnergizerEaten { .. } => self.dots += 1,
+                Event::Collision {
+                    pacman,
+                    with: Collider::Actor(handle),
+                } if Some(handle) == self.handle && eaten_by.is_none() => eaten_by = Some(pacman),
+                _ => {}
+            }
+        }
+
+        let eaten_by = eaten_by.and_then(|pacman| {
+            let fruit = actors.despawn(self.handle.take()?)?;
+            score.eat_fruit(spec.fruit, spec.fruit_points, events);
+            let popup = Actor::new(ActorKind::Points(spec.fruit_points), fruit.motion.x, fruit.motion.y);
+            actors.spawn(popup.with_lifetime(POINTS_TICKS));
+            Some(pacman)
+        });
+
+        if self.shown < FRUIT_DOTS.len() && self.dots >= FRUIT_DOTS[self.shown] {
+            self.shown += 1;
+            if let Some(handle) = self.handle.take() {
+                actors.despawn(handle);
+            }
+            let ticks = FRUIT_TICKS + rng.below(FRUIT_EXTRA_TICKS + 1);
+            let (x, y) = fruit_position(house);
+            let fruit = Actor::new(ActorKind::Fruit(spec.fruit), x, y);
+            self.handle = Some(actors.spawn(fruit.with_lifetime(ticks)));
+        }
+        eaten_by
+    }
+}
+
+impl Encode for BonusFruit {
+    fn encode(&self, w: &mut StateWriter) {
+        w.write_u32(self.dots);
+        w.write_u8(self.shown as u8);
+        w.write(&self.handle);
+    }
+}
+
+impl Decode for BonusFruit {
+    fn decode(r: &mut StateReader) -> Result<BonusFruit, Error> {
+        Ok(BonusFruit {
+            dots: r.read_u32()?,
+            shown: (r.read_u8()? as usize).min(FRUIT_DOTS.len()),
+            handle: r.read()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use level::{Fruit, LevelTable};
+    use maze::Maze;
+    use score::ScoreConfig;
+
+    fn eat_pellets(fruit: &mut BonusFruit, actors: &mut Actors, score: &mut Score, rng: &mut Rng, count: u32) {
+        let spec = LevelTable::arcade().get(3).clone();
+        for _ in 0..count {
+            let mut events = vec![Event::PelletEaten { points: 10 }];
+            fruit.update(&spec, Maze::arcade().house(), actors, score, rng, &mut events);
+        }
+    }
+
+    #[test]
+    fn appears_after_70_and_170_dots() {
+        let mut fruit = BonusFruit::new();
+        let mut actors = Actors::new();
+        let mut score = Score::new(ScoreConfig::default());
+        let mut rng = Rng::new(1);
+
+        eat_pellets(&mut fruit, &mut actors, &mut score, &mut rng, 69);
+        assert!(fruit.handle(&actors).is_none());
+        eat_pellets(&mut fruit, &mut actors, &mut score, &mut rng, 1);
+        let handle = fruit.handle(&actors).unwrap();
+        let lifetime = actors.get(handle).unwrap().lifetime.unwrap();
+        assert!((FRUIT_TICKS..=FRUIT_TICKS + FRUIT_EXTRA_TICKS).contains(&lifetime));
+        assert_eq!(actors.get(handle).unwrap().kind, ActorKind::Fruit(Fruit::Peach));
+
+        for _ in 0..lifetime {
+            actors.tick();
+        }
+        assert!(fruit.handle(&actors).is_none());
+
+        eat_pellets(&mut fruit, &mut actors, &mut score, &mut rng, 100);
+        assert!(fruit.handle(&actors).is_some());
+        eat_pellets(&mut fruit, &mut actors, &mut score, &mut rng, 100);
+        assert_eq!(fruit.dots(), 270);
+    }
+
+    #[test]
+    fn touching_awards_the_level_fruit() {
+        let mut fruit = BonusFruit::new();
+        let mut actors = Actors::new();
+        let mut score = Score::new(ScoreConfig::default());
+        let mut rng = Rng::new(1);
+        eat_pellets(&mut fruit, &mut actors, &mut score, &mut rng, 70);
+        let before = score.score();
+
+        let handle = fruit.handle(&actors).unwrap();
+        let spec = LevelTable::arcade().get(3).clone();
+        let mut events = vec![Event::Collision {
+            pacman: 0,
+            with: Collider::Actor(handle),
+        }];
+        let house = Maze::arcade().house();
+        assert_eq!(fruit.update(&spec, house, &mut actors, &mut score, &mut rng, &mut events), Some(0));
+
+        assert_eq!(score.score(), before + 500);
+        assert!(events.contains(&Event::FruitEaten {
+            fruit: Fruit::Peach,
+            points: 500
+        }));
+        assert!(fruit.handle(&actors).is_none());
+        assert_eq!(actors.iter().next().unwrap().1.kind, ActorKind::Points(500));
+    }
+}
@@ -0,0 +1,426 @@
+use failure::{err_msg, Error};
+
+use actor::{Actor, ActorKind, Motion};
+use maze::{Direction, House, Maze, TilePos, Zone, TILE_SIZE};
+use rng::Rng;
+use state::{Decode, Encode, StateReader, StateWriter};
+use world::TICKS_PER_SECOND;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GhostKind {
+    Blinky,
+    Pinky,
+    Inky,
+    Clyde,
+}
+
+impl GhostKind {
+    pub const ALL: [GhostKind; 4] = [
+        GhostKind::Blinky,
+        GhostKind::Pinky,
+        GhostKind::Inky,
+        GhostKind::Clyde,
+    ];
+
+    pub fn scatter_target(self) -> TilePos {
+        match self {
+            GhostKind::Blinky => TilePos::new(25, -3),
+            GhostKind::Pinky => TilePos::new(2, -3),
+            GhostKind::Inky => TilePos::new(27, 31),
+            GhostKind::Clyde => TilePos::new(0, 31),
+        }
+    }
+
+    // Number of pellets Pac-Man has to eat before the ghost leaves the house.
+    pub fn dot_limit(self, level: u32) -> u32 {
+        match (self, level) {
+            (GhostKind::Inky, 1) => 30,
+            (GhostKind::Clyde, 1) => 60,
+            (GhostKind::Clyde, 2) => 50,
+            _ => 0,
+        }
+    }
+
+    fn home_x(self, house: House) -> i32 {
+        let (x, _) = house.exit();
+        match self {
+            GhostKind::Blinky | GhostKind::Pinky => x,
+            GhostKind::Inky => x - 2 * TILE_SIZE,
+            GhostKind::Clyde => x + 2 * TILE_SIZE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GhostState {
+    InHouse,
+    LeavingHouse,
+    Active,
+    Eaten,
+    EnteringHouse,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GhostMode {
+    Scatter,
+    Chase,
+}
+
+// Alternating scatter and chase durations in ticks, starting with scatter.
+// The last entry lasts forever.
+const FOREVER: u32 = u32::MAX;
+const LEVEL_1_SCHEDULE: [u32; 8] = [420, 1200, 420, 1200, 300, 1200, 300, FOREVER];
+const LEVEL_2_SCHEDULE: [u32; 8] = [420, 1200, 420, 1200, 300, 61_980, 1, FOREVER];
+const LEVEL_5_SCHEDULE: [u32; 8] = [300, 1200, 300, 1200, 300, 62_220, 1, FOREVER];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModeTimer {
+    schedule: &'static [u32],
+    index: usize,
+    ticks: u32,
+}
+
+impl ModeTimer {
+    pub fn new(level: u32) -> ModeTimer {
+        let schedule: &'static [u32] = match level {
+            1 => &LEVEL_1_SCHEDULE,
+            2..=4 => &LEVEL_2_SCHEDULE,
+            _ => &LEVEL_5_SCHEDULE,
+        };
+        ModeTimer {
+            schedule,
+            index: 0,
+            ticks: 0,
+        }
+    }
+
+    pub fn mode(&self) -> GhostMode {
+        if self.index.is_multiple_of(2) {
+            GhostMode::Scatter
+        } else {
+            GhostMode::Chase
+        }
+    }
+
+    // None once the final, endless chase has started.
+    pub fn remaining(&self) -> Option<u32> {
+        match self.schedule[self.index] {
+            FOREVER => None,
+            duration => Some(duration - self.ticks),
+        }
+    }
+
+    // The schedule comes from the level, only the position in it is saved.
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), Error> {
+        let index = r.read_u8()? as usize;
+        let ticks = r.read_u32()?;
+        if index >= self.schedule.len() {
+            return Err(err_msg(format!("Invalid mode index {}", index)));
+        }
+        let duration = self.schedule[index];
+        if duration != FOREVER && ticks > duration {
+            return Err(err_msg(format!("Mode timer {} is past its duration {}", ticks, duration)));
+        }
+        self.index = index;
+        self.ticks = ticks;
+        Ok(())
+    }
+
+    // Returns true when the mode changed.
+    pub fn tick(&mut self) -> bool {
+        if self.schedule[self.index] == FOREVER {
+            return false;
+        }
+
+        self.ticks += 1;
+        if self.ticks >= self.schedule[self.index] {
+            self.index += 1;
+            self.ticks = 0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+// A ghost's own state. Where it is and which way it's headed is kept by the
+// actor it belongs to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ghost {
+    pub kind: GhostKind,
+    pub state: GhostState,
+    pub frightened: bool,
+    pub dot_counter: u32,
+    pub target: TilePos,
+    // The turn a player steering this ghost asked for, taken at the next
+    // tile where it's open.
+    pub steer: Option<Direction>,
+}
+
+impl Ghost {
+    // The ghost at its start position, ready to spawn.
+    pub fn actor(kind: GhostKind, house: House) -> Actor {
+        let (exit_x, exit_y) = house.exit();
+        let (x, y, dir, state) = match kind {
+            GhostKind::Blinky => (exit_x, exit_y, Direction::Left, GhostState::Active),
+            GhostKind::Pinky => (kind.home_x(house), house.center_y(), Direction::Down, GhostState::InHouse),
+            _ => (kind.home_x(house), house.center_y(), Direction::Up, GhostState::InHouse),
+        };
+        let ghost = Ghost {
+            kind,
+            state,
+            frightened: false,
+            dot_counter: 0,
+            target: kind.scatter_target(),
+            steer: None,
+        };
+        Actor::new(ActorKind::Ghost(ghost), x, y).with_dir(dir)
+    }
+
+    pub fn reverse(&self, motion: &mut Motion) {
+        if self.state == GhostState::Active {
+            motion.dir = motion.dir.opposite();
+        }
+    }
+
+    pub fn eaten(&mut self) {
+        self.state = GhostState::Eaten;
+        self.frightened = false;
+    }
+
+    // `tile` is where the ghost itself is.
+    pub fn chase_target(&self, tile: TilePos, pacman: TilePos, pacman_dir: Direction, blinky: TilePos) -> TilePos {
+        // Looking ahead while Pac-Man faces up also shifts the target left,
+        // an overflow bug in the arcade code that players rely on.
+        let ahead = |tiles: i32| {
+            let target = pacman.offset(pacman_dir, tiles);
+            if pacman_dir == Direction::Up {
+                target.offset(Direction::Left, tiles)
+            } else {
+                target
+            }
+        };
+
+        match self.kind {
+            GhostKind::Blinky => pacman,
+            GhostKind::Pinky => ahead(4),
+            GhostKind::Inky => {
+                let pivot = ahead(2);
+                TilePos::new(2 * pivot.x - blinky.x, 2 * pivot.y - blinky.y)
+            }
+            GhostKind::Clyde => {
+                if tile.distance_squared(pacman) > 64 {
+                    pacman
+                } else {
+                    self.kind.scatter_target()
+                }
+            }
+        }
+    }
+
+    pub fn advance(&mut self, motion: &mut Motion, maze: &Maze, rng: &mut Rng) {
+        let house = maze.house();
+        for _ in 0..motion.steps() {
+            match self.state {
+                GhostState::InHouse => self.bob(motion, house),
+                GhostState::LeavingHouse => self.leave_house(motion, house),
+                GhostState::EnteringHouse => self.enter_house(motion, house),
+                GhostState::Active | GhostState::Eaten => self.step(motion, maze, house, rng),
+            }
+        }
+    }
+
+    // Half a tile up and down around the middle of the house.
+    fn bob(&mut self, motion: &mut Motion, house: House) {
+        if motion.y <= house.center_y() - TILE_SIZE / 2 {
+            motion.dir = Direction::Down;
+        } else if motion.y >= house.center_y() + TILE_SIZE / 2 {
+            motion.dir = Direction::Up;
+        }
+        motion.y += motion.dir.delta().1;
+    }
+
+    fn leave_house(&mut self, motion: &mut Motion, house: House) {
+        let (exit_x, exit_y) = house.exit();
+        if motion.x != exit_x {
+            motion.dir = if motion.x < exit_x {
+                Direction::Right
+            } else {
+                Direction::Left
+            };
+            motion.x += motion.dir.delta().0;
+        } else if motion.y > exit_y {
+            motion.dir = Direction::Up;
+            motion.y -= 1;
+        } else {
+            self.state = GhostState::Active;
+            motion.dir = Direction::Left;
+        }
+    }
+
+    fn enter_house(&mut self, motion: &mut Motion, house: House) {
+        let (exit_x, _) = house.exit();
+        if motion.x != exit_x {
+            motion.dir = if motion.x < exit_x {
+                Direction::Right
+            } else {
+                Direction::Left
+            };
+            motion.x += motion.dir.delta().0;
+        } else if motion.y < house.center_y() {
+            motion.dir = Direction::Down;
+            motion.y += 1;
+        } else {
+            self.state = GhostState::LeavingHouse;
+        }
+    }
+
+    fn step(&mut self, motion: &mut Motion, maze: &Maze, house: House, rng: &mut Rng) {
+        if self.state == GhostState::Eaten {
+            let (exit_x, exit_y) = house.exit();
+            self.target = house.entrance();
+            if motion.y == exit_y && (motion.x - exit_x).abs() <= TILE_SIZE {
+                self.state = GhostState::EnteringHouse;
+                return;
+            }
+        }
+
+        if motion.at_tile_center() {
+            motion.dir = self.choose_direction(motion, maze, rng);
+            if self.steer == Some(motion.dir) {
+                self.steer = None;
+            }
+        }
+
+        let (dx, dy) = motion.dir.delta();
+        motion.x = maze.wrap_x(motion.x + dx);
+        motion.y += dy;
+    }
+
+    // Ghosts never reverse on their own. At each tile they take the exit
+    // closest to their target, or a random one while frightened. Only
+    // frightened ghosts and eyes may turn up in a no up turn zone. A player
+    // steering the ghost overrides all that, within the same rules.
+    fn choose_direction(&self, motion: &Motion, maze: &Maze, rng: &mut Rng) -> Direction {
+        let tile = motion.tile();
+        let reverse = motion.dir.opposite();
+        let no_up = self.state == GhostState::Active && !self.frightened && maze.zone(tile) == Zone::NoUpTurn;
+        let is_open = |dir: Direction| {
+            dir != reverse && maze.is_walkable(tile.step(dir)) && !(no_up && dir == Direction::Up)
+        };
+
+        if let Some(dir) = self.steer.filter(|&dir| self.state == GhostState::Active && is_open(dir)) {
+            return dir;
+        }
+
+        if self.frightened {
+            let start = rng.below(4) as usize;
+            return (0..4)
+                .map(|i| Direction::ALL[(start + i) % 4])
+                .find(|&dir| is_open(dir))
+                .unwrap_or(reverse);
+        }
+
+        let mut best: Option<(Direction, i32)> = None;
+        for &dir in Direction::ALL.iter() {
+            if !is_open(dir) {
+                continue;
+            }
+            let distance = tile.step(dir).distance_squared(self.target);
+            if best.is_none_or(|(_, best_distance)| distance < best_distance) {
+                best = Some((dir, distance));
+            }
+        }
+        best.map_or(reverse, |(dir, _)| dir)
+    }
+}
+
+pub fn idle_limit(level: u32) -> u32 {
+    if level < 5 {
+        4 * TICKS_PER_SECOND
+    } else {
+        3 * TICKS_PER_SECOND
+    }
+}
+
+impl Encode for ModeTimer {
+    fn encode(&self, w: &mut StateWriter) {
+        w.write_u8(self.index as u8);
+        w.write_u32(self.ticks);
+    }
+}
+
+impl Encode for GhostKind {
+    fn encode(&self, w: &mut StateWriter) {
+        w.write_u8(*self as u8);
+    }
+}
+
+impl Decode for GhostKind {
+    fn decode(r: &mut StateReader) -> Result<GhostKind, Error> {
+        let value = r.read_u8()?;
+        GhostKind::ALL
+            .get(value as usize)
+            .cloned()
+            .ok_or_else(|| err_msg(format!("Invalid ghost {}", value)))
+    }
+}
+
+impl Encode for GhostState {
+    fn encode(&self, w: &mut StateWriter) {
+        w.write_u8(*self as u8);
+    }
+}
+
+impl Decode for GhostState {
+    fn decode(r: &mut StateReader) -> Result<GhostState, Error> {
+        match r.read_u8()? {
+            0 => Ok(GhostState::InHouse),
+            1 => Ok(GhostState::LeavingHouse),
+            2 => Ok(GhostState::Active),
+            3 => Ok(GhostState::Eaten),
+            4 => Ok(GhostState::EnteringHouse),
+            value => Err(err_msg(format!("Invalid ghost state {}", value))),
+        }
+    }
+}
+
+impl Encode for Ghost {
+    fn encode(&self, w: &mut StateWriter) {
+        w.write(&self.kind);
+        w.write(&self.state);
+        w.write_bool(self.frightened);
+        w.write_u32(self.dot_counter);
+        w.write(&self.target);
+        w.write(&self.steer);
+    }
+}
+
+impl Decode for Ghost {
+    fn decode(r: &mut StateReader) -> Result<Ghost, Error> {
+        Ok(Ghost {
+            kind: r.read()?,
+            state: r.read()?,
+            frightened: r.read_bool()?,
+            dot_counter: r.read_u32()?,
+            target: r.read()?,
+            steer: r.read()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mode_timer_rejects_ticks_past_the_duration() {
+        let mut timer = ModeTimer::new(1);
+        let data = ::state::encode(|w| {
+            w.write_u8(0);
+            w.write_u32(LEVEL_1_SCHEDULE[0] + 1);
+        });
+        assert!(timer.load_state(&mut ::state::decode(&data).unwrap()).is_err());
+        assert_eq!(timer.remaining(), Some(LEVEL_1_SCHEDULE[0]));
+    }
+}
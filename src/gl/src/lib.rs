@@ -1 +1,3 @@
+#![allow(bare_trait_objects, unused_mut, clippy::all)]
+
 include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
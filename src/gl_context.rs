@@ -1,11 +1,12 @@
 use std::ffi::{CStr, CString};
+use std::ptr;
 
 use failure::Error;
 use gl;
 
 use bridge;
 
-pub static mut CURRENT_GL_CONTEXT: *mut GlContext = 0 as *mut GlContext;
+pub static mut CURRENT_GL_CONTEXT: *mut GlContext = ptr::null_mut();
 
 #[repr(u32)]
 pub enum GlCapability {
@@ -37,7 +38,7 @@ impl GlContext {
 
     pub fn current() -> &'static GlContext {
         unsafe {
-            if CURRENT_GL_CONTEXT == 0 as *mut GlContext {
+            if CURRENT_GL_CONTEXT.is_null() {
                 panic!("Failed to get current gl context. Call GlContext::init() first.")
             }
 
@@ -47,7 +48,7 @@ impl GlContext {
 
     pub fn current_mut() -> &'static mut GlContext {
         unsafe {
-            if CURRENT_GL_CONTEXT == 0 as *mut GlContext {
+            if CURRENT_GL_CONTEXT.is_null() {
                 panic!("Failed to get current gl context. Call GlContext::init() first.")
             }
 
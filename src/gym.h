@@ -0,0 +1,61 @@
+#ifndef PACMAN_GYM_H
+#define PACMAN_GYM_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#include <stdint.h>
+
+// A headless game for training agents, see src/gym.rs. Link against the
+// pacman static or dynamic library.
+
+enum PacManEnvObservation {
+    // width x height tiles, one 0/1 byte per PACMAN_ENV_CHANNEL_*.
+    PACMAN_ENV_OBSERVATION_GRID = 0,
+    // RGB bytes with `scale` pixels per tile.
+    PACMAN_ENV_OBSERVATION_RGB = 1,
+};
+
+enum PacManEnvChannel {
+    PACMAN_ENV_CHANNEL_WALL = 0,
+    PACMAN_ENV_CHANNEL_PELLET = 1,
+    PACMAN_ENV_CHANNEL_ENERGIZER = 2,
+    PACMAN_ENV_CHANNEL_PACMAN = 3,
+    PACMAN_ENV_CHANNEL_GHOST = 4,
+    PACMAN_ENV_CHANNEL_FRIGHTENED_GHOST = 5,
+    PACMAN_ENV_CHANNEL_EYES = 6,
+};
+
+enum PacManEnvAction {
+    PACMAN_ENV_ACTION_NONE = 0,
+    PACMAN_ENV_ACTION_UP = 1,
+    PACMAN_ENV_ACTION_DOWN = 2,
+    PACMAN_ENV_ACTION_LEFT = 3,
+    PACMAN_ENV_ACTION_RIGHT = 4,
+};
+
+typedef struct PacManEnv PacManEnv;
+
+// Returns NULL for an unknown observation kind or a scale below 1.
+PacManEnv *pacman_env_new(int observation_kind, int scale);
+void pacman_env_free(PacManEnv *env);
+
+// Game ticks run per step, 4 by default.
+void pacman_env_set_ticks_per_step(PacManEnv *env, int ticks);
+
+// Observations are row major with interleaved channels. Returns the size in
+// bytes, any of the out pointers may be NULL.
+int pacman_env_observation_size(const PacManEnv *env, int *width, int *height, int *channels);
+
+// `observation` receives pacman_env_observation_size() bytes and may be NULL.
+void pacman_env_reset(PacManEnv *env, uint32_t seed, uint8_t *observation);
+
+// Returns the points scored during the step. `done` is set once the game is over.
+float pacman_env_step(PacManEnv *env, int action, uint8_t *observation, int *done);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,410 @@
+// The C functions below trust the pointers they are given, like bridge does.
+#![allow(clippy::missing_safety_doc)]
+
+use std::os::raw::{c_float, c_int};
+use std::ptr;
+use std::slice;
+
+use ghost::GhostState;
+use maze::{Tile, TILE_SIZE};
+use phase::Phase;
+use simulation::Simulation;
+use world::World;
+use {Buttons, Input};
+
+// A gym style environment for training agents: the agent picks a direction,
+// the game runs a few ticks, and the agent gets back what it sees, the points
+// scored and whether the game is over. Everything outside of actual play
+// (READY!, death animations, intermissions) is skipped over.
+
+pub const DEFAULT_TICKS_PER_STEP: u32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    None,
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Action {
+    pub const ALL: [Action; 5] = [Action::None, Action::Up, Action::Down, Action::Left, Action::Right];
+
+    fn buttons(self) -> Buttons {
+        match self {
+            Action::None => Buttons::empty(),
+            Action::Up => Buttons::UP,
+            Action::Down => Buttons::DOWN,
+            Action::Left => Buttons::LEFT,
+            Action::Right => Buttons::RIGHT,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObservationKind {
+    // One cell per maze tile with a 0/1 byte for each of the GRID_* channels.
+    Grid,
+    // An RGB picture of the maze with `scale` pixels per tile.
+    Rgb { scale: u32 },
+}
+
+pub const GRID_WALL: usize = 0;
+pub const GRID_PELLET: usize = 1;
+pub const GRID_ENERGIZER: usize = 2;
+pub const GRID_PACMAN: usize = 3;
+pub const GRID_GHOST: usize = 4;
+pub const GRID_FRIGHTENED_GHOST: usize = 5;
+pub const GRID_EYES: usize = 6;
+pub const GRID_CHANNELS: usize = 7;
+
+// Row major, channels interleaved: `data[(y * width + x) * channels + c]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Observation {
+    pub width: usize,
+    pub height: usize,
+    pub channels: usize,
+    pub data: Vec<u8>,
+}
+
+impl Observation {
+    fn new(width: usize, height: usize, channels: usize) -> Observation {
+        Observation {
+            width,
+            height,
+            channels,
+            data: vec![0; width * height * channels],
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize, channel: usize) -> u8 {
+        self.data[(y * self.width + x) * self.channels + channel]
+    }
+
+    fn set(&mut self, x: usize, y: usize, channel: usize, value: u8) {
+        if x < self.width && y < self.height {
+            self.data[(y * self.width + x) * self.channels + channel] = value;
+        }
+    }
+
+    fn fill(&mut self, x: usize, y: usize, size: usize, color: [u8; 3]) {
+        for py in y..y + size {
+            for px in x..x + size {
+                for (c, &value) in color.iter().enumerate() {
+                    self.set(px, py, c, value);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub observation: Observation,
+    pub reward: i32,
+    pub done: bool,
+}
+
+pub struct Env {
+    simulation: Simulation,
+    input: Input,
+    kind: ObservationKind,
+    ticks_per_step: u32,
+}
+
+impl Env {
+    pub fn new(kind: ObservationKind) -> Env {
+        Env {
+            simulation: Simulation::arcade(0),
+            input: Input::new(),
+            kind,
+            ticks_per_step: DEFAULT_TICKS_PER_STEP,
+        }
+    }
+
+    pub fn set_ticks_per_step(&mut self, ticks: u32) {
+        self.ticks_per_step = ticks.max(1);
+    }
+
+    pub fn simulation(&self) -> &Simulation {
+        &self.simulation
+    }
+
+    // The size of every observation, as (width, height, channels).
+    pub fn observation_size(&self) -> (usize, usize, usize) {
+        let maze = self.simulation.world().maze();
+        let (width, height) = (maze.width() as usize, maze.height() as usize);
+        match self.kind {
+            ObservationKind::Grid => (width, height, GRID_CHANNELS),
+            ObservationKind::Rgb { scale } => (width * scale as usize, height * scale as usize, 3),
+        }
+    }
+
+    // Starts a new game and runs it up to the point the agent can move.
+    pub fn reset(&mut self, seed: u32) -> Observation {
+        self.simulation.restart(seed);
+        self.input = Input::new();
+        self.tick(Buttons::START);
+        self.skip_to_play();
+        self.observe()
+    }
+
+    pub fn step(&mut self, action: Action) -> Step {
+        let score = self.simulation.world().score().score();
+        for _ in 0..self.ticks_per_step {
+            self.tick(action.buttons());
+            if !self.is_playing() {
+                break;
+            }
+        }
+        self.skip_to_play();
+
+        Step {
+            observation: self.observe(),
+            reward: (self.simulation.world().score().score() - score) as i32,
+            done: self.is_done(),
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        matches!(*self.simulation.phase(), Phase::GameOver(_) | Phase::Attract(_))
+    }
+
+    fn is_playing(&self) -> bool {
+        matches!(*self.simulation.phase(), Phase::Playing(_))
+    }
+
+    fn tick(&mut self, buttons: Buttons) {
+        self.input.update(buttons);
+        self.simulation.update(&self.input);
+    }
+
+    fn skip_to_play(&mut self) {
+        while !self.is_playing() && !self.is_done() {
+            self.tick(Buttons::empty());
+        }
+    }
+
+    pub fn observe(&self) -> Observation {
+        match self.kind {
+            ObservationKind::Grid => grid(self.simulation.world()),
+            ObservationKind::Rgb { scale } => rgb(self.simulation.world(), scale as usize),
+        }
+    }
+}
+
+fn grid(world: &World) -> Observation {
+    let maze = world.maze();
+    let mut observation = Observation::new(maze.width() as usize, maze.height() as usize, GRID_CHANNELS);
+
+    for (pos, tile) in maze.tiles() {
+        let channel = match tile {
+            Tile::Wall | Tile::Door => GRID_WALL,
+            Tile::Pellet => GRID_PELLET,
+            Tile::Energizer => GRID_ENERGIZER,
+            Tile::Empty => continue,
+        };
+        observation.set(pos.x as usize, pos.y as usize, channel, 1);
+    }
+
+    let pacman = world.pacman().tile();
+    if maze.contains(pacman) {
+        observation.set(pacman.x as usize, pacman.y as usize, GRID_PACMAN, 1);
+    }
+
+    for (actor, ghost) in world.ghosts() {
+        let tile = actor.tile();
+        if !maze.contains(tile) {
+            continue;
+        }
+        let channel = match ghost.state {
+            GhostState::Eaten | GhostState::EnteringHouse => GRID_EYES,
+            _ if ghost.frightened => GRID_FRIGHTENED_GHOST,
+            _ => GRID_GHOST,
+        };
+        observation.set(tile.x as usize, tile.y as usize, channel, 1);
+    }
+
+    observation
+}
+
+const WALL_COLOR: [u8; 3] = [33, 33, 222];
+const PELLET_COLOR: [u8; 3] = [255, 184, 151];
+const PACMAN_COLOR: [u8; 3] = [255, 255, 0];
+const GHOST_COLORS: [[u8; 3]; 4] = [[255, 0, 0], [255, 184, 255], [0, 255, 255], [255, 184, 82]];
+const FRIGHTENED_COLOR: [u8; 3] = [33, 33, 255];
+const EYES_COLOR: [u8; 3] = [255, 255, 255];
+
+// A flat coloured picture rather than the sprite sheet, so no assets are needed.
+fn rgb(world: &World, scale: usize) -> Observation {
+    let maze = world.maze();
+    let mut observation = Observation::new(maze.width() as usize * scale, maze.height() as usize * scale, 3);
+
+    for (pos, tile) in maze.tiles() {
+        let (x, y) = (pos.x as usize * scale, pos.y as usize * scale);
+        match tile {
+            Tile::Wall | Tile::Door => observation.fill(x, y, scale, WALL_COLOR),
+            Tile::Pellet => observation.fill(x + scale / 2, y + scale / 2, (scale / 4).max(1), PELLET_COLOR),
+            Tile::Energizer => observation.fill(x + scale / 4, y + scale / 4, (scale / 2).max(1), PELLET_COLOR),
+            Tile::Empty => {}
+        }
+    }
+
+    // Actors are a tile sized square centered on their position.
+    let mut actor = |x: i32, y: i32, color: [u8; 3]| {
+        let left = (x - TILE_SIZE / 2).max(0) as usize * scale / TILE_SIZE as usize;
+        let top = (y - TILE_SIZE / 2).max(0) as usize * scale / TILE_SIZE as usize;
+        observation.fill(left, top, scale, color);
+    };
+
+    for ((ghost, state), &color) in world.ghosts().zip(GHOST_COLORS.iter()) {
+        let color = match state.state {
+            GhostState::Eaten | GhostState::EnteringHouse => EYES_COLOR,
+            _ if state.frightened => FRIGHTENED_COLOR,
+            _ => color,
+        };
+        actor(ghost.motion.x, ghost.motion.y, color);
+    }
+    let pacman = &world.pacman().motion;
+    actor(pacman.x, pacman.y, PACMAN_COLOR);
+
+    observation
+}
+
+// C interface for binding from other languages, see gym.h.
+
+pub const PACMAN_ENV_OBSERVATION_GRID: c_int = 0;
+pub const PACMAN_ENV_OBSERVATION_RGB: c_int = 1;
+
+#[no_mangle]
+pub extern "C" fn pacman_env_new(observation_kind: c_int, scale: c_int) -> *mut Env {
+    let kind = match observation_kind {
+        PACMAN_ENV_OBSERVATION_GRID => ObservationKind::Grid,
+        PACMAN_ENV_OBSERVATION_RGB if scale > 0 => ObservationKind::Rgb { scale: scale as u32 },
+        _ => return ptr::null_mut(),
+    };
+    Box::into_raw(Box::new(Env::new(kind)))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn pacman_env_free(env: *mut Env) {
+    if !env.is_null() {
+        drop(Box::from_raw(env));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn pacman_env_set_ticks_per_step(env: *mut Env, ticks: c_int) {
+    (*env).set_ticks_per_step(ticks.max(1) as u32);
+}
+
+// Returns the number of bytes in an observation.
+#[no_mangle]
+pub unsafe extern "C" fn pacman_env_observation_size(
+    env: *const Env,
+    width: *mut c_int,
+    height: *mut c_int,
+    channels: *mut c_int,
+) -> c_int {
+    let (w, h, c) = (*env).observation_size();
+    if !width.is_null() {
+        *width = w as c_int;
+    }
+    if !height.is_null() {
+        *height = h as c_int;
+    }
+    if !channels.is_null() {
+        *channels = c as c_int;
+    }
+    (w * h * c) as c_int
+}
+
+unsafe fn copy_observation(observation: &Observation, out: *mut u8) {
+    if !out.is_null() {
+        slice::from_raw_parts_mut(out, observation.data.len()).copy_from_slice(&observation.data);
+    }
+}
+
+// `observation` must hold pacman_env_observation_size() bytes, or be NULL.
+#[no_mangle]
+pub unsafe extern "C" fn pacman_env_reset(env: *mut Env, seed: u32, observation: *mut u8) {
+    copy_observation(&(*env).reset(seed), observation);
+}
+
+// Returns the reward. Invalid actions are treated as doing nothing.
+#[no_mangle]
+pub unsafe extern "C" fn pacman_env_step(
+    env: *mut Env,
+    action: c_int,
+    observation: *mut u8,
+    done: *mut c_int,
+) -> c_float {
+    let action = Action::ALL.get(action as usize).cloned().unwrap_or(Action::None);
+    let step = (*env).step(action);
+    copy_observation(&step.observation, observation);
+    if !done.is_null() {
+        *done = step.done as c_int;
+    }
+    step.reward as c_float
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reset_skips_to_play() {
+        let mut env = Env::new(ObservationKind::Grid);
+        let observation = env.reset(3);
+        assert!(env.is_playing());
+        assert_eq!(
+            (observation.width, observation.height, observation.channels),
+            env.observation_size()
+        );
+
+        let pacman = env.simulation().world().pacman().tile();
+        assert_eq!(observation.get(pacman.x as usize, pacman.y as usize, GRID_PACMAN), 1);
+        assert_eq!(observation.get(0, 0, GRID_WALL), 1);
+    }
+
+    #[test]
+    fn eating_is_rewarded_and_idling_ends_the_game() {
+        let mut env = Env::new(ObservationKind::Rgb { scale: 2 });
+        env.reset(3);
+
+        let mut total = 0;
+        let mut steps = 0;
+        loop {
+            let step = env.step(Action::Left);
+            assert_eq!(step.observation.data.len(), 28 * 2 * 31 * 2 * 3);
+            total += step.reward;
+            steps += 1;
+            if step.done {
+                break;
+            }
+            assert!(steps < 10_000, "Game never ended");
+        }
+        assert!(total > 0);
+        assert_eq!(env.simulation().world().score().lives(), 0);
+    }
+
+    #[test]
+    fn c_interface() {
+        unsafe {
+            assert!(pacman_env_new(7, 0).is_null());
+
+            let env = pacman_env_new(PACMAN_ENV_OBSERVATION_GRID, 0);
+            let mut channels = 0;
+            let size = pacman_env_observation_size(env, ptr::null_mut(), ptr::null_mut(), &mut channels);
+            assert_eq!(channels, GRID_CHANNELS as c_int);
+
+            let mut observation = vec![0; size as usize];
+            pacman_env_reset(env, 1, observation.as_mut_ptr());
+            let mut done = 1;
+            pacman_env_step(env, 3, observation.as_mut_ptr(), &mut done);
+            assert_eq!(done, 0);
+            pacman_env_free(env);
+        }
+    }
+}
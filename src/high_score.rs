@@ -0,0 +1,340 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use failure::{err_msg, Error};
+use state::{Decode, Encode, StateReader, StateWriter};
+use storage;
+
+pub const NUM_ENTRIES: usize = 10;
+pub const FILE_NAME: &str = "highscores.dat";
+
+const MAGIC: &[u8; 4] = b"PMHS";
+const VERSION: u16 = 1;
+const HEADER_SIZE: usize = 7;
+const ENTRY_SIZE: usize = 7;
+const CHECKSUM_SIZE: usize = 4;
+
+const LETTERS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ. ";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HighScoreEntry {
+    pub initials: [u8; 3],
+    pub score: u32,
+}
+
+impl HighScoreEntry {
+    pub fn initials(&self) -> &str {
+        // Initials are validated on insert and decode, so they are always ASCII.
+        ::std::str::from_utf8(&self.initials).unwrap()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HighScoreTable {
+    entries: Vec<HighScoreEntry>,
+}
+
+impl HighScoreTable {
+    pub fn new() -> HighScoreTable {
+        HighScoreTable {
+            entries: Vec::new(),
+        }
+    }
+
+    // A missing file is a fresh install. A corrupt one is moved aside so the
+    // next save doesn't destroy it, and we start over with an empty table.
+    pub fn load<P: AsRef<Path>>(path: P) -> HighScoreTable {
+        let path = path.as_ref();
+        let data = match storage::read(path) {
+            Ok(data) => data,
+            Err(e) => {
+                match e.downcast_ref::<io::Error>() {
+                    Some(e) if e.kind() == io::ErrorKind::NotFound => {}
+                    _ => warn!("Failed to read high scores {}: {}", path.display(), e),
+                }
+                return HighScoreTable::new();
+            }
+        };
+
+        match HighScoreTable::decode(&data) {
+            Ok(table) => table,
+            Err(e) => {
+                warn!("Corrupt high score file {}: {}", path.display(), e);
+                let mut backup = path.as_os_str().to_owned();
+                backup.push(".corrupt");
+                if let Err(e) = fs::rename(path, &backup) {
+                    warn!("Failed to move corrupt high score file aside: {}", e);
+                }
+                HighScoreTable::new()
+            }
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        storage::write(path, &self.encode())
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(HEADER_SIZE + self.entries.len() * ENTRY_SIZE + CHECKSUM_SIZE);
+        data.extend_from_slice(MAGIC);
+        data.extend_from_slice(&VERSION.to_le_bytes());
+        data.push(self.entries.len() as u8);
+        for entry in self.entries.iter() {
+            data.extend_from_slice(&entry.initials);
+            data.extend_from_slice(&entry.score.to_le_bytes());
+        }
+        let checksum = storage::fnv1a(&data);
+        data.extend_from_slice(&checksum.to_le_bytes());
+        data
+    }
+
+    pub fn decode(data: &[u8]) -> Result<HighScoreTable, Error> {
+        if data.len() < HEADER_SIZE + CHECKSUM_SIZE {
+            return Err(err_msg("File is truncated"));
+        }
+
+        if &data[0..4] != MAGIC {
+            return Err(err_msg("Invalid magic"));
+        }
+
+        let version = u16::from_le_bytes([data[4], data[5]]);
+        if version != VERSION {
+            return Err(err_msg(format!("Unsupported version {}", version)));
+        }
+
+        let count = data[6] as usize;
+        if count > NUM_ENTRIES {
+            return Err(err_msg(format!("Too many entries {}", count)));
+        }
+
+        let body_size = HEADER_SIZE + count * ENTRY_SIZE;
+        if data.len() != body_size + CHECKSUM_SIZE {
+            return Err(err_msg("File size doesn't match entry count"));
+        }
+
+        let (body, checksum) = data.split_at(body_size);
+        let checksum = u32::from_le_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]);
+        if storage::fnv1a(body) != checksum {
+            return Err(err_msg("Checksum mismatch"));
+        }
+
+        let mut entries = Vec::with_capacity(count);
+        for chunk in body[HEADER_SIZE..].chunks(ENTRY_SIZE) {
+            let initials = [chunk[0], chunk[1], chunk[2]];
+            if !is_valid_initials(&initials) {
+                return Err(err_msg("Invalid initials"));
+            }
+            let score = u32::from_le_bytes([chunk[3], chunk[4], chunk[5], chunk[6]]);
+            if entries.last().is_some_and(|last: &HighScoreEntry| last.score < score) {
+                return Err(err_msg("Entries are not sorted"));
+            }
+            entries.push(HighScoreEntry { initials, score });
+        }
+
+        Ok(HighScoreTable { entries })
+    }
+
+    pub fn entries(&self) -> &[HighScoreEntry] {
+        &self.entries
+    }
+
+    pub fn high_score(&self) -> u32 {
+        self.entries.first().map_or(0, |entry| entry.score)
+    }
+
+    // The lowest score that makes the table.
+    pub fn qualifying_score(&self) -> u32 {
+        match self.entries.last() {
+            Some(last) if self.entries.len() >= NUM_ENTRIES => last.score.saturating_add(1),
+            _ => 1,
+        }
+    }
+
+    pub fn qualifies(&self, score: u32) -> bool {
+        score >= self.qualifying_score()
+    }
+
+    // Returns the rank of the new entry, starting at 0, if it made the table.
+    pub fn insert(&mut self, initials: [u8; 3], score: u32) -> Option<usize> {
+        if !self.qualifies(score) || !is_valid_initials(&initials) {
+            return None;
+        }
+
+        let rank = self
+            .entries
+            .iter()
+            .position(|entry| score > entry.score)
+            .unwrap_or(self.entries.len());
+        self.entries.insert(rank, HighScoreEntry { initials, score });
+        self.entries.truncate(NUM_ENTRIES);
+        Some(rank)
+    }
+}
+
+fn is_valid_initials(initials: &[u8; 3]) -> bool {
+    initials.iter().all(|c| LETTERS.contains(c))
+}
+
+// Arcade style initials entry: up/down cycles the current letter, confirm
+// moves on to the next one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InitialsEntry {
+    letters: [usize; 3],
+    cursor: usize,
+}
+
+impl InitialsEntry {
+    pub fn new() -> InitialsEntry {
+        InitialsEntry {
+            letters: [0; 3],
+            cursor: 0,
+        }
+    }
+
+    pub fn next_letter(&mut self) {
+        if let Some(letter) = self.letters.get_mut(self.cursor) {
+            *letter = (*letter + 1) % LETTERS.len();
+        }
+    }
+
+    pub fn prev_letter(&mut self) {
+        if let Some(letter) = self.letters.get_mut(self.cursor) {
+            *letter = (*letter + LETTERS.len() - 1) % LETTERS.len();
+        }
+    }
+
+    // Returns true once all three letters are confirmed.
+    pub fn confirm(&mut self) -> bool {
+        if self.cursor < self.letters.len() {
+            self.cursor += 1;
+        }
+        self.is_done()
+    }
+
+    pub fn back(&mut self) {
+        self.cursor = self.cursor.saturating_sub(1);
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.cursor == self.letters.len()
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn initials(&self) -> [u8; 3] {
+        [
+            LETTERS[self.letters[0]],
+            LETTERS[self.letters[1]],
+            LETTERS[self.letters[2]],
+        ]
+    }
+}
+
+impl Default for InitialsEntry {
+    fn default() -> InitialsEntry {
+        InitialsEntry::new()
+    }
+}
+
+impl Encode for InitialsEntry {
+    fn encode(&self, w: &mut StateWriter) {
+        for &letter in self.letters.iter() {
+            w.write_u8(letter as u8);
+        }
+        w.write_u8(self.cursor as u8);
+    }
+}
+
+impl Decode for InitialsEntry {
+    fn decode(r: &mut StateReader) -> Result<InitialsEntry, Error> {
+        let mut letters = [0; 3];
+        for letter in letters.iter_mut() {
+            *letter = r.read_u8()? as usize;
+            if *letter >= LETTERS.len() {
+                return Err(err_msg(format!("Invalid letter {}", letter)));
+            }
+        }
+        let cursor = r.read_u8()? as usize;
+        if cursor > letters.len() {
+            return Err(err_msg(format!("Invalid cursor {}", cursor)));
+        }
+        Ok(InitialsEntry { letters, cursor })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(scores: &[u32]) -> HighScoreTable {
+        let mut table = HighScoreTable::new();
+        for &score in scores {
+            table.insert(*b"AAA", score);
+        }
+        table
+    }
+
+    #[test]
+    fn keeps_top_ten_sorted() {
+        let mut table = table(&[100, 900, 500, 300, 700, 200, 800, 400, 600, 1000]);
+        assert!(!table.qualifies(100));
+        assert_eq!(table.qualifying_score(), 101);
+        assert_eq!(table.insert(*b"BOB", 550), Some(5));
+        assert_eq!(table.qualifying_score(), 201);
+        assert_eq!(HighScoreTable::new().qualifying_score(), 1);
+
+        let scores = table.entries().iter().map(|entry| entry.score).collect::<Vec<_>>();
+        assert_eq!(scores, vec![1000, 900, 800, 700, 600, 550, 500, 400, 300, 200]);
+        assert_eq!(table.high_score(), 1000);
+    }
+
+    #[test]
+    fn round_trips_through_file_format() {
+        let table = table(&[1230, 450, 7890]);
+        assert_eq!(HighScoreTable::decode(&table.encode()).unwrap(), table);
+    }
+
+    #[test]
+    fn rejects_corrupt_data() {
+        let data = table(&[1230, 450]).encode();
+
+        let mut flipped = data.clone();
+        flipped[9] ^= 0xff;
+        assert!(HighScoreTable::decode(&flipped).is_err());
+
+        let mut future = data.clone();
+        future[4] = 2;
+        assert!(HighScoreTable::decode(&future).is_err());
+
+        assert!(HighScoreTable::decode(&data[..data.len() - 1]).is_err());
+        assert!(HighScoreTable::decode(b"garbage").is_err());
+    }
+
+    #[test]
+    fn moves_corrupt_file_aside() {
+        let path = ::std::env::temp_dir().join(format!("pacman-high-score-test-{}.dat", ::std::process::id()));
+        let mut backup = path.as_os_str().to_owned();
+        backup.push(".corrupt");
+        fs::write(&path, b"garbage").unwrap();
+
+        assert_eq!(HighScoreTable::load(&path), HighScoreTable::new());
+        assert!(!path.exists());
+        assert_eq!(fs::read(&backup).unwrap(), b"garbage");
+        fs::remove_file(&backup).unwrap();
+    }
+
+    #[test]
+    fn initials_entry_cycles_letters() {
+        let mut entry = InitialsEntry::new();
+        entry.prev_letter();
+        assert!(!entry.confirm());
+        entry.next_letter();
+        entry.next_letter();
+        assert!(!entry.confirm());
+        assert!(entry.confirm());
+        assert_eq!(&entry.initials(), b" CA");
+    }
+}
@@ -9,7 +9,7 @@ use std::os::raw::*;
 use failure::{err_msg, Error};
 use stb::image::*;
 
-fn base() -> PathBuf {
+pub fn base() -> PathBuf {
     // TODO(coeuvre): Other platform?
     std::env::current_exe()
         .unwrap()
@@ -151,6 +151,6 @@ impl StbImage {
 
 impl Drop for StbImage {
     fn drop(&mut self) {
-        unsafe { stbi_image_free(self.data as *mut u8) };
+        unsafe { stbi_image_free(self.data) };
     }
 }
@@ -0,0 +1,274 @@
+use std::str::FromStr;
+
+use failure::{err_msg, Error};
+
+use state::{Decode, Encode, StateReader, StateWriter};
+use Rgb;
+
+static ARCADE_LEVELS: &str = include_str!("../assets/levels.txt");
+
+const NUM_COLUMNS: usize = 14;
+
+// Levels without a colour of their own have the arcade's blue walls.
+pub const DEFAULT_WALL_COLOR: Rgb = Rgb(0x21, 0x21, 0xde);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fruit {
+    Cherries,
+    Strawberry,
+    Peach,
+    Apple,
+    Melon,
+    Galaxian,
+    Bell,
+    Key,
+}
+
+impl Fruit {
+    pub const ALL: [Fruit; 8] = [
+        Fruit::Cherries,
+        Fruit::Strawberry,
+        Fruit::Peach,
+        Fruit::Apple,
+        Fruit::Melon,
+        Fruit::Galaxian,
+        Fruit::Bell,
+        Fruit::Key,
+    ];
+}
+
+impl FromStr for Fruit {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Fruit, Error> {
+        match s {
+            "cherries" => Ok(Fruit::Cherries),
+            "strawberry" => Ok(Fruit::Strawberry),
+            "peach" => Ok(Fruit::Peach),
+            "apple" => Ok(Fruit::Apple),
+            "melon" => Ok(Fruit::Melon),
+            "galaxian" => Ok(Fruit::Galaxian),
+            "bell" => Ok(Fruit::Bell),
+            "key" => Ok(Fruit::Key),
+            _ => Err(err_msg(format!("Unknown fruit {}", s))),
+        }
+    }
+}
+
+// Speeds are percentages of Pac-Man's top speed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LevelSpec {
+    pub fruit: Fruit,
+    pub fruit_points: u32,
+    pub pacman_speed: u32,
+    pub pacman_fright_speed: u32,
+    pub ghost_speed: u32,
+    pub ghost_fright_speed: u32,
+    pub ghost_tunnel_speed: u32,
+    pub fright_seconds: u32,
+    pub fright_flashes: u32,
+    pub elroy1_dots_left: u32,
+    pub elroy1_speed: u32,
+    pub elroy2_dots_left: u32,
+    pub elroy2_speed: u32,
+    pub wall_color: Rgb,
+}
+
+impl LevelSpec {
+    fn parse(line_no: usize, columns: &[&str]) -> Result<LevelSpec, Error> {
+        if columns.len() != NUM_COLUMNS && columns.len() != NUM_COLUMNS + 1 {
+            return Err(err_msg(format!(
+                "Line {}: expected {} columns and maybe a wall colour, found {}",
+                line_no,
+                NUM_COLUMNS,
+                columns.len()
+            )));
+        }
+
+        let number = |index: usize| -> Result<u32, Error> {
+            columns[index].parse::<u32>().map_err(|e| {
+                err_msg(format!(
+                    "Line {}: invalid number {} in column {}: {}",
+                    line_no,
+                    columns[index],
+                    index + 1,
+                    e
+                ))
+            })
+        };
+
+        Ok(LevelSpec {
+            fruit: columns[1].parse()?,
+            fruit_points: number(2)?,
+            pacman_speed: number(3)?,
+            pacman_fright_speed: number(4)?,
+            ghost_speed: number(5)?,
+            ghost_fright_speed: number(6)?,
+            ghost_tunnel_speed: number(7)?,
+            fright_seconds: number(8)?,
+            fright_flashes: number(9)?,
+            elroy1_dots_left: number(10)?,
+            elroy1_speed: number(11)?,
+            elroy2_dots_left: number(12)?,
+            elroy2_speed: number(13)?,
+            wall_color: match columns.get(NUM_COLUMNS) {
+                Some(color) => color.parse().map_err(|e| err_msg(format!("Line {}: {}", line_no, e)))?,
+                None => DEFAULT_WALL_COLOR,
+            },
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LevelTable {
+    levels: Vec<LevelSpec>,
+}
+
+impl LevelTable {
+    pub fn arcade() -> LevelTable {
+        LevelTable::parse(ARCADE_LEVELS).expect("Invalid builtin level table")
+    }
+
+    pub fn parse(source: &str) -> Result<LevelTable, Error> {
+        let mut levels = Vec::new();
+
+        for (index, line) in source.lines().enumerate() {
+            let line_no = index + 1;
+            let line = match line.find('#') {
+                Some(comment) => &line[..comment],
+                None => line,
+            };
+            let columns = line.split_whitespace().collect::<Vec<_>>();
+            if columns.is_empty() {
+                continue;
+            }
+
+            let level = columns[0].parse::<usize>().ok();
+            if level != Some(levels.len() + 1) {
+                return Err(err_msg(format!(
+                    "Line {}: expected level {}, found {}",
+                    line_no,
+                    levels.len() + 1,
+                    columns[0]
+                )));
+            }
+
+            levels.push(LevelSpec::parse(line_no, &columns)?);
+        }
+
+        if levels.is_empty() {
+            return Err(err_msg("Level table is empty"));
+        }
+
+        Ok(LevelTable { levels })
+    }
+
+    // Levels start at 1. Levels past the end of the table reuse the last row.
+    pub fn get(&self, level: u32) -> &LevelSpec {
+        let index = (level.max(1) as usize - 1).min(self.levels.len() - 1);
+        &self.levels[index]
+    }
+
+    pub fn len(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.levels.is_empty()
+    }
+}
+
+impl Encode for Fruit {
+    fn encode(&self, w: &mut StateWriter) {
+        w.write_u8(*self as u8);
+    }
+}
+
+impl Decode for Fruit {
+    fn decode(r: &mut StateReader) -> Result<Fruit, Error> {
+        let value = r.read_u8()?;
+        Fruit::ALL
+            .get(value as usize)
+            .cloned()
+            .ok_or_else(|| err_msg(format!("Invalid fruit {}", value)))
+    }
+}
+
+// Tables aren't part of a save state, this is only for comparing them.
+impl Encode for LevelSpec {
+    fn encode(&self, w: &mut StateWriter) {
+        w.write(&self.fruit);
+        for &value in [
+            self.fruit_points,
+            self.pacman_speed,
+            self.pacman_fright_speed,
+            self.ghost_speed,
+            self.ghost_fright_speed,
+            self.ghost_tunnel_speed,
+            self.fright_seconds,
+            self.fright_flashes,
+            self.elroy1_dots_left,
+            self.elroy1_speed,
+            self.elroy2_dots_left,
+            self.elroy2_speed,
+        ]
+        .iter()
+        {
+            w.write_u32(value);
+        }
+        let Rgb(r, g, b) = self.wall_color;
+        w.write_u8(r);
+        w.write_u8(g);
+        w.write_u8(b);
+    }
+}
+
+impl Encode for LevelTable {
+    fn encode(&self, w: &mut StateWriter) {
+        w.write_u32(self.levels.len() as u32);
+        for level in self.levels.iter() {
+            w.write(level);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEVEL_1: &str = "1 cherries 100 80 90 75 50 40 6 5 20 80 10 85";
+
+    #[test]
+    fn parses_the_arcade_table() {
+        let table = LevelTable::arcade();
+        let first = table.get(1);
+        assert_eq!(first.fruit, Fruit::Cherries);
+        assert_eq!(first.wall_color, DEFAULT_WALL_COLOR);
+        assert_eq!(LevelTable::parse(LEVEL_1).unwrap().get(1).fright_seconds, 6);
+
+        let colored = LevelTable::parse(&format!("{} ff0000", LEVEL_1)).unwrap();
+        assert_eq!(colored.get(1).wall_color, Rgb(0xff, 0, 0));
+    }
+
+    #[test]
+    fn rejects_bad_tables() {
+        // Too few columns, too many, and a bad wall colour.
+        assert!(LevelTable::parse("1 cherries 100 80").is_err());
+        assert!(LevelTable::parse(&format!("{} ff0000 extra", LEVEL_1)).is_err());
+        assert!(LevelTable::parse(&format!("{} blue", LEVEL_1)).is_err());
+        // Bad values.
+        assert!(LevelTable::parse(&LEVEL_1.replace("cherries", "banana")).is_err());
+        assert!(LevelTable::parse(&LEVEL_1.replace(" 100 ", " -100 ")).is_err());
+        assert!(LevelTable::parse(&LEVEL_1.replace("1 cherries", "2 cherries")).is_err());
+        assert!(LevelTable::parse("# nothing but a comment\n").is_err());
+    }
+
+    #[test]
+    fn levels_past_the_end_reuse_the_last() {
+        let table = LevelTable::parse(&format!("{}\n{}", LEVEL_1, LEVEL_1.replacen("1", "2", 1))).unwrap();
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.get(0), table.get(1));
+        assert_eq!(table.get(99), table.get(2));
+        assert_eq!(LevelTable::arcade().get(1000), LevelTable::arcade().get(LevelTable::arcade().len() as u32));
+    }
+}
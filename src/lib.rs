@@ -10,39 +10,158 @@ extern crate stb;
 
 pub mod image;
 pub mod gl_context;
+pub mod level;
+pub mod event;
+pub mod score;
+pub mod storage;
+pub mod high_score;
+pub mod maze;
+pub mod rng;
+pub mod ghost;
+pub mod world;
+pub mod phase;
+pub mod canvas;
+pub mod replay;
+pub mod state;
+pub mod simulation;
+pub mod gym;
+pub mod autoplay;
+pub mod pathfinding;
+pub mod actor;
+pub mod collision;
+pub mod fruit;
+pub mod multiplayer;
+pub mod netplay;
+pub mod stream;
+pub mod editor;
+pub mod mazegen;
+pub mod walls;
+pub mod tilemap;
+pub mod shapes;
+pub mod overlay;
 
+use std::cell::RefCell;
+use std::env;
 use std::ffi::{CStr, CString};
-use std::path::Path;
+use std::ops::BitOr;
+use std::str::FromStr;
+use std::ptr;
+use std::path::{Path, PathBuf};
+use std::fs::File;
+use std::io::Read;
 use std::os::raw::*;
 use gl::types::*;
-use bridge::PlatformEvent;
+use bridge::{Key, MouseButton, PlatformEvent};
 use failure::{err_msg, Error};
 use image::Image;
 use gl_context::{GlContext, GlCapability};
+use level::LevelTable;
+use high_score::HighScoreTable;
+use maze::{Direction, Maze};
+use multiplayer::{Mode, Party, MAX_PLAYERS};
+use netplay::{Rollback, Session, UdpTransport};
+use stream::{StreamClient, StreamServer};
+use world::World;
+use autoplay::{Bot, InputSource, Keyboard, ReplayInput};
+use pathfinding::FlowField;
+use simulation::Simulation;
+use canvas::{Canvas, MazeLayers};
+use overlay::DebugOverlay;
+use tilemap::{Tilemap, FLOATS_PER_TILE};
+use replay::Replay;
+use editor::Editor;
+use event::Event;
 
 static VERTEX_SHADER: &str = r#"
 #version 330 core
 layout (location = 0) in vec3 a_pos;
 layout (location = 1) in vec2 a_tex_coord;
 
+uniform vec2 u_view_size;
+uniform vec2 u_texture_size;
+// x, y, width, height in pixels, y pointing down.
+uniform vec4 u_dst;
+uniform vec4 u_src;
+
+out vec2 tex_coord;
+
+void main() {
+    vec2 corner = vec2(a_tex_coord.x, 1.0 - a_tex_coord.y);
+    vec2 pos = (u_dst.xy + corner * u_dst.zw) / u_view_size * 2.0 - 1.0;
+    gl_Position = vec4(pos.x, -pos.y, a_pos.z, 1.0);
+    tex_coord = (u_src.xy + corner * u_src.zw) / u_texture_size;
+}
+"#;
+
+// Tilemap layers have their corners in the vertices instead of uniforms.
+static TILEMAP_VERTEX_SHADER: &str = r#"
+#version 330 core
+layout (location = 0) in vec2 a_pos;
+layout (location = 1) in vec2 a_tex_coord;
+
+uniform vec2 u_view_size;
+uniform vec2 u_texture_size;
+// Where the layer's top left goes, in pixels.
+uniform vec2 u_offset;
+
 out vec2 tex_coord;
 
 void main() {
-    gl_Position = vec4(a_pos.x, a_pos.y, a_pos.z, 1.0);
-    tex_coord = a_tex_coord;
+    vec2 pos = (a_pos + u_offset) / u_view_size * 2.0 - 1.0;
+    gl_Position = vec4(pos.x, -pos.y, 0.0, 1.0);
+    tex_coord = a_tex_coord / u_texture_size;
+}
+"#;
+
+// Shapes are untextured triangles in view pixels, with a linear colour for
+// each corner so shapes of every colour can be drawn together.
+static SHAPE_VERTEX_SHADER: &str = r#"
+#version 330 core
+layout (location = 0) in vec2 a_pos;
+layout (location = 1) in vec3 a_color;
+
+uniform vec2 u_view_size;
+
+out vec3 color;
+
+void main() {
+    vec2 pos = a_pos / u_view_size * 2.0 - 1.0;
+    gl_Position = vec4(pos.x, -pos.y, 0.0, 1.0);
+    color = a_color;
+}
+"#;
+
+static SHAPE_FRAGMENT_SHADER: &str = r#"
+#version 330 core
+in vec3 color;
+
+out vec4 frag_color;
+
+void main() {
+    frag_color = vec4(color, 1.0);
 }
 "#;
 
+// A corner from `shapes` followed by its colour.
+const SHAPE_FLOATS_PER_VERTEX: usize = shapes::FLOATS_PER_VERTEX + 3;
+
 static FRAGMENT_SHADER: &str = r#"
 #version 330 core
 
 uniform sampler2D texture0;
+// Multiplies the texture, white leaves it as it is.
+uniform vec3 u_tint;
 
 in vec2 tex_coord;
 out vec4 frag_color;
 
 void main() {
-    frag_color = texture(texture0, tex_coord);
+    vec4 color = texture(texture0, tex_coord);
+    // The sprite sheet has no alpha channel, black is transparent.
+    if (color.r + color.g + color.b < 0.05) {
+        discard;
+    }
+    frag_color = vec4(color.rgb * u_tint, color.a);
 }
 "#;
 
@@ -55,7 +174,7 @@ pub enum GlShader {
 impl GlShader {
     unsafe fn compile_shader(kind: GLenum, source: *const GLchar) -> Result<GLuint, Error> {
         let shader = gl::CreateShader(kind);
-        gl::ShaderSource(shader, 1, &source, 0 as *const GLint);
+        gl::ShaderSource(shader, 1, &source, ptr::null());
         gl::CompileShader(shader);
         let mut success = 0;
         gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut success);
@@ -64,8 +183,8 @@ impl GlShader {
         } else {
             gl::DeleteShader(shader);
             let mut log = ['\0'; 512];
-            let ptr = (&mut log[..]).as_mut_ptr() as *mut GLchar;
-            gl::GetShaderInfoLog(shader, 512, 0 as *mut GLsizei, ptr);
+            let ptr = log[..].as_mut_ptr() as *mut GLchar;
+            gl::GetShaderInfoLog(shader, 512, ptr::null_mut(), ptr);
             Err(err_msg(format!(
                 "Failed to compile shader {}",
                 CStr::from_ptr(ptr).to_str().unwrap()
@@ -124,8 +243,8 @@ impl GlProgram {
                 Ok(GlProgram(program))
             } else {
                 let mut log = ['\0'; 512];
-                let ptr = (&mut log[..]).as_mut_ptr() as *mut GLchar;
-                gl::GetProgramInfoLog(program, 512, 0 as *mut GLsizei, ptr);
+                let ptr = log[..].as_mut_ptr() as *mut GLchar;
+                gl::GetProgramInfoLog(program, 512, ptr::null_mut(), ptr);
                 Err(err_msg(format!(
                     "Failed to compile shader {}",
                     CStr::from_ptr(ptr).to_str().unwrap()
@@ -139,6 +258,15 @@ impl GlProgram {
             gl::UseProgram(self.0);
         }
     }
+
+    pub fn uniform_location(&self, name: &str) -> Result<GLint, Error> {
+        let cstring = CString::new(name)?;
+        let location = unsafe { gl::GetUniformLocation(self.0, cstring.as_ptr() as *const GLchar) };
+        if location < 0 {
+            return Err(err_msg(format!("Invalid uniform {}", name)));
+        }
+        Ok(location)
+    }
 }
 
 impl Drop for GlProgram {
@@ -153,11 +281,72 @@ pub struct GlTexture {
     id: GLuint
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub w: i32,
+    pub h: i32,
+}
+
+impl Rect {
+    pub const fn new(x: i32, y: i32, w: i32, h: i32) -> Rect {
+        Rect { x, y, w, h }
+    }
+}
+
+// A colour as it appears on screen, written as six hex digits like "2121de".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl Rgb {
+    pub const WHITE: Rgb = Rgb(255, 255, 255);
+
+    // The framebuffer converts to sRGB on the way out, so colours go to the
+    // shaders linear.
+    fn to_linear(self) -> [f32; 3] {
+        let channel = |c: u8| (c as f32 / 255.0).powf(2.2);
+        [channel(self.0), channel(self.1), channel(self.2)]
+    }
+}
+
+impl FromStr for Rgb {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Rgb, Error> {
+        let invalid = || err_msg(format!("Invalid colour {}, expected six hex digits", s));
+        if s.len() != 6 || !s.is_ascii() {
+            return Err(invalid());
+        }
+        let channel = |i: usize| u8::from_str_radix(&s[i..i + 2], 16).map_err(|_| invalid());
+        Ok(Rgb(channel(0)?, channel(2)?, channel(4)?))
+    }
+}
+
 pub struct GlRenderer {
     program: GlProgram,
     vao: GLuint,
     vbo: GLuint,
     ebo: GLuint,
+    view_size: (i32, i32),
+    u_view_size: GLint,
+    u_texture_size: GLint,
+    u_dst: GLint,
+    u_src: GLint,
+    u_tint: GLint,
+    tilemap_program: GlProgram,
+    u_tilemap_view_size: GLint,
+    u_tilemap_texture_size: GLint,
+    u_tilemap_offset: GLint,
+    u_tilemap_tint: GLint,
+    shape_program: GlProgram,
+    shape_vao: GLuint,
+    // Refilled for every flush.
+    shape_vbo: GLuint,
+    u_shape_view_size: GLint,
+    // Shapes waiting to be drawn, see `flush_shapes`.
+    shape_batch: Vec<f32>,
+    shape_corners: Vec<f32>,
 }
 
 impl GlRenderer {
@@ -207,6 +396,30 @@ impl GlRenderer {
             GlShader::compile_fragment_shader(FRAGMENT_SHADER)?,
         ];
         let program = GlProgram::link(&shaders)?;
+        let u_view_size = program.uniform_location("u_view_size")?;
+        let u_texture_size = program.uniform_location("u_texture_size")?;
+        let u_dst = program.uniform_location("u_dst")?;
+        let u_src = program.uniform_location("u_src")?;
+        let u_tint = program.uniform_location("u_tint")?;
+
+        let shaders = [
+            GlShader::compile_vertex_shader(TILEMAP_VERTEX_SHADER)?,
+            GlShader::compile_fragment_shader(FRAGMENT_SHADER)?,
+        ];
+        let tilemap_program = GlProgram::link(&shaders)?;
+        let u_tilemap_view_size = tilemap_program.uniform_location("u_view_size")?;
+        let u_tilemap_texture_size = tilemap_program.uniform_location("u_texture_size")?;
+        let u_tilemap_offset = tilemap_program.uniform_location("u_offset")?;
+        let u_tilemap_tint = tilemap_program.uniform_location("u_tint")?;
+
+        let shaders = [
+            GlShader::compile_vertex_shader(SHAPE_VERTEX_SHADER)?,
+            GlShader::compile_fragment_shader(SHAPE_FRAGMENT_SHADER)?,
+        ];
+        let shape_program = GlProgram::link(&shaders)?;
+        let u_shape_view_size = shape_program.uniform_location("u_view_size")?;
+        let mut shape_vao = 0;
+        let mut shape_vbo = 0;
 
         unsafe {
             gl::GenVertexArrays(1, &mut vao);
@@ -236,7 +449,7 @@ impl GlRenderer {
                 gl::FLOAT,
                 gl::FALSE,
                 (5 * std::mem::size_of::<GLfloat>()) as i32,
-                0 as *const c_void,
+                ptr::null(),
             );
             gl::EnableVertexAttribArray(0);
 
@@ -252,6 +465,30 @@ impl GlRenderer {
 
             gl::BindVertexArray(0);
 
+            gl::GenVertexArrays(1, &mut shape_vao);
+            gl::BindVertexArray(shape_vao);
+            gl::GenBuffers(1, &mut shape_vbo);
+            gl::BindBuffer(gl::ARRAY_BUFFER, shape_vbo);
+            gl::VertexAttribPointer(
+                0,
+                shapes::FLOATS_PER_VERTEX as GLint,
+                gl::FLOAT,
+                gl::FALSE,
+                (SHAPE_FLOATS_PER_VERTEX * std::mem::size_of::<GLfloat>()) as i32,
+                ptr::null(),
+            );
+            gl::EnableVertexAttribArray(0);
+            gl::VertexAttribPointer(
+                1,
+                3,
+                gl::FLOAT,
+                gl::FALSE,
+                (SHAPE_FLOATS_PER_VERTEX * std::mem::size_of::<GLfloat>()) as i32,
+                (shapes::FLOATS_PER_VERTEX * std::mem::size_of::<GLfloat>()) as *const c_void,
+            );
+            gl::EnableVertexAttribArray(1);
+            gl::BindVertexArray(0);
+
             // gl::PolygonMode(gl::FRONT_AND_BACK, gl::LINE);
         }
 
@@ -260,10 +497,28 @@ impl GlRenderer {
             vao,
             vbo,
             ebo,
+            view_size: (1, 1),
+            u_view_size,
+            u_texture_size,
+            u_dst,
+            u_src,
+            u_tint,
+            tilemap_program,
+            u_tilemap_view_size,
+            u_tilemap_texture_size,
+            u_tilemap_offset,
+            u_tilemap_tint,
+            shape_program,
+            shape_vao,
+            shape_vbo,
+            u_shape_view_size,
+            shape_batch: Vec::new(),
+            shape_corners: Vec::new(),
         })
     }
 
     pub fn swap_buffers(&mut self) -> Result<(), Error> {
+        self.flush_shapes()?;
         unsafe {
             bridge::swap_gl_buffers();
         }
@@ -271,6 +526,8 @@ impl GlRenderer {
     }
 
     pub fn clear(&mut self, r: f32, g: f32, b: f32, a: f32) -> Result<(), Error> {
+        // Shapes from before would only be cleared away.
+        self.shape_batch.clear();
         unsafe {
             gl::ClearColor(r, g, b, a);
             gl::Clear(gl::COLOR_BUFFER_BIT);
@@ -278,19 +535,269 @@ impl GlRenderer {
         Ok(())
     }
 
+    // The size of the virtual screen that destination rects are given in.
+    pub fn set_view_size(&mut self, width: i32, height: i32) {
+        // Batched shapes are in the old view's pixels.
+        if let Err(e) = self.flush_shapes() {
+            warn!("Failed to draw shapes: {}", e);
+        }
+        self.view_size = (width, height);
+    }
+
+    // Turns a window pixel into one on the virtual screen.
+    pub fn window_to_view(&self, x: i32, y: i32) -> (i32, i32) {
+        let mut viewport = [0; 4];
+        unsafe {
+            gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+        }
+        let (width, height) = (viewport[2].max(1), viewport[3].max(1));
+        ((x - viewport[0]) * self.view_size.0 / width, (y - viewport[1]) * self.view_size.1 / height)
+    }
+
     pub fn render_texture(&mut self, texture: &Texture) -> Result<(), Error> {
+        let (width, height) = self.view_size;
+        let src = Rect::new(0, 0, texture.width, texture.height);
+        self.render_sprite(texture, src, Rect::new(0, 0, width, height))
+    }
+
+    pub fn render_sprite(&mut self, texture: &Texture, src: Rect, dst: Rect) -> Result<(), Error> {
+        self.render_sprite_tinted(texture, src, dst, Rgb::WHITE)
+    }
+
+    // Draws a sprite with its colours multiplied by `tint`, for sprites
+    // drawn in white to be any colour.
+    pub fn render_sprite_tinted(&mut self, texture: &Texture, src: Rect, dst: Rect, tint: Rgb) -> Result<(), Error> {
+        self.flush_shapes()?;
         self.program.active();
 
+        let [r, g, b] = tint.to_linear();
         unsafe {
-//            let texture = (&*self.assets).get_texture(texture_id).ok_or_else(|| err_msg(format!("Invalid texture id {}", texture_id)))?;
+            gl::Uniform3f(self.u_tint, r, g, b);
+            gl::Uniform2f(self.u_view_size, self.view_size.0 as f32, self.view_size.1 as f32);
+            gl::Uniform2f(self.u_texture_size, texture.width as f32, texture.height as f32);
+            gl::Uniform4f(self.u_dst, dst.x as f32, dst.y as f32, dst.w as f32, dst.h as f32);
+            gl::Uniform4f(self.u_src, src.x as f32, src.y as f32, src.w as f32, src.h as f32);
             gl::ActiveTexture(gl::TEXTURE0);
             gl::BindTexture(gl::TEXTURE_2D, texture.gl_texture.id);
             gl::BindVertexArray(self.vao);
-            gl::DrawElements(gl::TRIANGLES, 6, gl::UNSIGNED_INT, 0 as *const c_void);
+            gl::DrawElements(gl::TRIANGLES, 6, gl::UNSIGNED_INT, ptr::null());
+            gl::BindVertexArray(0);
+        }
+        Ok(())
+    }
+}
+
+impl GlRenderer {
+    // Draws a whole layer with its top left at `x`, `y` in one call, after
+    // uploading whatever tiles changed since it was last drawn.
+    pub fn render_tilemap(&mut self, layer: &mut TilemapLayer, texture: &Texture, x: i32, y: i32, tint: Rgb) -> Result<(), Error> {
+        self.flush_shapes()?;
+        layer.upload();
+        self.tilemap_program.active();
+
+        let [r, g, b] = tint.to_linear();
+        unsafe {
+            gl::Uniform2f(self.u_tilemap_view_size, self.view_size.0 as f32, self.view_size.1 as f32);
+            gl::Uniform2f(self.u_tilemap_texture_size, texture.width as f32, texture.height as f32);
+            gl::Uniform2f(self.u_tilemap_offset, x as f32, y as f32);
+            gl::Uniform3f(self.u_tilemap_tint, r, g, b);
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::BindTexture(gl::TEXTURE_2D, texture.gl_texture.id);
+            gl::BindVertexArray(layer.vao);
+            gl::DrawElements(gl::TRIANGLES, layer.index_count, gl::UNSIGNED_INT, ptr::null());
+            gl::BindVertexArray(0);
+        }
+        Ok(())
+    }
+}
+
+impl GlRenderer {
+    // Adds triangles from `shapes`, in view pixels, to the shape batch.
+    pub fn render_triangles(&mut self, vertices: &[f32], color: Rgb) -> Result<(), Error> {
+        let [r, g, b] = color.to_linear();
+        for corner in vertices.chunks(shapes::FLOATS_PER_VERTEX) {
+            self.shape_batch.extend_from_slice(corner);
+            self.shape_batch.extend_from_slice(&[r, g, b]);
+        }
+        Ok(())
+    }
+
+    // Draws every shape batched since the last flush in one call. Drawing
+    // anything else, changing the view size and swapping buffers flush
+    // first, so shapes still end up in the order they were drawn in.
+    pub fn flush_shapes(&mut self) -> Result<(), Error> {
+        if self.shape_batch.is_empty() {
+            return Ok(());
+        }
+        self.shape_program.active();
+
+        unsafe {
+            gl::Uniform2f(self.u_shape_view_size, self.view_size.0 as f32, self.view_size.1 as f32);
+            gl::BindVertexArray(self.shape_vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.shape_vbo);
+            gl::BufferData(
+                gl::ARRAY_BUFFER,
+                std::mem::size_of_val(&self.shape_batch[..]) as GLsizeiptr,
+                self.shape_batch.as_ptr() as *const c_void,
+                gl::STREAM_DRAW,
+            );
+            gl::DrawArrays(gl::TRIANGLES, 0, (self.shape_batch.len() / SHAPE_FLOATS_PER_VERTEX) as GLsizei);
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
             gl::BindVertexArray(0);
         }
+        self.shape_batch.clear();
         Ok(())
     }
+
+    // Batches the corners `add` makes, reusing one buffer for them.
+    fn add_shape<F: FnOnce(&mut Vec<f32>)>(&mut self, color: Rgb, add: F) -> Result<(), Error> {
+        let mut corners = std::mem::take(&mut self.shape_corners);
+        corners.clear();
+        add(&mut corners);
+        let result = self.render_triangles(&corners, color);
+        self.shape_corners = corners;
+        result
+    }
+
+    pub fn render_line(&mut self, from: (f32, f32), to: (f32, f32), thickness: f32, color: Rgb) -> Result<(), Error> {
+        self.add_shape(color, |vertices| shapes::line(vertices, from, to, thickness))
+    }
+
+    pub fn render_polyline(&mut self, points: &[(f32, f32)], thickness: f32, color: Rgb) -> Result<(), Error> {
+        self.add_shape(color, |vertices| shapes::polyline(vertices, points, thickness))
+    }
+
+    pub fn render_rect(&mut self, rect: Rect, color: Rgb) -> Result<(), Error> {
+        self.add_shape(color, |vertices| shapes::rect(vertices, rect))
+    }
+
+    pub fn render_rect_outline(&mut self, rect: Rect, thickness: i32, color: Rgb) -> Result<(), Error> {
+        self.add_shape(color, |vertices| shapes::rect_outline(vertices, rect, thickness))
+    }
+
+    pub fn render_circle(&mut self, center: (f32, f32), radius: f32, color: Rgb) -> Result<(), Error> {
+        self.add_shape(color, |vertices| shapes::circle(vertices, center, radius))
+    }
+
+    pub fn render_circle_outline(&mut self, center: (f32, f32), radius: f32, thickness: f32, color: Rgb) -> Result<(), Error> {
+        self.add_shape(color, |vertices| shapes::circle_outline(vertices, center, radius, thickness))
+    }
+}
+
+// A tilemap kept on the GPU. The vertex buffer is made once and only the
+// tiles that change are sent again.
+pub struct TilemapLayer {
+    map: Tilemap,
+    vao: GLuint,
+    vbo: GLuint,
+    ebo: GLuint,
+    index_count: GLsizei,
+}
+
+impl TilemapLayer {
+    pub fn new(map: Tilemap) -> TilemapLayer {
+        let (mut vao, mut vbo, mut ebo) = (0, 0, 0);
+        let indices = map.indices();
+        let stride = (4 * std::mem::size_of::<GLfloat>()) as GLsizei;
+        unsafe {
+            gl::GenVertexArrays(1, &mut vao);
+            gl::BindVertexArray(vao);
+
+            gl::GenBuffers(1, &mut vbo);
+            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+            gl::BufferData(
+                gl::ARRAY_BUFFER,
+                std::mem::size_of_val(map.vertices()) as GLsizeiptr,
+                map.vertices().as_ptr() as *const c_void,
+                gl::DYNAMIC_DRAW,
+            );
+
+            gl::GenBuffers(1, &mut ebo);
+            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, ebo);
+            gl::BufferData(
+                gl::ELEMENT_ARRAY_BUFFER,
+                std::mem::size_of_val(&indices[..]) as GLsizeiptr,
+                indices.as_ptr() as *const c_void,
+                gl::STATIC_DRAW,
+            );
+
+            gl::VertexAttribPointer(0, 2, gl::FLOAT, gl::FALSE, stride, ptr::null());
+            gl::EnableVertexAttribArray(0);
+            gl::VertexAttribPointer(
+                1,
+                2,
+                gl::FLOAT,
+                gl::FALSE,
+                stride,
+                (2 * std::mem::size_of::<GLfloat>()) as *const c_void,
+            );
+            gl::EnableVertexAttribArray(1);
+
+            gl::BindVertexArray(0);
+        }
+
+        let mut map = map;
+        map.clear_dirty();
+        TilemapLayer {
+            map,
+            vao,
+            vbo,
+            ebo,
+            index_count: indices.len() as GLsizei,
+        }
+    }
+
+    pub fn map(&self) -> &Tilemap {
+        &self.map
+    }
+
+    pub fn set(&mut self, x: i32, y: i32, src: Option<Rect>) {
+        self.map.set(x, y, src);
+    }
+
+    fn upload(&mut self) {
+        let runs = self.map.take_dirty();
+        if runs.is_empty() {
+            return;
+        }
+        let size = std::mem::size_of::<GLfloat>() * FLOATS_PER_TILE;
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+            for run in runs {
+                let vertices = &self.map.vertices()[run.start * FLOATS_PER_TILE..run.end * FLOATS_PER_TILE];
+                gl::BufferSubData(
+                    gl::ARRAY_BUFFER,
+                    (run.start * size) as GLintptr,
+                    (run.len() * size) as GLsizeiptr,
+                    vertices.as_ptr() as *const c_void,
+                );
+            }
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        }
+    }
+}
+
+impl Drop for TilemapLayer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.vao);
+            gl::DeleteBuffers(1, &self.vbo);
+            gl::DeleteBuffers(1, &self.ebo);
+        }
+    }
+}
+
+impl Drop for GlRenderer {
+    fn drop(&mut self) {
+        trace!("Drop GlRenderer");
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.vao);
+            gl::DeleteBuffers(1, &self.vbo);
+            gl::DeleteBuffers(1, &self.ebo);
+            gl::DeleteVertexArrays(1, &self.shape_vao);
+            gl::DeleteBuffers(1, &self.shape_vbo);
+        }
+    }
 }
 
 pub type Renderer = GlRenderer;
@@ -299,9 +806,29 @@ pub type Renderer = GlRenderer;
 pub struct Texture {
     id: usize,
     name: String,
+    width: i32,
+    height: i32,
     gl_texture: GlTexture,
 }
 
+impl Texture {
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+}
+
 pub struct Assets {
     textures: Vec<Texture>,
 }
@@ -316,7 +843,19 @@ impl Assets {
     pub fn load_texture<P: AsRef<Path>>(&mut self, path: P) -> Result<usize, Error> {
         let name = path.as_ref().to_str().ok_or_else(|| err_msg(format!("Invalid path {}", path.as_ref().display())))?.to_string();
         let image = Image::load(path)?;
+        let pixels = image.data().as_rgba8_ptr()?;
+        Ok(self.add_texture(name, image.width() as i32, image.height() as i32, pixels))
+    }
 
+    // Makes a texture out of RGBA pixels drawn by the game itself.
+    pub fn create_texture(&mut self, name: &str, width: i32, height: i32, rgba: &[u8]) -> Result<usize, Error> {
+        if rgba.len() != (width * height * 4) as usize {
+            return Err(err_msg(format!("Texture {} should be {}x{} pixels", name, width, height)));
+        }
+        Ok(self.add_texture(name.to_string(), width, height, rgba.as_ptr()))
+    }
+
+    fn add_texture(&mut self, name: String, width: i32, height: i32, pixels: *const u8) -> usize {
         let mut texture = 0;
         unsafe {
             gl::GenTextures(1, &mut texture);
@@ -329,89 +868,552 @@ impl Assets {
                 gl::TEXTURE_2D,
                 0,
                 gl::SRGB_ALPHA as i32,
-                image.width() as i32,
-                image.height() as i32,
+                width,
+                height,
                 0,
                 gl::RGBA,
                 gl::UNSIGNED_BYTE,
-                image.data().as_rgba8_ptr().unwrap() as *const c_void,
+                pixels as *const c_void,
             );
         }
         let id = self.textures.len();
         let result = Texture {
             id,
             name,
+            width,
+            height,
             gl_texture: GlTexture { id: texture }
         };
         self.textures.push(result);
-        Ok(id)
+        id
     }
 
     pub fn get_texture(&self, id: usize) -> Option<&Texture> {
         self.textures.get(id)
     }
+
+    pub fn load_level_table<P: AsRef<Path>>(&mut self, path: P) -> Result<LevelTable, Error> {
+        let path = image::base().join(path);
+        trace!("Loading level table {}", path.display());
+
+        let mut source = String::new();
+        File::open(&path)?.read_to_string(&mut source)?;
+
+        LevelTable::parse(&source).map_err(|e| err_msg(format!("{}: {}", path.display(), e)))
+    }
+
+    pub fn load_maze<P: AsRef<Path>>(&mut self, path: P) -> Result<Maze, Error> {
+        let path = image::base().join(path);
+        trace!("Loading maze {}", path.display());
+
+        let mut source = String::new();
+        File::open(&path)?.read_to_string(&mut source)?;
+
+        Maze::parse(&source).map_err(|e| err_msg(format!("{}: {}", path.display(), e)))
+    }
+}
+
+impl Default for Assets {
+    fn default() -> Assets {
+        Assets::new()
+    }
 }
 
 pub struct PacMan {
     frame: u64,
-    frequency: u64,
-    last_counter: u64,
     texture_id: usize,
+    walls_texture_id: usize,
+    // Changed while rendering, which only gets to see the game.
+    layers: RefCell<MazeLayers>,
+    simulation: Simulation,
+    // Kept out of the simulation, which only reports initials entered.
+    high_scores: HighScoreTable,
+    high_score_path: Option<PathBuf>,
+    // Set $PACMAN_DEBUG_PATHS to see the way from each ghost to Pac-Man.
+    debug_paths: Option<FlowField>,
+    overlay: RefCell<DebugOverlay>,
+}
+
+impl PacMan {
+    pub fn simulation(&self) -> &Simulation {
+        &self.simulation
+    }
 }
 
 impl Game for PacMan {
     fn load(assets: &mut Assets) -> Result<PacMan, Error> {
-        let texture_id = assets.load_texture("pacman.png").unwrap();
+        let (texture_id, walls_texture_id) = load_textures(assets)?;
+        let levels = assets.load_level_table("levels.txt").unwrap_or_else(|e| {
+            warn!("Using the arcade levels, failed to load the level table: {}", e);
+            LevelTable::arcade()
+        });
+        let maze = load_maze(assets)?;
+        let high_score_path = storage::data_dir().map(|dir| dir.join(high_score::FILE_NAME));
+        let high_scores = match high_score_path {
+            Some(ref path) => HighScoreTable::load(path),
+            None => {
+                warn!("No data directory, high scores won't be saved");
+                HighScoreTable::new()
+            }
+        };
+        let mut world = World::new(levels, maze, world::DEFAULT_SEED);
+        // $PACMAN_PARTY turns START into a party game, "coop 3" or "versus 2".
+        // Games over the network are always versus.
+        if let Ok(setup) = env::var("PACMAN_PARTY") {
+            match setup.parse::<Party>() {
+                Ok(party) => world.set_party_setup(Some(party)),
+                Err(e) => warn!("Ignoring $PACMAN_PARTY: {}", e),
+            }
+        } else if env::var_os("PACMAN_NETPLAY").is_some() {
+            world.set_party_setup(Some(Party::new(Mode::Versus, 2)));
+        }
+        // Both sides of a network game have to start out the same, whatever
+        // their tables hold, so those scores never go on the table.
+        if env::var_os("PACMAN_NETPLAY").is_some() {
+            world.set_qualifying_score(u32::MAX);
+        } else {
+            world.set_qualifying_score(high_scores.qualifying_score());
+        }
+        // $PACMAN_ENDLESS plays a new maze every level.
+        world.set_endless(env::var_os("PACMAN_ENDLESS").is_some());
+        let layers = RefCell::new(MazeLayers::new(world.maze().width(), world.maze().height()));
         Ok(PacMan {
             frame: 0,
-            frequency: bridge::get_performance_frequency(),
-            last_counter: bridge::get_performance_counter(),
             texture_id,
+            walls_texture_id,
+            layers,
+            simulation: Simulation::new(world),
+            high_scores,
+            high_score_path,
+            debug_paths: env::var_os("PACMAN_DEBUG_PATHS").map(|_| FlowField::new()),
+            overlay: RefCell::new(DebugOverlay::new()),
         })
     }
 
-    fn update(&mut self, _input: &Input, assets: &mut Assets) {
-        let current_counter = bridge::get_performance_counter();
-        let delta = ((current_counter - self.last_counter) as f64 / self.frequency as f64) as f32;
-        self.last_counter = current_counter;
-        self.frame = self.frame + 1;
-        trace!("Update for frame {}, delta {}", self.frame, delta);
+    fn restart(&mut self, seed: u32) {
+        self.simulation.restart(seed);
+    }
+
+    fn seed(&self) -> u32 {
+        self.simulation.seed()
+    }
+
+    fn player(&self) -> usize {
+        self.simulation.world().player()
+    }
+
+    fn local_players(&self) -> usize {
+        self.simulation.world().party().map_or(1, Party::players)
+    }
+
+    fn world(&self) -> &World {
+        self.simulation.world()
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        self.simulation.save_state()
+    }
+
+    fn setup_hash(&self) -> u32 {
+        self.simulation.setup_hash()
+    }
+
+    fn load_state(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.simulation.load_state(data)
+    }
+
+    fn update(&mut self, input: &Input, assets: &mut Assets) {
+        // Everything here has to be deterministic, so time only advances in
+        // fixed ticks and never comes from the clock.
+        self.frame += 1;
+        trace!("Update for frame {}, delta {}", self.frame, input.delta);
         trace!("{:?}", assets.textures);
+
+        self.simulation.update(input);
+
+        if let Some(ref mut field) = self.debug_paths {
+            let world = self.simulation.world();
+            field.update(world.maze(), world.pacman().tile());
+        }
     }
 
     fn render(&self, renderer: &mut Renderer, assets: &Assets) {
         trace!("Rendering frame {}", self.frame);
 
         renderer.clear(0.0, 0.0, 0.0, 1.0).unwrap();
+        renderer.set_view_size(canvas::VIEW_WIDTH, canvas::VIEW_HEIGHT);
+
+        {
+            let texture = assets.get_texture(self.texture_id).unwrap();
+            let walls = assets.get_texture(self.walls_texture_id).unwrap();
+            let mut layers = self.layers.borrow_mut();
+            let mut canvas = Canvas::new(renderer, texture, walls)
+                .with_layers(&mut layers)
+                .with_high_score(self.high_scores.high_score());
+            let simulation = &self.simulation;
+            simulation.phase().render(simulation.world(), &mut canvas).unwrap();
+
+            if let Some(map) = self.debug_paths.as_ref().and_then(|field| field.map()) {
+                let world = simulation.world();
+                for (ghost, _) in world.ghosts() {
+                    if let Some(path) = map.path(world.maze(), ghost.tile()) {
+                        canvas.path(&path).unwrap();
+                    }
+                }
+            }
+        }
 
-        renderer.render_texture(assets.get_texture(self.texture_id).unwrap()).unwrap();
+        // A pass of its own over everything else.
+        self.overlay.borrow_mut().render(renderer, self.simulation.world()).unwrap();
 
         renderer.swap_buffers().unwrap();
     }
+
+    fn toggle_debug_overlay(&mut self) {
+        self.overlay.get_mut().toggle();
+    }
+
+    fn textures(&self) -> (usize, usize) {
+        (self.texture_id, self.walls_texture_id)
+    }
+
+    fn record_high_scores(&mut self) {
+        for event in self.simulation.world().events().iter() {
+            if let Event::HighScore { score, initials } = *event {
+                let rank = match self.high_scores.insert(initials, score) {
+                    Some(rank) => rank,
+                    None => continue,
+                };
+                info!("New high score {} at rank {}", score, rank + 1);
+                if let Some(ref path) = self.high_score_path {
+                    if let Err(e) = self.high_scores.save(path) {
+                        error!("Failed to save high scores to {}: {}", path.display(), e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Buttons(u8);
+
+impl Buttons {
+    pub const UP: Buttons = Buttons(1);
+    pub const DOWN: Buttons = Buttons(1 << 1);
+    pub const LEFT: Buttons = Buttons(1 << 2);
+    pub const RIGHT: Buttons = Buttons(1 << 3);
+    pub const START: Buttons = Buttons(1 << 4);
+    pub const BACK: Buttons = Buttons(1 << 5);
+    // Starts a two player game.
+    pub const START2: Buttons = Buttons(1 << 6);
+
+    pub fn empty() -> Buttons {
+        Buttons(0)
+    }
+
+    pub fn from_bits(bits: u8) -> Buttons {
+        Buttons(bits)
+    }
+
+    pub fn bits(self) -> u8 {
+        self.0
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn contains(self, other: Buttons) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: Buttons) {
+        self.0 |= other.0;
+    }
+
+    pub fn remove(&mut self, other: Buttons) {
+        self.0 &= !other.0;
+    }
+}
+
+impl BitOr for Buttons {
+    type Output = Buttons;
+
+    fn bitor(self, other: Buttons) -> Buttons {
+        Buttons(self.0 | other.0)
+    }
 }
 
+// The buttons held on the current and the previous tick, for each local
+// player. Everything but a multiplayer party only looks at the first player.
 pub struct Input {
     delta: f32,
+    buttons: [Buttons; MAX_PLAYERS],
+    last_buttons: [Buttons; MAX_PLAYERS],
 }
 
 impl Input {
     pub fn new() -> Input {
         Input {
             delta: 0.0,
+            buttons: [Buttons::empty(); MAX_PLAYERS],
+            last_buttons: [Buttons::empty(); MAX_PLAYERS],
+        }
+    }
+
+    // Sets the buttons held during the next tick.
+    pub fn update(&mut self, buttons: Buttons) {
+        self.update_players(&[buttons]);
+    }
+
+    // Same for every player, those left out hold nothing.
+    pub fn update_players(&mut self, buttons: &[Buttons]) {
+        self.last_buttons = self.buttons;
+        for (player, held) in self.buttons.iter_mut().enumerate() {
+            *held = buttons.get(player).cloned().unwrap_or_default();
+        }
+    }
+
+    pub fn buttons(&self) -> Buttons {
+        self.buttons[0]
+    }
+
+    pub fn player_buttons(&self, player: usize) -> Buttons {
+        self.buttons.get(player).cloned().unwrap_or_default()
+    }
+
+    pub fn is_down(&self, button: Buttons) -> bool {
+        self.buttons[0].contains(button)
+    }
+
+    // True only on the tick the button went down.
+    pub fn is_pressed(&self, button: Buttons) -> bool {
+        self.buttons[0].contains(button) && !self.last_buttons[0].contains(button)
+    }
+
+    pub fn direction(&self) -> Option<Direction> {
+        self.player_direction(0)
+    }
+
+    pub fn player_direction(&self, player: usize) -> Option<Direction> {
+        let buttons = self.player_buttons(player);
+        if buttons.contains(Buttons::UP) {
+            Some(Direction::Up)
+        } else if buttons.contains(Buttons::LEFT) {
+            Some(Direction::Left)
+        } else if buttons.contains(Buttons::DOWN) {
+            Some(Direction::Down)
+        } else if buttons.contains(Buttons::RIGHT) {
+            Some(Direction::Right)
+        } else {
+            None
         }
     }
 }
 
+impl Default for Input {
+    fn default() -> Input {
+        Input::new()
+    }
+}
+
 pub trait Game: Sized {
     fn load(assets: &mut Assets) -> Result<Self, Error>;
+    // Starts over from a fresh game, the way it was after loading.
+    fn restart(&mut self, seed: u32);
+    fn seed(&self) -> u32;
+    // Whose turn it is, starting at 0.
+    fn player(&self) -> usize;
+    // How many players are steering at the same time.
+    fn local_players(&self) -> usize;
+    // What input sources get to look at to pick their buttons.
+    fn world(&self) -> &World;
+    fn save_state(&self) -> Vec<u8>;
+    // Only equal for games that play out the same given the same input.
+    fn setup_hash(&self) -> u32;
+    // Leaves the game untouched if the state can't be loaded.
+    fn load_state(&mut self, data: &[u8]) -> Result<(), Error>;
     fn update(&mut self, input: &Input, assets: &mut Assets);
     fn render(&self, renderer: &mut Renderer, assets: &Assets);
+    // Shows or hides whatever the game draws on top to help debug it.
+    fn toggle_debug_overlay(&mut self);
+    // The sprite sheet and maze wall texture ids, the editor draws with them
+    // too.
+    fn textures(&self) -> (usize, usize);
+    // Puts initials entered during the last update on the high score table.
+    // Only called for ticks played live, never for ones played back or
+    // simulated again, so each entry is made once.
+    fn record_high_scores(&mut self);
+}
+
+const FAST_FORWARD_TICKS: usize = 8;
+const MAZE_FILE_NAME: &str = "maze.txt";
+const SHEET_FILE_NAME: &str = "pacman.png";
+const QUICK_SAVE_FILE_NAME: &str = "quicksave.pmss";
+const SEEK_TICKS: usize = 5 * world::TICKS_PER_SECOND as usize;
+
+// The sprite sheet and the atlas maze walls are drawn from, by texture id.
+fn load_textures(assets: &mut Assets) -> Result<(usize, usize), Error> {
+    let sheet = assets.load_texture(SHEET_FILE_NAME)?;
+    let walls = assets.create_texture("walls", walls::ATLAS_SIZE, walls::ATLAS_SIZE, &walls::atlas())?;
+    Ok((sheet, walls))
+}
+
+// Where the editor saves the maze. The shipped maze is only played while
+// there is nothing here.
+fn edited_maze_path() -> Option<PathBuf> {
+    storage::data_dir().map(|dir| dir.join(MAZE_FILE_NAME))
+}
+
+fn load_maze(assets: &mut Assets) -> Result<Maze, Error> {
+    if let Some(path) = edited_maze_path().filter(|path| path.exists()) {
+        match assets.load_maze(&path) {
+            Ok(maze) => return Ok(maze),
+            Err(e) => warn!("Using the shipped maze, failed to load the edited one: {}", e),
+        }
+    }
+    assets.load_maze(MAZE_FILE_NAME)
+}
+
+// An empty recording that starts from where the game is now, so it plays
+// back the same even after the maze or levels change.
+fn new_recording<G: Game>(game: &G) -> Replay {
+    Replay::from_state(game.seed(), game.save_state(), game.setup_hash())
 }
 
+// Replays are read from $PACMAN_REPLAY if set, so testers can pass one along
+// with a bug report.
+fn replay_path() -> Option<PathBuf> {
+    env::var_os("PACMAN_REPLAY")
+        .map(PathBuf::from)
+        .or_else(|| storage::data_dir().map(|dir| dir.join(replay::FILE_NAME)))
+}
+
+fn quick_save_path() -> Option<PathBuf> {
+    storage::data_dir().map(|dir| dir.join(QUICK_SAVE_FILE_NAME))
+}
+
+// $PACMAN_NETPLAY is "<player> <local port> <peer address>", for instance
+// "0 7000 192.168.1.2:7000" on one machine and "1 7000 192.168.1.1:7000" on
+// the other.
+fn netplay_session() -> Option<Session<UdpTransport>> {
+    let setup = env::var("PACMAN_NETPLAY").ok()?;
+    let parts: Vec<&str> = setup.split_whitespace().collect();
+    let session = match parts[..] {
+        [player, port, peer] => player
+            .parse::<usize>()
+            .ok()
+            .filter(|&player| player < 2)
+            .ok_or_else(|| err_msg(format!("Expected player 0 or 1, found {}", player)))
+            .and_then(|player| {
+                let transport = UdpTransport::bind(("0.0.0.0", port.parse::<u16>()?), peer)?;
+                Ok(Session::new(transport, player))
+            }),
+        _ => Err(err_msg("Expected a player, a local port and a peer address")),
+    };
+    match session {
+        Ok(session) => {
+            info!("Playing over the network as player {}", session.local_player() + 1);
+            Some(session)
+        }
+        Err(e) => {
+            error!("Ignoring $PACMAN_NETPLAY: {}", e);
+            None
+        }
+    }
+}
+
+// $PACMAN_STREAM is a port to stream the game on, $PACMAN_SPECTATE the
+// address of a game to watch instead of playing.
+fn stream_server() -> Option<StreamServer> {
+    let port = env::var("PACMAN_STREAM").ok()?;
+    let server = port
+        .parse::<u16>()
+        .map_err(Error::from)
+        .and_then(|port| StreamServer::bind(("0.0.0.0", port)));
+    match server {
+        Ok(server) => {
+            info!("Streaming on port {}", port);
+            Some(server)
+        }
+        Err(e) => {
+            error!("Ignoring $PACMAN_STREAM: {}", e);
+            None
+        }
+    }
+}
+
+fn spectate_client() -> Option<StreamClient> {
+    let addr = env::var("PACMAN_SPECTATE").ok()?;
+    match StreamClient::connect(&addr[..]) {
+        Ok(client) => {
+            info!("Watching {}", addr);
+            Some(client)
+        }
+        Err(e) => {
+            error!("Can't watch {}: {}", addr, e);
+            None
+        }
+    }
+}
+
+// Lets a netplay session step and rewind the game.
+struct NetplayGame<'a, G: 'a> {
+    game: &'a mut G,
+    assets: &'a mut Assets,
+}
+
+impl<'a, G: Game> Rollback for NetplayGame<'a, G> {
+    fn save_state(&self) -> Vec<u8> {
+        self.game.save_state()
+    }
+
+    fn load_state(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.game.load_state(data)
+    }
+
+    fn advance(&mut self, input: &Input) {
+        self.game.update(input, self.assets);
+    }
+
+    fn setup_hash(&self) -> u32 {
+        self.game.setup_hash()
+    }
+}
+
+// Buttons any controller can press, even when it isn't their turn.
+const SHARED_BUTTONS: [Buttons; 3] = [Buttons::START, Buttons::START2, Buttons::BACK];
+
 pub struct DesktopRunner<G> {
     assets: Box<Assets>,
     renderer: Renderer,
     input: Input,
+    // Arrow keys are the first controller, WASD the second, IJKL the third
+    // and TFGH the fourth. With several players at once each has their own.
+    // Otherwise the first two both steer whoever is up, unless
+    // $PACMAN_SEPARATE_CONTROLLERS is set.
+    keyboards: [Keyboard; MAX_PLAYERS],
+    separate_controllers: bool,
+    // Set $PACMAN_AUTOPLAY to have the bot steer the first player. The keys
+    // still work alongside it, to start a game or help it along.
+    bot: Option<Bot>,
+    // Every tick since the game was started. While playing back, input
+    // comes from `playback`, a copy of the recording, instead of live.
+    recording: Replay,
+    playback: Option<ReplayInput>,
+    fast_forward: bool,
+    quick_save: Option<Vec<u8>>,
+    // Set when playing against someone over the network. The session owns
+    // the input then, every local controller steers our player.
+    netplay: Option<Session<UdpTransport>>,
+    // Sends the game to viewers after every update. When spectating, the
+    // game only ever loads the states that come in and is never updated.
+    stream: Option<StreamServer>,
+    spectate: Option<StreamClient>,
+    // F6 opens the maze editor on the maze file. The game is paused while
+    // it's open, the edited maze is played after a restart.
+    editor: Option<Editor>,
     game: G,
 }
 
@@ -422,12 +1424,303 @@ impl<G: Game> DesktopRunner<G> {
         let renderer = GlRenderer::new()?;
         let input = Input::new();
         let game = G::load(assets.as_mut())?;
-        Ok(DesktopRunner {
+        let mut runner = DesktopRunner {
             assets,
             renderer,
             input,
+            keyboards: Default::default(),
+            separate_controllers: env::var_os("PACMAN_SEPARATE_CONTROLLERS").is_some(),
+            bot: env::var_os("PACMAN_AUTOPLAY").map(|_| Bot::default()),
+            recording: new_recording(&game),
+            playback: None,
+            fast_forward: false,
+            quick_save: None,
+            netplay: netplay_session(),
+            stream: stream_server(),
+            spectate: spectate_client(),
+            editor: None,
             game,
-        })
+        };
+
+        if env::var_os("PACMAN_REPLAY").is_some() {
+            runner.load_replay();
+        }
+
+        Ok(runner)
+    }
+
+    fn tick(&mut self) {
+        if self.netplay.is_some() {
+            self.tick_netplay();
+        } else {
+            self.tick_local();
+        }
+    }
+
+    fn tick_local(&mut self) {
+        let world = self.game.world();
+        let (buttons, live) = match self.playback.as_mut().and_then(|playback| playback.next_players(world)) {
+            Some(buttons) => (buttons, false),
+            None => {
+                if self.playback.take().is_some() {
+                    info!("Replay finished after {} ticks", self.recording.len());
+                }
+                let buttons = self.take_buttons();
+                self.recording.push_players(&buttons);
+                (buttons, true)
+            }
+        };
+
+        self.input.update_players(&buttons);
+        self.game.update(&self.input, self.assets.as_mut());
+        if live {
+            self.game.record_high_scores();
+        }
+    }
+
+    // Shows the latest state from the stream, once per update however many
+    // ticks arrived.
+    fn spectate(&mut self) {
+        let client = self.spectate.as_mut().unwrap();
+        match client.poll() {
+            Ok(true) => {
+                if let Err(e) = self.game.load_state(client.state().unwrap()) {
+                    error!("Failed to show tick {:?}: {}", client.tick(), e);
+                }
+            }
+            Ok(false) => {}
+            Err(e) => {
+                error!("Stopped watching: {}", e);
+                self.spectate = None;
+            }
+        }
+    }
+
+    fn tick_netplay(&mut self) {
+        let buttons = self.take_buttons().iter().fold(Buttons::empty(), |held, &buttons| held | buttons);
+        let session = self.netplay.as_mut().unwrap();
+        let mut game = NetplayGame {
+            game: &mut self.game,
+            assets: self.assets.as_mut(),
+        };
+        // Nothing that goes wrong here can be fixed by going on, the two
+        // sides would only drift further apart.
+        if let Err(e) = session.update(&mut game, buttons) {
+            error!("Netplay failed at frame {}, playing on alone: {}", session.frame(), e);
+            self.netplay = None;
+        }
+    }
+
+    // Live input from every controller, and the bot if it's playing.
+    fn take_buttons(&mut self) -> [Buttons; MAX_PLAYERS] {
+        let world = self.game.world();
+        let mut held = [Buttons::empty(); MAX_PLAYERS];
+        for (buttons, keyboard) in held.iter_mut().zip(self.keyboards.iter_mut()) {
+            *buttons = keyboard.next(world).unwrap_or_default();
+        }
+        if let Some(buttons) = self.bot.as_mut().and_then(|bot| bot.next(world)) {
+            held[0].insert(buttons);
+        }
+        if self.game.local_players() > 1 {
+            return held;
+        }
+
+        let (first, second) = (held[0], held[1]);
+        let mut buttons = [Buttons::empty(); MAX_PLAYERS];
+        if !self.separate_controllers {
+            buttons[0] = first | second;
+            return buttons;
+        }
+
+        let (mut up, other) = if self.game.player() == 1 { (second, first) } else { (first, second) };
+        for &button in SHARED_BUTTONS.iter() {
+            if other.contains(button) {
+                up.insert(button);
+            }
+        }
+        buttons[0] = up;
+        buttons
+    }
+
+    // Restarts the game and quickly plays the recording up to `tick`. Always
+    // steps the game locally, netplay never plays back.
+    pub fn seek(&mut self, tick: usize) {
+        let tick = tick.min(self.recording.len());
+        info!("Seeking to tick {} of {}", tick, self.recording.len());
+
+        self.input = Input::new();
+        if let Err(e) = self.rewind() {
+            error!("Can't play the replay back: {}", e);
+            self.playback = None;
+            self.recording = new_recording(&self.game);
+            return;
+        }
+        self.playback = Some(ReplayInput::new(self.recording.clone()));
+        while self.playback.as_ref().is_some_and(|playback| playback.tick() < tick) {
+            self.tick_local();
+        }
+    }
+
+    // Puts the game back to where the recording starts.
+    fn rewind(&mut self) -> Result<(), Error> {
+        self.game.restart(self.recording.seed());
+        if let Some(state) = self.recording.start_state() {
+            self.game.load_state(state)?;
+        }
+        if self.recording.setup_hash().is_some_and(|hash| hash != self.game.setup_hash()) {
+            return Err(err_msg("It was recorded with another maze, level table or settings"));
+        }
+        Ok(())
+    }
+
+    pub fn play(&mut self, replay: Replay) {
+        // The session owns the game's input, a replay can't take it over.
+        if self.netplay.is_some() {
+            warn!("Not playing the replay during a netplay session");
+            return;
+        }
+        self.recording = replay;
+        self.seek(0);
+    }
+
+    // Takes over with live input from the current tick.
+    pub fn stop_playback(&mut self) {
+        if let Some(tick) = self.playback.take().map(|playback| playback.tick()) {
+            info!("Stopped replay at tick {}", tick);
+            self.recording.truncate(tick);
+        }
+    }
+
+    fn save_replay(&self) {
+        match replay_path() {
+            Some(path) => match self.recording.save(&path) {
+                Ok(()) => info!("Saved {} ticks to {}", self.recording.len(), path.display()),
+                Err(e) => error!("Failed to save replay to {}: {}", path.display(), e),
+            },
+            None => warn!("No data directory, can't save the replay"),
+        }
+    }
+
+    fn load_replay(&mut self) {
+        match replay_path().map(Replay::load) {
+            Some(Ok(replay)) => self.play(replay),
+            Some(Err(e)) => error!("Failed to load replay: {}", e),
+            None => warn!("No data directory, can't load a replay"),
+        }
+    }
+
+    fn quick_save(&mut self) {
+        let data = self.game.save_state();
+        match quick_save_path() {
+            Some(path) => match storage::write(&path, &data) {
+                Ok(()) => info!("Saved state to {}", path.display()),
+                Err(e) => error!("Failed to save state to {}: {}", path.display(), e),
+            },
+            None => info!("No data directory, state is only kept until exit"),
+        }
+        self.quick_save = Some(data);
+    }
+
+    fn quick_load(&mut self) {
+        let data = match self.quick_save {
+            Some(ref data) => data.clone(),
+            None => match quick_save_path().map(storage::read) {
+                Some(Ok(data)) => data,
+                Some(Err(e)) => {
+                    error!("Failed to read saved state: {}", e);
+                    return;
+                }
+                None => {
+                    warn!("Nothing to load");
+                    return;
+                }
+            },
+        };
+
+        if let Err(e) = self.game.load_state(&data) {
+            error!("Failed to load state: {}", e);
+            return;
+        }
+
+        // The recording goes on from the loaded state so it still replays.
+        self.playback = None;
+        self.input = Input::new();
+        self.recording = new_recording(&self.game);
+        info!("Loaded state");
+    }
+
+    fn toggle_editor(&mut self) {
+        if self.editor.take().is_some() {
+            return;
+        }
+        self.load_editor_maze();
+    }
+
+    fn load_editor_maze(&mut self) {
+        match load_maze(self.assets.as_mut()) {
+            Ok(maze) => self.editor = Some(Editor::new(maze)),
+            Err(e) => error!("Failed to load the maze to edit: {}", e),
+        }
+    }
+
+    fn save_editor_maze(&self) {
+        let editor = self.editor.as_ref().unwrap();
+        let path = match edited_maze_path() {
+            Some(path) => path,
+            None => return error!("No data directory to save the maze to"),
+        };
+        match editor.save(&path) {
+            Ok(()) => info!("Saved maze to {}, restart to play it", path.display()),
+            Err(e) => {
+                error!("Not saving the maze: {}", e);
+                for problem in editor.problems() {
+                    warn!("{}", problem);
+                }
+            }
+        }
+    }
+
+    // While the editor is open it takes every key.
+    fn on_editor_key(&mut self, key: Key) {
+        match key {
+            Key::F6 | Key::Escape => self.editor = None,
+            Key::F2 => self.save_editor_maze(),
+            Key::F3 => self.load_editor_maze(),
+            Key::Left => self.editor.as_mut().unwrap().cycle_tool(-1),
+            Key::Right => self.editor.as_mut().unwrap().cycle_tool(1),
+            _ => {}
+        }
+    }
+
+    fn render_editor(&mut self) {
+        let editor = self.editor.as_ref().unwrap();
+        let (texture, walls) = self.game.textures();
+        let texture = self.assets.get_texture(texture).unwrap();
+        let walls = self.assets.get_texture(walls).unwrap();
+        self.renderer.clear(0.0, 0.0, 0.0, 1.0).unwrap();
+        self.renderer.set_view_size(canvas::VIEW_WIDTH, canvas::VIEW_HEIGHT);
+        editor.render(&mut Canvas::new(&mut self.renderer, texture, walls)).unwrap();
+        self.renderer.swap_buffers().unwrap();
+    }
+
+    // Keys that control the runner rather than the game.
+    fn on_runner_key(&mut self, key: Key) -> bool {
+        // The peer can't follow us back in time, and spectators only watch.
+        if self.netplay.is_some() || self.spectate.is_some() {
+            return false;
+        }
+        match (key, self.playback.as_ref().map(ReplayInput::tick)) {
+            (Key::F6, _) => self.toggle_editor(),
+            (Key::F2, _) => self.save_replay(),
+            (Key::F3, _) => self.load_replay(),
+            (Key::F5, _) => self.quick_save(),
+            (Key::F9, _) => self.quick_load(),
+            (Key::Left, Some(tick)) => self.seek(tick.saturating_sub(SEEK_TICKS)),
+            (Key::Right, Some(tick)) => self.seek(tick + SEEK_TICKS),
+            (Key::Escape, Some(_)) => self.stop_playback(),
+            _ => return false,
+        }
+        true
     }
 }
 
@@ -442,16 +1735,101 @@ impl<G: Game> bridge::Runner for DesktopRunner<G> {
         match *event {
             PlatformEvent::Update { dt } => {
                 self.input.delta = dt;
-                self.game.update(&self.input, self.assets.as_mut());
+                if self.editor.is_some() {
+                    return;
+                }
+                if self.spectate.is_some() {
+                    return self.spectate();
+                }
+                let ticks = if self.fast_forward && self.playback.is_some() {
+                    FAST_FORWARD_TICKS
+                } else {
+                    1
+                };
+                for _ in 0..ticks {
+                    self.tick();
+                }
+                // Viewers only need what gets drawn, not every fast
+                // forwarded tick.
+                if let Some(ref mut server) = self.stream {
+                    server.publish(&self.game.save_state());
+                }
             },
+            PlatformEvent::Render if self.editor.is_some() => self.render_editor(),
             PlatformEvent::Render => self.game.render(&mut self.renderer, self.assets.as_ref()),
             PlatformEvent::Close => bridge::quit(),
             PlatformEvent::Resized { width, height } => unsafe {
                 trace!("Resizing {}x{}", width, height);
                 gl::Viewport(0, 0, width, height);
             },
+            PlatformEvent::KeyDown { key } if self.editor.is_some() => self.on_editor_key(key),
+            PlatformEvent::KeyDown { key } => {
+                // Only changes what is drawn, so it works in every mode.
+                if key == Key::F1 {
+                    return self.game.toggle_debug_overlay();
+                }
+                if key == Key::F4 {
+                    self.fast_forward = true;
+                } else if self.on_runner_key(key) {
+                    return;
+                }
+                if let Some((controller, button)) = button_for_key(key) {
+                    self.keyboards[controller].press(button);
+                }
+            }
+            PlatformEvent::KeyUp { key } => {
+                if key == Key::F4 {
+                    self.fast_forward = false;
+                }
+                if let Some((controller, button)) = button_for_key(key) {
+                    self.keyboards[controller].release(button);
+                }
+            }
+            PlatformEvent::MouseMove { x, y } => {
+                let (x, y) = self.renderer.window_to_view(x, y);
+                if let Some(ref mut editor) = self.editor {
+                    editor.mouse_move(x, y);
+                }
+            }
+            PlatformEvent::MouseDown { x, y, button } => {
+                let (x, y) = self.renderer.window_to_view(x, y);
+                if let Some(ref mut editor) = self.editor {
+                    editor.mouse_down(x, y, button == MouseButton::Right);
+                }
+            }
+            PlatformEvent::MouseUp { .. } => {
+                if let Some(ref mut editor) = self.editor {
+                    editor.mouse_up();
+                }
+            }
         }
     }
 }
 
+// The controller a key belongs to and the button it presses.
+fn button_for_key(key: Key) -> Option<(usize, Buttons)> {
+    match key {
+        Key::Up => Some((0, Buttons::UP)),
+        Key::Down => Some((0, Buttons::DOWN)),
+        Key::Left => Some((0, Buttons::LEFT)),
+        Key::Right => Some((0, Buttons::RIGHT)),
+        Key::Enter | Key::Space | Key::Num1 => Some((0, Buttons::START)),
+        Key::Num2 => Some((0, Buttons::START2)),
+        Key::Backspace => Some((0, Buttons::BACK)),
+        Key::W => Some((1, Buttons::UP)),
+        Key::S => Some((1, Buttons::DOWN)),
+        Key::A => Some((1, Buttons::LEFT)),
+        Key::D => Some((1, Buttons::RIGHT)),
+        Key::I => Some((2, Buttons::UP)),
+        Key::K => Some((2, Buttons::DOWN)),
+        Key::J => Some((2, Buttons::LEFT)),
+        Key::L => Some((2, Buttons::RIGHT)),
+        Key::T => Some((3, Buttons::UP)),
+        Key::G => Some((3, Buttons::DOWN)),
+        Key::F => Some((3, Buttons::LEFT)),
+        Key::H => Some((3, Buttons::RIGHT)),
+        _ => None,
+    }
+}
+
 entry!(DesktopRunner<PacMan>);
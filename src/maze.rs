@@ -0,0 +1,697 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+use failure::{err_msg, Error};
+use state::{Decode, Encode, StateReader, StateWriter};
+
+static ARCADE_MAZE: &str = include_str!("../assets/maze.txt");
+
+pub const TILE_SIZE: i32 = 8;
+
+// The widest and tallest maze a save state can hold.
+pub const MAX_SIZE: i32 = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Up,
+    Left,
+    Down,
+    Right,
+}
+
+impl Direction {
+    // The order ghosts use to break ties between equally good directions.
+    pub const ALL: [Direction; 4] = [
+        Direction::Up,
+        Direction::Left,
+        Direction::Down,
+        Direction::Right,
+    ];
+
+    pub fn opposite(self) -> Direction {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Left => Direction::Right,
+            Direction::Down => Direction::Up,
+            Direction::Right => Direction::Left,
+        }
+    }
+
+    pub fn delta(self) -> (i32, i32) {
+        match self {
+            Direction::Up => (0, -1),
+            Direction::Left => (-1, 0),
+            Direction::Down => (0, 1),
+            Direction::Right => (1, 0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TilePos {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl TilePos {
+    pub fn new(x: i32, y: i32) -> TilePos {
+        TilePos { x, y }
+    }
+
+    // The tile containing a pixel position.
+    pub fn from_pixel(x: i32, y: i32) -> TilePos {
+        TilePos {
+            x: x.div_euclid(TILE_SIZE),
+            y: y.div_euclid(TILE_SIZE),
+        }
+    }
+
+    pub fn step(self, direction: Direction) -> TilePos {
+        self.offset(direction, 1)
+    }
+
+    pub fn offset(self, direction: Direction, tiles: i32) -> TilePos {
+        let (dx, dy) = direction.delta();
+        TilePos {
+            x: self.x + dx * tiles,
+            y: self.y + dy * tiles,
+        }
+    }
+
+    pub fn distance_squared(self, other: TilePos) -> i32 {
+        let dx = self.x - other.x;
+        let dy = self.y - other.y;
+        dx * dx + dy * dy
+    }
+
+    pub fn center(self) -> (i32, i32) {
+        (
+            self.x * TILE_SIZE + TILE_SIZE / 2,
+            self.y * TILE_SIZE + TILE_SIZE / 2,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tile {
+    Wall,
+    Empty,
+    Pellet,
+    Energizer,
+    Door,
+}
+
+impl Tile {
+    fn parse(c: char) -> Option<(Tile, Zone)> {
+        match c {
+            '#' => Some((Tile::Wall, Zone::Normal)),
+            ' ' => Some((Tile::Empty, Zone::Normal)),
+            '.' => Some((Tile::Pellet, Zone::Normal)),
+            'o' => Some((Tile::Energizer, Zone::Normal)),
+            '-' => Some((Tile::Door, Zone::Normal)),
+            '=' => Some((Tile::Empty, Zone::Tunnel)),
+            '_' => Some((Tile::Empty, Zone::NoUpTurn)),
+            ',' => Some((Tile::Pellet, Zone::NoUpTurn)),
+            _ => None,
+        }
+    }
+
+    fn to_char(self, zone: Zone) -> char {
+        match (self, zone) {
+            (Tile::Wall, _) => '#',
+            (Tile::Empty, Zone::Tunnel) => '=',
+            (Tile::Empty, Zone::NoUpTurn) => '_',
+            (Tile::Empty, _) => ' ',
+            (Tile::Pellet, Zone::NoUpTurn) => ',',
+            (Tile::Pellet, _) => '.',
+            (Tile::Energizer, _) => 'o',
+            (Tile::Door, _) => '-',
+        }
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            Tile::Wall => 0,
+            Tile::Empty => 1,
+            Tile::Pellet => 2,
+            Tile::Energizer => 3,
+            Tile::Door => 4,
+        }
+    }
+
+    fn from_u8(value: u8) -> Option<Tile> {
+        match value {
+            0 => Some(Tile::Wall),
+            1 => Some(Tile::Empty),
+            2 => Some(Tile::Pellet),
+            3 => Some(Tile::Energizer),
+            4 => Some(Tile::Door),
+            _ => None,
+        }
+    }
+
+    pub fn is_walkable(self) -> bool {
+        match self {
+            Tile::Empty | Tile::Pellet | Tile::Energizer => true,
+            Tile::Wall | Tile::Door => false,
+        }
+    }
+}
+
+// Rules for the ghosts that come with some tiles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Zone {
+    Normal,
+    // Ghosts slow down in the side tunnels.
+    Tunnel,
+    // Chasing or scattering ghosts can't turn up here.
+    NoUpTurn,
+}
+
+impl Zone {
+    fn to_u8(self) -> u8 {
+        match self {
+            Zone::Normal => 0,
+            Zone::Tunnel => 1,
+            Zone::NoUpTurn => 2,
+        }
+    }
+
+    fn from_u8(value: u8) -> Option<Zone> {
+        match value {
+            0 => Some(Zone::Normal),
+            1 => Some(Zone::Tunnel),
+            2 => Some(Zone::NoUpTurn),
+            _ => None,
+        }
+    }
+}
+
+// The ghost house, laid out like the arcade's: a two tile door on top and
+// six by three tiles inside its walls. Everything about it follows from
+// where the door is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct House {
+    // The left one of the two door tiles.
+    pub door: TilePos,
+}
+
+const HOUSE_LAYOUT: [&str; 5] = ["###--###", "#      #", "#      #", "#      #", "########"];
+const HOUSE_DOOR_COLUMN: i32 = 3;
+
+impl House {
+    // Where ghosts come out, above the middle of the door.
+    pub fn exit(self) -> (i32, i32) {
+        (self.door.x * TILE_SIZE + TILE_SIZE, (self.door.y - 1) * TILE_SIZE + TILE_SIZE / 2)
+    }
+
+    // The tile eaten ghosts head for before they drop back in.
+    pub fn entrance(self) -> TilePos {
+        TilePos::new(self.door.x, self.door.y - 1)
+    }
+
+    // Ghosts waiting inside bob up and down around this.
+    pub fn center_y(self) -> i32 {
+        (self.door.y + 2) * TILE_SIZE + TILE_SIZE / 2
+    }
+
+    pub fn contains(self, pos: TilePos) -> bool {
+        let left = self.door.x - HOUSE_DOOR_COLUMN;
+        let width = HOUSE_LAYOUT[0].len() as i32;
+        (left..left + width).contains(&pos.x) && (self.door.y..self.door.y + HOUSE_LAYOUT.len() as i32).contains(&pos.y)
+    }
+
+    // Every tile of the house, walls and door included.
+    pub fn tiles(self) -> impl Iterator<Item = (TilePos, Tile)> {
+        let left = self.door.x - HOUSE_DOOR_COLUMN;
+        let top = self.door.y;
+        HOUSE_LAYOUT.iter().enumerate().flat_map(move |(y, row)| {
+            row.chars().enumerate().map(move |(x, c)| {
+                let (tile, _) = Tile::parse(c).unwrap();
+                (TilePos::new(left + x as i32, top + y as i32), tile)
+            })
+        })
+    }
+}
+
+// Something that keeps a maze from being played.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Problem {
+    NoHouse,
+    NarrowDoor,
+    BlockedStart,
+    BlockedHouseExit,
+    NoPellets,
+    Unreachable(TilePos),
+    // Ghosts can't turn around, one that walks in here is stuck.
+    DeadEnd(TilePos),
+    // Different from the tile mirroring it, for mazes meant to be symmetric.
+    Asymmetric(TilePos),
+    // Open on one side of the maze but not on the other.
+    OneSidedTunnel(i32),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Problem::NoHouse => write!(f, "There is no ghost house door"),
+            Problem::NarrowDoor => write!(f, "The ghost house door has to be two tiles wide"),
+            Problem::BlockedStart => write!(f, "Pac-Man starts inside a wall"),
+            Problem::BlockedHouseExit => write!(f, "Ghosts can't get out of the house"),
+            Problem::NoPellets => write!(f, "There are no pellets to eat"),
+            Problem::Unreachable(pos) => write!(f, "Tile ({}, {}) can't be reached", pos.x, pos.y),
+            Problem::DeadEnd(pos) => write!(f, "Tile ({}, {}) is a dead end", pos.x, pos.y),
+            Problem::Asymmetric(pos) => write!(f, "Tile ({}, {}) doesn't match the other side", pos.x, pos.y),
+            Problem::OneSidedTunnel(row) => write!(f, "Row {} is open on one side only", row),
+        }
+    }
+}
+
+// A grid of tiles, one character per tile:
+//
+//   #  wall
+//   .  pellet
+//   o  energizer
+//   -  ghost house door
+//      (space) empty floor
+//   =  empty floor in a tunnel
+//   _  empty floor where ghosts can't turn up
+//   ,  pellet where ghosts can't turn up
+//
+// The left and right edges are joined: leaving one side comes back in on the
+// other, so a row open at both ends is a tunnel. The ghost house is below the
+// leftmost door tile.
+//
+// The grid ends at the first blank line, settings may follow it:
+//
+//   start X Y  the tile Pac-Man starts on, at its right edge
+//
+// Without a start or a door they are where the arcade has them, relative to
+// the size of the maze.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Maze {
+    width: i32,
+    height: i32,
+    tiles: Vec<Tile>,
+    zones: Vec<Zone>,
+    pellets_left: u32,
+    start: TilePos,
+    door: Option<TilePos>,
+}
+
+impl Maze {
+    pub fn arcade() -> Maze {
+        Maze::parse(ARCADE_MAZE).expect("Invalid builtin maze")
+    }
+
+    pub fn parse(source: &str) -> Result<Maze, Error> {
+        let mut lines = source.lines();
+        let grid = lines.by_ref().take_while(|line| !line.is_empty()).collect::<Vec<_>>();
+        let mut start = None;
+        for (i, line) in lines.enumerate() {
+            let mut parts = line.split_whitespace();
+            match parts.next() {
+                Some("start") => {
+                    let mut number = || parts.next().and_then(|n| n.parse::<i32>().ok());
+                    let pos = number()
+                        .and_then(|x| Some(TilePos::new(x, number()?)))
+                        .ok_or_else(|| err_msg(format!("Expected \"start X Y\" at line {}", grid.len() + i + 2)))?;
+                    start = Some(pos);
+                }
+                Some(setting) => {
+                    return Err(err_msg(format!("Unknown setting {:?} at line {}", setting, grid.len() + i + 2)))
+                }
+                None => {}
+            }
+        }
+
+        let lines = grid;
+        let width = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0);
+        if width == 0 {
+            return Err(err_msg("Maze is empty"));
+        }
+
+        let mut tiles = Vec::with_capacity(width * lines.len());
+        let mut zones = Vec::with_capacity(width * lines.len());
+        for (y, line) in lines.iter().enumerate() {
+            let mut chars = line.chars();
+            for x in 0..width {
+                // Trailing spaces are often stripped by editors.
+                let c = chars.next().unwrap_or(' ');
+                let (tile, zone) = Tile::parse(c).ok_or_else(|| {
+                    err_msg(format!("Invalid tile {:?} at line {}, column {}", c, y + 1, x + 1))
+                })?;
+                tiles.push(tile);
+                zones.push(zone);
+            }
+        }
+
+        let maze = Maze::from_tiles(width as i32, lines.len() as i32, tiles, zones, start);
+        match start {
+            Some(pos) if !maze.contains(pos) => Err(err_msg(format!("Start ({}, {}) is outside the maze", pos.x, pos.y))),
+            _ => Ok(maze),
+        }
+    }
+
+    // Walls everywhere, to carve a maze out of.
+    pub fn filled(width: i32, height: i32) -> Maze {
+        let len = (width.max(1) * height.max(1)) as usize;
+        Maze::from_tiles(width.max(1), height.max(1), vec![Tile::Wall; len], vec![Zone::Normal; len], None)
+    }
+
+    fn from_tiles(width: i32, height: i32, tiles: Vec<Tile>, zones: Vec<Zone>, start: Option<TilePos>) -> Maze {
+        let mut maze = Maze {
+            width,
+            height,
+            tiles,
+            zones,
+            pellets_left: 0,
+            start: start.unwrap_or_else(|| TilePos::new((width / 2 - 1).max(0), (height - 8).max(0))),
+            door: None,
+        };
+        maze.count();
+        maze
+    }
+
+    // Works out what follows from the tiles after they changed.
+    fn count(&mut self) {
+        self.pellets_left = self
+            .tiles
+            .iter()
+            .filter(|&&tile| tile == Tile::Pellet || tile == Tile::Energizer)
+            .count() as u32;
+        let width = self.width;
+        self.door = self
+            .tiles
+            .iter()
+            .position(|&tile| tile == Tile::Door)
+            .map(|index| TilePos::new(index as i32 % width, index as i32 / width));
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    pub fn contains(&self, pos: TilePos) -> bool {
+        pos.x >= 0 && pos.x < self.width && pos.y >= 0 && pos.y < self.height
+    }
+
+    // Columns wrap around, rows don't.
+    fn index(&self, pos: TilePos) -> Option<usize> {
+        if pos.y >= 0 && pos.y < self.height {
+            Some((pos.y * self.width + pos.x.rem_euclid(self.width)) as usize)
+        } else {
+            None
+        }
+    }
+
+    // Everything above or below the maze is a wall.
+    pub fn get(&self, pos: TilePos) -> Tile {
+        self.index(pos).map_or(Tile::Wall, |index| self.tiles[index])
+    }
+
+    pub fn zone(&self, pos: TilePos) -> Zone {
+        self.index(pos).map_or(Zone::Normal, |index| self.zones[index])
+    }
+
+    // Brings a pixel x back into the maze after walking off one side.
+    pub fn wrap_x(&self, x: i32) -> i32 {
+        x.rem_euclid(self.width * TILE_SIZE)
+    }
+
+    pub fn is_walkable(&self, pos: TilePos) -> bool {
+        self.get(pos).is_walkable()
+    }
+
+    // Removes the pellet or energizer at `pos` and returns what was there.
+    pub fn eat(&mut self, pos: TilePos) -> Tile {
+        let tile = self.get(pos);
+        match tile {
+            Tile::Pellet | Tile::Energizer => {
+                let index = self.index(pos).unwrap();
+                self.tiles[index] = Tile::Empty;
+                self.pellets_left -= 1;
+                tile
+            }
+            _ => Tile::Empty,
+        }
+    }
+
+    pub fn pellets_left(&self) -> u32 {
+        self.pellets_left
+    }
+
+    // Changes a tile, for building and editing mazes rather than playing them.
+    pub fn set(&mut self, pos: TilePos, tile: Tile, zone: Zone) {
+        if let Some(index) = self.index(pos) {
+            self.tiles[index] = tile;
+            self.zones[index] = zone;
+            self.count();
+        }
+    }
+
+    // The tile Pac-Man starts on the right edge of.
+    pub fn start(&self) -> TilePos {
+        self.start
+    }
+
+    pub fn set_start(&mut self, pos: TilePos) {
+        if self.contains(pos) {
+            self.start = pos;
+        }
+    }
+
+    // Pac-Man's start in pixels, between the start tile and the next.
+    pub fn start_position(&self) -> (i32, i32) {
+        let (x, y) = self.start().center();
+        (x + TILE_SIZE / 2, y)
+    }
+
+    pub fn has_house(&self) -> bool {
+        self.door.is_some()
+    }
+
+    pub fn house(&self) -> House {
+        House {
+            door: self
+                .door
+                .unwrap_or_else(|| TilePos::new(self.width / 2 - 1, (self.height - 7) / 2)),
+        }
+    }
+
+    // Builds the ghost house with its door at `door`, in place of the old one.
+    pub fn place_house(&mut self, door: TilePos) {
+        for index in 0..self.tiles.len() {
+            if self.tiles[index] == Tile::Door {
+                self.tiles[index] = Tile::Wall;
+            }
+        }
+        for (pos, tile) in (House { door }).tiles() {
+            if let Some(index) = self.index(pos).filter(|_| self.contains(pos)) {
+                self.tiles[index] = tile;
+                self.zones[index] = Zone::Normal;
+            }
+        }
+        self.count();
+    }
+
+    // Everything wrong with the maze, empty if it can be played.
+    pub fn check(&self) -> Vec<Problem> {
+        let mut problems = Vec::new();
+        let house = self.house();
+        if !self.has_house() {
+            problems.push(Problem::NoHouse);
+        } else if self.get(house.door.step(Direction::Right)) != Tile::Door {
+            problems.push(Problem::NarrowDoor);
+        }
+
+        let start = self.start();
+        if !self.is_walkable(start) || !self.is_walkable(start.step(Direction::Right)) {
+            problems.push(Problem::BlockedStart);
+            return problems;
+        }
+
+        let reached = self.reachable_from(start);
+        let is_reached = |pos: TilePos| self.index(pos).is_some_and(|index| reached[index]);
+        if self.has_house() && !is_reached(house.entrance()) {
+            problems.push(Problem::BlockedHouseExit);
+        }
+        if self.pellets_left == 0 {
+            problems.push(Problem::NoPellets);
+        }
+        for y in 0..self.height {
+            let left = self.is_walkable(TilePos::new(0, y));
+            let right = self.is_walkable(TilePos::new(self.width - 1, y));
+            if left != right {
+                problems.push(Problem::OneSidedTunnel(y));
+            }
+        }
+        // Only ghosts get into the house.
+        let open = self
+            .tiles()
+            .filter(|&(pos, tile)| tile.is_walkable() && !(self.has_house() && house.contains(pos)))
+            .map(|(pos, _)| pos)
+            .collect::<Vec<_>>();
+        problems.extend(open.iter().filter(|&&pos| !is_reached(pos)).map(|&pos| Problem::Unreachable(pos)));
+        problems.extend(
+            open.iter()
+                .filter(|&&pos| Direction::ALL.iter().filter(|&&dir| self.is_walkable(pos.step(dir))).count() < 2)
+                .map(|&pos| Problem::DeadEnd(pos)),
+        );
+        problems
+    }
+
+    // Which tiles can be walked to from `from`, by index.
+    fn reachable_from(&self, from: TilePos) -> Vec<bool> {
+        let mut reached = vec![false; self.tiles.len()];
+        let mut queue = VecDeque::new();
+        if let Some(index) = self.index(from) {
+            reached[index] = true;
+            queue.push_back(from);
+        }
+        while let Some(pos) = queue.pop_front() {
+            for &dir in Direction::ALL.iter() {
+                let next = pos.step(dir);
+                let next = TilePos::new(next.x.rem_euclid(self.width), next.y);
+                if let Some(index) = self.index(next) {
+                    if !reached[index] && self.tiles[index].is_walkable() {
+                        reached[index] = true;
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+        reached
+    }
+
+    // Whether the walls and door are where they are in `other`, whatever has
+    // been eaten.
+    pub fn same_walls(&self, other: &Maze) -> bool {
+        let solid = |tile: &Tile| !tile.is_walkable();
+        self.width == other.width
+            && self.height == other.height
+            && self.tiles.iter().map(solid).eq(other.tiles.iter().map(solid))
+    }
+
+    pub fn tiles(&self) -> impl Iterator<Item = (TilePos, Tile)> + '_ {
+        let width = self.width;
+        self.tiles
+            .iter()
+            .enumerate()
+            .map(move |(index, &tile)| (TilePos::new(index as i32 % width, index as i32 / width), tile))
+    }
+}
+
+// Writes the maze back out the way `parse` reads it.
+impl fmt::Display for Maze {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for y in 0..self.height {
+            let line: String = (0..self.width)
+                .map(|x| {
+                    let pos = TilePos::new(x, y);
+                    self.get(pos).to_char(self.zone(pos))
+                })
+                .collect();
+            writeln!(f, "{}", line)?;
+        }
+        writeln!(f)?;
+        writeln!(f, "start {} {}", self.start.x, self.start.y)
+    }
+}
+
+impl Encode for Maze {
+    fn encode(&self, w: &mut StateWriter) {
+        w.write_i32(self.width);
+        w.write_i32(self.height);
+        w.write_i32(self.start.x);
+        w.write_i32(self.start.y);
+        for (tile, zone) in self.tiles.iter().zip(self.zones.iter()) {
+            w.write_u8(tile.to_u8());
+            w.write_u8(zone.to_u8());
+        }
+    }
+}
+
+impl Decode for Maze {
+    fn decode(r: &mut StateReader) -> Result<Maze, Error> {
+        let width = r.read_i32()?;
+        let height = r.read_i32()?;
+        if width <= 0 || height <= 0 || width > MAX_SIZE || height > MAX_SIZE {
+            return Err(err_msg(format!("Invalid maze size {}x{}", width, height)));
+        }
+        let start = TilePos::new(r.read_i32()?, r.read_i32()?);
+
+        let mut tiles = Vec::with_capacity((width * height) as usize);
+        let mut zones = Vec::with_capacity((width * height) as usize);
+        for _ in 0..width * height {
+            let value = r.read_u8()?;
+            tiles.push(Tile::from_u8(value).ok_or_else(|| err_msg(format!("Invalid tile {}", value)))?);
+            let value = r.read_u8()?;
+            zones.push(Zone::from_u8(value).ok_or_else(|| err_msg(format!("Invalid zone {}", value)))?);
+        }
+
+        Ok(Maze::from_tiles(width, height, tiles, zones, Some(start)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_zones() {
+        let maze = Maze::parse("#_,#\n=  =\n").unwrap();
+        assert_eq!(maze.get(TilePos::new(1, 0)), Tile::Empty);
+        assert_eq!(maze.zone(TilePos::new(1, 0)), Zone::NoUpTurn);
+        assert_eq!(maze.get(TilePos::new(2, 0)), Tile::Pellet);
+        assert_eq!(maze.zone(TilePos::new(2, 0)), Zone::NoUpTurn);
+        assert_eq!(maze.zone(TilePos::new(0, 1)), Zone::Tunnel);
+        assert_eq!(maze.zone(TilePos::new(1, 1)), Zone::Normal);
+        assert_eq!(maze.pellets_left(), 1);
+    }
+
+    #[test]
+    fn sides_wrap_around() {
+        let mut maze = Maze::parse("#.#\n. o\n").unwrap();
+        assert_eq!(maze.get(TilePos::new(-1, 1)), Tile::Energizer);
+        assert_eq!(maze.get(TilePos::new(3, 1)), Tile::Pellet);
+        assert_eq!(maze.get(TilePos::new(1, -1)), Tile::Wall);
+        assert_eq!(maze.eat(TilePos::new(-1, 1)), Tile::Energizer);
+        assert_eq!(maze.get(TilePos::new(2, 1)), Tile::Empty);
+        assert_eq!(maze.wrap_x(-1), 3 * TILE_SIZE - 1);
+    }
+
+    #[test]
+    fn arcade_layout() {
+        let maze = Maze::arcade();
+        assert_eq!(maze.check(), Vec::new());
+        assert_eq!(maze.house().exit(), (112, 92));
+        assert_eq!(maze.start_position(), (112, 188));
+        assert_eq!(Maze::parse(&maze.to_string()).unwrap(), maze);
+    }
+
+    #[test]
+    fn finds_problems() {
+        let mut maze = Maze::arcade();
+        maze.place_house(TilePos::new(3, 1));
+        assert!(maze.check().contains(&Problem::BlockedHouseExit));
+
+        let mut maze = Maze::arcade();
+        let far = TilePos::new(1, 1);
+        maze.set(far.step(Direction::Right), Tile::Wall, Zone::Normal);
+        maze.set(far.step(Direction::Down), Tile::Wall, Zone::Normal);
+        maze.set(TilePos::new(0, 14), Tile::Wall, Zone::Normal);
+        let problems = maze.check();
+        assert_eq!(problems[..2], [Problem::OneSidedTunnel(14), Problem::Unreachable(far)]);
+        assert!(problems.contains(&Problem::DeadEnd(far)));
+        assert!(problems.contains(&Problem::DeadEnd(TilePos::new(1, 14))));
+
+        maze.set_start(TilePos::new(0, 0));
+        assert_eq!(maze.check(), vec![Problem::BlockedStart]);
+        assert_eq!(Maze::filled(4, 4).check(), vec![Problem::NoHouse, Problem::BlockedStart]);
+    }
+}
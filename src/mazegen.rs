@@ -0,0 +1,312 @@
+use maze::{Maze, Problem, Tile, TilePos, Zone};
+use rng::Rng;
+
+// Generated mazes are the arcade's size and keep its middle: the ghost house
+// in a ring of corridor, the tunnel through the sides and Pac-Man's start
+// below the house. Everything else is corridors between the crossings of the
+// arcade's lanes, picked at random for the left half and mirrored.
+pub const WIDTH: i32 = 28;
+pub const HEIGHT: i32 = 31;
+
+const COLUMNS: [i32; 4] = [1, 6, 9, 12];
+const ROWS: [i32; 10] = [1, 5, 8, 11, 14, 17, 20, 23, 26, 29];
+
+const RING_TOP: usize = 3;
+const TUNNEL: usize = 4;
+const RING_BOTTOM: usize = 5;
+const START_ROW: usize = 7;
+const DOOR: TilePos = TilePos { x: 13, y: 12 };
+const START: TilePos = TilePos { x: 13, y: 23 };
+const TUNNEL_LENGTH: i32 = 6;
+
+// One in this many of the corridors a maze could do without are added
+// anyway, so there is more than one way around.
+const LOOP_CHANCE: u32 = 3;
+
+// How often to try again should a maze come out wrong.
+const ATTEMPTS: u32 = 16;
+
+// A crossing of a column and a row lane, by index.
+type Node = (usize, usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edge {
+    // To the next column to the right. From the last column it crosses the
+    // middle to its mirror image.
+    Right(Node),
+    Down(Node),
+}
+
+impl Edge {
+    fn nodes(self) -> (Node, Option<Node>) {
+        match self {
+            Edge::Right((x, y)) if x + 1 == COLUMNS.len() => ((x, y), None),
+            Edge::Right((x, y)) => ((x, y), Some((x + 1, y))),
+            Edge::Down((x, y)) => ((x, y), Some((x, y + 1))),
+        }
+    }
+}
+
+// Inside the ring is the house, and the tunnel is walled in along its length.
+fn is_node(node: Node) -> bool {
+    node != (COLUMNS.len() - 1, TUNNEL)
+}
+
+fn is_open_edge(edge: Edge) -> bool {
+    let (a, b) = edge.nodes();
+    let ends_in_house = !is_node(a) || b.is_some_and(|b| !is_node(b));
+    let crosses_tunnel = match edge {
+        Edge::Down((x, y)) => x == 0 && (y == TUNNEL || y + 1 == TUNNEL),
+        Edge::Right(_) => false,
+    };
+    !ends_in_house && !crosses_tunnel
+}
+
+// The ring around the house, the way into the tunnel and the row Pac-Man
+// starts on are always there.
+fn fixed_edges() -> Vec<Edge> {
+    let last = COLUMNS.len() - 1;
+    vec![
+        Edge::Right((2, RING_TOP)),
+        Edge::Right((last, RING_TOP)),
+        Edge::Down((2, RING_TOP)),
+        Edge::Down((2, TUNNEL)),
+        Edge::Right((2, RING_BOTTOM)),
+        Edge::Right((last, RING_BOTTOM)),
+        Edge::Right((0, TUNNEL)),
+        Edge::Right((1, TUNNEL)),
+        Edge::Right((last, START_ROW)),
+    ]
+}
+
+fn all_edges() -> Vec<Edge> {
+    let mut edges = Vec::new();
+    for y in 0..ROWS.len() {
+        for x in 0..COLUMNS.len() {
+            edges.push(Edge::Right((x, y)));
+            if y + 1 < ROWS.len() {
+                edges.push(Edge::Down((x, y)));
+            }
+        }
+    }
+    edges.retain(|&edge| is_open_edge(edge));
+    edges
+}
+
+// Corridors out of a node. The way through the middle counts once, even
+// though it leads back to the node's mirror image, and so does the tunnel.
+fn degree(edges: &[Edge], node: Node) -> usize {
+    let tunnel = if node == (0, TUNNEL) { 1 } else { 0 };
+    tunnel
+        + edges
+            .iter()
+            .filter(|edge| {
+                let (a, b) = edge.nodes();
+                a == node || b == Some(node)
+            })
+            .count()
+}
+
+fn find(parents: &mut [usize], index: usize) -> usize {
+    let mut root = index;
+    while parents[root] != root {
+        root = parents[root];
+    }
+    parents[index] = root;
+    root
+}
+
+// Joins two nodes' groups, returning false if they were joined already.
+fn join(parents: &mut [usize], a: Node, b: Option<Node>) -> bool {
+    let index = |(x, y): Node| y * COLUMNS.len() + x;
+    let b = match b {
+        Some(b) => b,
+        // Crossing the middle joins a node to its mirror image, which is
+        // the same as itself as far as the left half goes.
+        None => return true,
+    };
+    let (a, b) = (find(parents, index(a)), find(parents, index(b)));
+    parents[a] = b;
+    a != b
+}
+
+fn shuffle<T>(items: &mut [T], rng: &mut Rng) {
+    for i in (1..items.len()).rev() {
+        items.swap(i, rng.below(i as u32 + 1) as usize);
+    }
+}
+
+// Picks the corridors for the left half: every node connected to every
+// other, with at least two ways out of each.
+fn corridors(rng: &mut Rng) -> Vec<Edge> {
+    let mut parents = (0..COLUMNS.len() * ROWS.len()).collect::<Vec<_>>();
+    let mut edges = fixed_edges();
+    for &edge in edges.iter() {
+        let (a, b) = edge.nodes();
+        join(&mut parents, a, b);
+    }
+
+    let mut candidates = all_edges();
+    candidates.retain(|edge| !edges.contains(edge));
+    shuffle(&mut candidates, rng);
+
+    // A random spanning tree first, so everything can be reached.
+    let mut spare = Vec::new();
+    for edge in candidates {
+        let (a, b) = edge.nodes();
+        if b.is_some() && join(&mut parents, a, b) {
+            edges.push(edge);
+        } else {
+            spare.push(edge);
+        }
+    }
+
+    // Then dead ends are opened up and a few loops added.
+    let nodes = (0..ROWS.len()).flat_map(|y| (0..COLUMNS.len()).map(move |x| (x, y)));
+    for node in nodes.filter(|&node| is_node(node)) {
+        if degree(&edges, node) < 2 {
+            if let Some(index) = spare.iter().position(|edge| {
+                let (a, b) = edge.nodes();
+                a == node || b == Some(node)
+            }) {
+                edges.push(spare.remove(index));
+            }
+        }
+    }
+    for edge in spare {
+        if rng.below(LOOP_CHANCE) == 0 {
+            edges.push(edge);
+        }
+    }
+    edges
+}
+
+// Sets a tile and its mirror image.
+fn set_mirrored(maze: &mut Maze, pos: TilePos, tile: Tile, zone: Zone) {
+    maze.set(pos, tile, zone);
+    maze.set(TilePos::new(WIDTH - 1 - pos.x, pos.y), tile, zone);
+}
+
+fn carve(maze: &mut Maze, edges: &[Edge]) {
+    for &edge in edges {
+        let ((x, y), other) = edge.nodes();
+        let (to_x, to_y) = match (edge, other) {
+            (_, Some((other_x, other_y))) => (COLUMNS[other_x], ROWS[other_y]),
+            (_, None) => (WIDTH / 2, ROWS[y]),
+        };
+        for tile_y in ROWS[y]..=to_y {
+            for tile_x in COLUMNS[x]..=to_x {
+                set_mirrored(maze, TilePos::new(tile_x, tile_y), Tile::Pellet, Zone::Normal);
+            }
+        }
+    }
+}
+
+// Pellets everywhere except in the middle, around the house, and in the
+// tunnel. Ghosts can't turn up just above the house and on the start row,
+// like in the arcade.
+fn furnish(maze: &mut Maze, rng: &mut Rng) {
+    let middle = |pos: TilePos| (7..WIDTH - 7).contains(&pos.x) && (9..=19).contains(&pos.y);
+    let open = maze
+        .tiles()
+        .filter(|&(pos, tile)| tile.is_walkable() && pos.x < WIDTH / 2)
+        .map(|(pos, _)| pos)
+        .collect::<Vec<_>>();
+    for &pos in open.iter().filter(|&&pos| middle(pos)) {
+        set_mirrored(maze, pos, Tile::Empty, Zone::Normal);
+    }
+    for x in 0..=COLUMNS[2] {
+        let zone = if x < TUNNEL_LENGTH { Zone::Tunnel } else { Zone::Normal };
+        set_mirrored(maze, TilePos::new(x, ROWS[TUNNEL]), Tile::Empty, zone);
+    }
+    for x in 11..WIDTH / 2 {
+        set_mirrored(maze, TilePos::new(x, ROWS[RING_TOP]), Tile::Empty, Zone::NoUpTurn);
+        let pos = TilePos::new(x, START.y);
+        if maze.is_walkable(pos) {
+            let tile = if x < START.x { Tile::Pellet } else { Tile::Empty };
+            set_mirrored(maze, pos, tile, Zone::NoUpTurn);
+        }
+    }
+
+    // An energizer out towards each corner.
+    for &(top, bottom) in &[(1, 8), (20, 29)] {
+        let corner = open
+            .iter()
+            .filter(|pos| pos.x <= COLUMNS[1] && (top..=bottom).contains(&pos.y))
+            .collect::<Vec<_>>();
+        let pos = *corner[rng.below(corner.len() as u32) as usize];
+        set_mirrored(maze, pos, Tile::Energizer, Zone::Normal);
+    }
+}
+
+fn build(rng: &mut Rng) -> Maze {
+    let mut maze = Maze::filled(WIDTH, HEIGHT);
+    let edges = corridors(rng);
+    carve(&mut maze, &edges);
+    // Out through the sides from the end of the tunnel.
+    set_mirrored(&mut maze, TilePos::new(0, ROWS[TUNNEL]), Tile::Empty, Zone::Tunnel);
+    maze.place_house(DOOR);
+    maze.set_start(START);
+    furnish(&mut maze, rng);
+    maze
+}
+
+// A new maze for every seed, always the same one for the same seed. Every
+// maze passes `Maze::check`.
+pub fn generate(seed: u32) -> Maze {
+    let mut rng = Rng::new(seed);
+    for _ in 0..ATTEMPTS {
+        let maze = build(&mut rng);
+        let problems = maze.check();
+        match problems.first() {
+            None => return maze,
+            Some(problem) => warn!("Generated maze {} has {} problems: {}", seed, problems.len(), problem),
+        }
+    }
+    Maze::arcade()
+}
+
+// Everything `Maze::check` wants, and symmetric as well.
+pub fn check(maze: &Maze) -> Vec<Problem> {
+    let mut problems = maze.check();
+    for (pos, tile) in maze.tiles() {
+        let mirror = TilePos::new(maze.width() - 1 - pos.x, pos.y);
+        if maze.get(mirror) != tile && pos.x < maze.width() / 2 {
+            problems.push(Problem::Asymmetric(pos));
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maze::Direction;
+
+    #[test]
+    fn same_seed_same_maze() {
+        assert_eq!(generate(7), generate(7));
+        assert!(generate(7) != generate(8));
+    }
+
+    #[test]
+    fn mazes_are_playable() {
+        for seed in 0..200 {
+            let maze = generate(seed);
+            assert_eq!(check(&maze), Vec::new(), "seed {}:\n{}", seed, maze);
+            assert!(maze != Maze::arcade());
+
+            let energizers = maze.tiles().filter(|&(_, tile)| tile == Tile::Energizer).count();
+            assert_eq!(energizers, 4);
+            let tunnel = TilePos::new(0, ROWS[TUNNEL]);
+            assert!(maze.is_walkable(tunnel) && maze.is_walkable(tunnel.step(Direction::Left)));
+        }
+    }
+
+    #[test]
+    fn finds_asymmetry() {
+        let mut maze = generate(1);
+        maze.set(TilePos::new(0, 0), Tile::Pellet, Zone::Normal);
+        assert!(check(&maze).contains(&Problem::Asymmetric(TilePos::new(0, 0))));
+    }
+}
@@ -0,0 +1,162 @@
+use std::str::FromStr;
+
+use failure::{err_msg, Error};
+
+use ghost::GhostKind;
+use state::{Decode, Encode, StateReader, StateWriter};
+
+// Players that can play at the same time, one per controller.
+pub const MAX_PLAYERS: usize = 4;
+
+// What a ghost player gets for catching Pac-Man in versus.
+pub const CATCH_POINTS: u32 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    // Every player is a Pac-Man, sharing the lives.
+    Coop,
+    // The first player is Pac-Man, the others steer Blinky, Pinky and Inky.
+    Versus,
+}
+
+// What a player controls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    PacMan(usize),
+    Ghost(GhostKind),
+}
+
+// Local players on one maze at the same time. Not part of the arcade game,
+// which only ever has one Pac-Man on screen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Party {
+    mode: Mode,
+    points: Vec<u32>,
+}
+
+impl Party {
+    pub fn new(mode: Mode, players: usize) -> Party {
+        Party {
+            mode,
+            points: vec![0; players.clamp(1, MAX_PLAYERS)],
+        }
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn players(&self) -> usize {
+        self.points.len()
+    }
+
+    // Each player's own points. The shared score and lives stay in `Score`.
+    pub fn points(&self, player: usize) -> u32 {
+        self.points.get(player).cloned().unwrap_or(0)
+    }
+
+    pub fn pacmen(&self) -> usize {
+        match self.mode {
+            Mode::Coop => self.players(),
+            Mode::Versus => 1,
+        }
+    }
+
+    pub fn role(&self, player: usize) -> Role {
+        match self.mode {
+            Mode::Versus if player > 0 => Role::Ghost(GhostKind::ALL[player - 1]),
+            _ => Role::PacMan(player),
+        }
+    }
+
+    pub fn player_for(&self, role: Role) -> Option<usize> {
+        (0..self.players()).find(|&player| self.role(player) == role)
+    }
+
+    pub fn credit(&mut self, player: usize, points: u32) {
+        if let Some(total) = self.points.get_mut(player) {
+            *total += points;
+        }
+    }
+
+    // Back to nothing for a new game with the same players.
+    pub fn reset(&mut self) {
+        for points in self.points.iter_mut() {
+            *points = 0;
+        }
+    }
+}
+
+// Parses "coop 3" or "versus 2", the player count defaults to two.
+impl FromStr for Party {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Party, Error> {
+        let mut parts = s.split_whitespace();
+        let mode = match parts.next() {
+            Some("coop") => Mode::Coop,
+            Some("versus") => Mode::Versus,
+            _ => return Err(err_msg(format!("Unknown party mode {:?}", s))),
+        };
+        let players = match parts.next() {
+            Some(players) => players
+                .parse::<usize>()
+                .ok()
+                .filter(|players| (2..=MAX_PLAYERS).contains(players))
+                .ok_or_else(|| err_msg(format!("Expected 2 to {} players, found {}", MAX_PLAYERS, players)))?,
+            None => 2,
+        };
+        Ok(Party::new(mode, players))
+    }
+}
+
+impl Encode for Party {
+    fn encode(&self, w: &mut StateWriter) {
+        w.write_u8(match self.mode {
+            Mode::Coop => 0,
+            Mode::Versus => 1,
+        });
+        w.write_u8(self.points.len() as u8);
+        for &points in self.points.iter() {
+            w.write_u32(points);
+        }
+    }
+}
+
+impl Decode for Party {
+    fn decode(r: &mut StateReader) -> Result<Party, Error> {
+        let mode = match r.read_u8()? {
+            0 => Mode::Coop,
+            1 => Mode::Versus,
+            value => return Err(err_msg(format!("Invalid party mode {}", value))),
+        };
+        let players = r.read_u8()? as usize;
+        if players == 0 || players > MAX_PLAYERS {
+            return Err(err_msg(format!("Invalid player count {}", players)));
+        }
+        let points = (0..players).map(|_| r.read_u32()).collect::<Result<_, _>>()?;
+        Ok(Party { mode, points })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roles_follow_the_mode() {
+        let coop: Party = "coop 3".parse().unwrap();
+        assert_eq!(coop.pacmen(), 3);
+        assert_eq!(coop.role(2), Role::PacMan(2));
+
+        let versus: Party = "versus 4".parse().unwrap();
+        assert_eq!(versus.pacmen(), 1);
+        assert_eq!(versus.role(0), Role::PacMan(0));
+        assert_eq!(versus.role(3), Role::Ghost(GhostKind::Inky));
+        assert_eq!(versus.player_for(Role::Ghost(GhostKind::Blinky)), Some(1));
+        assert_eq!(versus.player_for(Role::Ghost(GhostKind::Clyde)), None);
+
+        assert!("versus 5".parse::<Party>().is_err());
+        assert!("tag".parse::<Party>().is_err());
+    }
+}
@@ -0,0 +1,755 @@
+use std::collections::VecDeque;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+
+use failure::{err_msg, Error};
+
+use multiplayer::MAX_PLAYERS;
+use rng::Rng;
+use simulation::Simulation;
+use storage;
+use {Buttons, Input};
+
+// Frames of local input delay by default. Hides up to this much latency
+// without any rollback at all.
+pub const DEFAULT_INPUT_DELAY: u32 = 2;
+
+// How far past the last frame with the peer's input we run on predictions.
+// Past that we wait, since every frame of it may have to be simulated again.
+pub const MAX_PREDICTION: u32 = 8;
+
+// How often the frame advantage and the state are checked, and the most
+// frames we wait at once to let the peer catch up.
+const SYNC_INTERVAL: u32 = 60;
+const MAX_SYNC_WAIT: i32 = 8;
+
+// Our hashes the peer may still ask about. It is never more than a few
+// frames behind, but its hash may take a while to arrive.
+const MAX_HASHES: usize = 4;
+
+const MAGIC: &[u8; 4] = b"PMNP";
+const HEADER_SIZE: usize = 33;
+const CHECKSUM_SIZE: usize = 4;
+const MAX_INPUTS: usize = 255;
+
+// Anything that can run a frame and go back to an earlier one. Everything
+// else about netplay only ever looks at the buttons.
+pub trait Rollback {
+    fn save_state(&self) -> Vec<u8>;
+    fn load_state(&mut self, data: &[u8]) -> Result<(), Error>;
+    fn advance(&mut self, input: &Input);
+    // Equal on both sides only if they start out the same, including
+    // whatever the save state leaves out.
+    fn setup_hash(&self) -> u32;
+}
+
+impl Rollback for Simulation {
+    fn save_state(&self) -> Vec<u8> {
+        Simulation::save_state(self)
+    }
+
+    fn setup_hash(&self) -> u32 {
+        Simulation::setup_hash(self)
+    }
+
+    fn load_state(&mut self, data: &[u8]) -> Result<(), Error> {
+        Simulation::load_state(self, data)
+    }
+
+    fn advance(&mut self, input: &Input) {
+        self.update(input);
+    }
+}
+
+// Moves packets to the peer. Packets may get lost or arrive late, but a
+// transport never hands back a packet that was sent in parts.
+pub trait Transport {
+    fn send(&mut self, packet: &[u8]) -> Result<(), Error>;
+    // The next packet that arrived, None once there are no more for now.
+    fn receive(&mut self) -> Result<Option<Vec<u8>>, Error>;
+    // Called once per frame, for transports that hold packets back.
+    fn tick(&mut self) {}
+}
+
+pub struct UdpTransport {
+    socket: UdpSocket,
+    peer: SocketAddr,
+}
+
+impl UdpTransport {
+    pub fn bind<A: ToSocketAddrs, P: ToSocketAddrs>(local: A, peer: P) -> Result<UdpTransport, Error> {
+        let socket = UdpSocket::bind(local)?;
+        socket.set_nonblocking(true)?;
+        let peer = peer
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| err_msg("No address for the peer"))?;
+        Ok(UdpTransport { socket, peer })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    pub fn set_peer(&mut self, peer: SocketAddr) {
+        self.peer = peer;
+    }
+}
+
+impl Transport for UdpTransport {
+    fn send(&mut self, packet: &[u8]) -> Result<(), Error> {
+        match self.socket.send_to(packet, self.peer) {
+            Ok(_) => Ok(()),
+            // Nobody listening yet, or a full buffer. Either way the next
+            // frame sends the same inputs again.
+            Err(ref e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::ConnectionRefused) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn receive(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        let mut buffer = [0; 1024];
+        loop {
+            match self.socket.recv_from(&mut buffer) {
+                Ok((len, from)) if from == self.peer => return Ok(Some(buffer[..len].to_vec())),
+                Ok(_) => continue,
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => return Ok(None),
+                Err(ref e) if e.kind() == ErrorKind::ConnectionRefused => return Ok(None),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+// Holds outgoing packets back for a number of frames and drops some of them,
+// to try bad connections on one machine. Seeded, so a run can be repeated.
+pub struct LossyTransport<T> {
+    inner: T,
+    delay: u32,
+    loss_percent: u32,
+    rng: Rng,
+    clock: u32,
+    outgoing: VecDeque<(u32, Vec<u8>)>,
+}
+
+impl<T: Transport> LossyTransport<T> {
+    pub fn new(inner: T, delay: u32, loss_percent: u32, seed: u32) -> LossyTransport<T> {
+        LossyTransport {
+            inner,
+            delay,
+            loss_percent: loss_percent.min(100),
+            rng: Rng::new(seed),
+            clock: 0,
+            outgoing: VecDeque::new(),
+        }
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T: Transport> Transport for LossyTransport<T> {
+    fn send(&mut self, packet: &[u8]) -> Result<(), Error> {
+        if self.rng.below(100) >= self.loss_percent {
+            self.outgoing.push_back((self.clock + self.delay, packet.to_vec()));
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        self.inner.receive()
+    }
+
+    fn tick(&mut self) {
+        self.clock += 1;
+        while self.outgoing.front().is_some_and(|&(due, _)| due <= self.clock) {
+            let (_, packet) = self.outgoing.pop_front().unwrap();
+            if let Err(e) = self.inner.send(&packet) {
+                warn!("Failed to send a delayed packet: {}", e);
+            }
+        }
+        self.inner.tick();
+    }
+}
+
+// What one side tells the other every frame. Inputs are sent again until the
+// peer acknowledges them, so lost packets only cost time.
+//
+//   magic "PMNP"
+//   frame u32, the next frame the sender will simulate
+//   advantage i32, how many frames the sender thinks it is ahead
+//   ack u32, how many of the receiver's inputs the sender has
+//   start u32, the frame of the first input
+//   setup u32, the sender's `Rollback::setup_hash` from before frame 0
+//   checked u32, the latest confirmed frame the sender hashed, 0 for none
+//   hash u32, the FNV-1a hash of the save state before that frame
+//   count u8 and that many buttons u8
+//   FNV-1a checksum u32
+//
+// All integers are little endian.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Packet {
+    frame: u32,
+    advantage: i32,
+    ack: u32,
+    start: u32,
+    setup: u32,
+    checked: u32,
+    hash: u32,
+    inputs: Vec<Buttons>,
+}
+
+impl Packet {
+    fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(HEADER_SIZE + self.inputs.len() + CHECKSUM_SIZE);
+        data.extend_from_slice(MAGIC);
+        data.extend_from_slice(&self.frame.to_le_bytes());
+        data.extend_from_slice(&self.advantage.to_le_bytes());
+        data.extend_from_slice(&self.ack.to_le_bytes());
+        data.extend_from_slice(&self.start.to_le_bytes());
+        data.extend_from_slice(&self.setup.to_le_bytes());
+        data.extend_from_slice(&self.checked.to_le_bytes());
+        data.extend_from_slice(&self.hash.to_le_bytes());
+        data.push(self.inputs.len() as u8);
+        data.extend(self.inputs.iter().map(|buttons| buttons.bits()));
+        let checksum = storage::fnv1a(&data);
+        data.extend_from_slice(&checksum.to_le_bytes());
+        data
+    }
+
+    fn decode(data: &[u8]) -> Result<Packet, Error> {
+        if data.len() < HEADER_SIZE + CHECKSUM_SIZE {
+            return Err(err_msg("Packet is truncated"));
+        }
+        let (body, checksum) = data.split_at(data.len() - CHECKSUM_SIZE);
+        let checksum = u32::from_le_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]);
+        if storage::fnv1a(body) != checksum {
+            return Err(err_msg("Checksum mismatch"));
+        }
+        if &body[0..4] != MAGIC {
+            return Err(err_msg("Invalid magic"));
+        }
+
+        let u32_at = |i: usize| u32::from_le_bytes([body[i], body[i + 1], body[i + 2], body[i + 3]]);
+        let count = body[32] as usize;
+        if body.len() != HEADER_SIZE + count {
+            return Err(err_msg(format!("Expected {} inputs, found {}", count, body.len() - HEADER_SIZE)));
+        }
+        Ok(Packet {
+            frame: u32_at(4),
+            advantage: u32_at(8) as i32,
+            ack: u32_at(12),
+            start: u32_at(16),
+            setup: u32_at(20),
+            checked: u32_at(24),
+            hash: u32_at(28),
+            inputs: body[HEADER_SIZE..].iter().map(|&bits| Buttons::from_bits(bits)).collect(),
+        })
+    }
+}
+
+// One side of a two player game over the network. Both sides run the whole
+// game and only send their buttons. Frames the peer's buttons haven't arrived
+// for yet are played with their last known buttons. When the real ones turn
+// out different, the game goes back to the last frame that was right and
+// plays the rest again.
+pub struct Session<T> {
+    transport: T,
+    local_player: usize,
+    input: Input,
+    // The next frame to simulate.
+    frame: u32,
+    // Local buttons by frame, already `input_delay` frames ahead of `frame`.
+    local_inputs: Vec<Buttons>,
+    // The peer's buttons as far as they arrived, and what was used for them
+    // on each frame simulated so far.
+    remote_inputs: Vec<Buttons>,
+    predicted: Vec<Buttons>,
+    // The earliest frame played with a wrong guess.
+    mispredicted: Option<u32>,
+    // The state before each frame that may still be rolled back.
+    states: VecDeque<(u32, Vec<u8>)>,
+    // How many of our inputs the peer has, and what it last said about itself.
+    remote_ack: u32,
+    remote_frame: u32,
+    remote_advantage: i32,
+    wait_frames: u32,
+    rollbacks: u32,
+    // No frame is played until the peer turns out to start from the same
+    // game as we do.
+    setup_hash: Option<u32>,
+    remote_setup_hash: Option<u32>,
+    // Hashes of the state before every SYNC_INTERVAL frames, ours while
+    // they may still be rolled back and the latest confirmed one from the
+    // peer, to catch the two sides drifting apart.
+    hashes: VecDeque<(u32, u32)>,
+    remote_hash: (u32, u32),
+    checked_frame: u32,
+}
+
+impl<T: Transport> Session<T> {
+    // `local_player` is 0 on one side and 1 on the other.
+    pub fn new(transport: T, local_player: usize) -> Session<T> {
+        let mut session = Session {
+            transport,
+            local_player: local_player.min(1),
+            input: Input::new(),
+            frame: 0,
+            local_inputs: Vec::new(),
+            remote_inputs: Vec::new(),
+            predicted: Vec::new(),
+            mispredicted: None,
+            states: VecDeque::new(),
+            remote_ack: 0,
+            remote_frame: 0,
+            remote_advantage: 0,
+            wait_frames: 0,
+            rollbacks: 0,
+            setup_hash: None,
+            remote_setup_hash: None,
+            hashes: VecDeque::new(),
+            remote_hash: (0, 0),
+            checked_frame: 0,
+        };
+        session.set_input_delay(DEFAULT_INPUT_DELAY);
+        session
+    }
+
+    // Only takes effect before the first frame.
+    pub fn set_input_delay(&mut self, frames: u32) {
+        if self.frame == 0 {
+            self.local_inputs = vec![Buttons::empty(); frames as usize];
+        }
+    }
+
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    pub fn local_player(&self) -> usize {
+        self.local_player
+    }
+
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    // Frames before this one have both players' real buttons and won't change.
+    pub fn confirmed_frame(&self) -> u32 {
+        (self.remote_inputs.len().min(self.local_inputs.len()) as u32).min(self.frame)
+    }
+
+    // How many times the game went back to fix a wrong guess.
+    pub fn rollbacks(&self) -> u32 {
+        self.rollbacks
+    }
+
+    // How many frames we are ahead of the peer, as far as we know.
+    pub fn frame_advantage(&self) -> i32 {
+        self.frame as i32 - self.remote_frame as i32
+    }
+
+    // Plays the next frame with the local player holding `buttons`, unless we
+    // are too far ahead of the peer. Returns whether a frame was played.
+    pub fn update<R: Rollback>(&mut self, game: &mut R, buttons: Buttons) -> Result<bool, Error> {
+        self.transport.tick();
+        self.poll(game)?;
+
+        let advanced = if self.remote_setup_hash.is_none() {
+            false
+        } else if self.wait_frames > 0 {
+            self.wait_frames -= 1;
+            false
+        } else if self.frame >= self.remote_inputs.len() as u32 + MAX_PREDICTION {
+            false
+        } else {
+            self.local_inputs.push(buttons);
+            self.simulate(game);
+            self.sync();
+            true
+        };
+
+        self.send()?;
+        Ok(advanced)
+    }
+
+    // Takes in what the peer sent and fixes up any wrong guesses, without
+    // playing a new frame.
+    pub fn poll<R: Rollback>(&mut self, game: &mut R) -> Result<(), Error> {
+        if self.setup_hash.is_none() {
+            self.setup_hash = Some(game.setup_hash());
+        }
+        self.receive()?;
+        if let Some(from) = self.mispredicted.take() {
+            self.roll_back(game, from)?;
+        }
+        self.check_hash()
+    }
+
+    // Compares the peer's latest hash with ours once our state for that
+    // frame is final too.
+    fn check_hash(&mut self) -> Result<(), Error> {
+        let (frame, hash) = self.remote_hash;
+        if frame <= self.checked_frame || frame > self.confirmed_frame() {
+            return Ok(());
+        }
+        let ours = match self.hashes.iter().find(|&&(kept, _)| kept == frame) {
+            Some(&(_, ours)) => ours,
+            None => return Ok(()),
+        };
+        self.checked_frame = frame;
+        if ours != hash {
+            return Err(err_msg(format!("Out of sync with the peer at frame {}", frame)));
+        }
+        Ok(())
+    }
+
+    // The latest of our hashes that can't change anymore.
+    fn confirmed_hash(&self) -> (u32, u32) {
+        let confirmed = self.confirmed_frame();
+        self.hashes
+            .iter()
+            .rev()
+            .find(|&&(frame, _)| frame <= confirmed)
+            .cloned()
+            .unwrap_or((0, 0))
+    }
+
+    fn receive(&mut self) -> Result<(), Error> {
+        while let Some(data) = self.transport.receive()? {
+            let packet = match Packet::decode(&data) {
+                Ok(packet) => packet,
+                Err(e) => {
+                    warn!("Dropping packet: {}", e);
+                    continue;
+                }
+            };
+
+            if Some(packet.setup) != self.setup_hash {
+                return Err(err_msg("The peer started a different game, check the maze, levels and settings"));
+            }
+            self.remote_setup_hash = Some(packet.setup);
+            if packet.checked > self.remote_hash.0 {
+                self.remote_hash = (packet.checked, packet.hash);
+            }
+
+            if packet.frame >= self.remote_frame {
+                self.remote_frame = packet.frame;
+                self.remote_advantage = packet.advantage;
+            }
+            self.remote_ack = self.remote_ack.max(packet.ack);
+
+            for (i, &buttons) in packet.inputs.iter().enumerate() {
+                let frame = packet.start + i as u32;
+                let next = self.remote_inputs.len() as u32;
+                if frame < next {
+                    continue;
+                } else if frame > next {
+                    break;
+                }
+                if self.predicted.get(frame as usize).is_some_and(|&guess| guess != buttons) {
+                    self.mispredicted = Some(self.mispredicted.map_or(frame, |from| from.min(frame)));
+                }
+                self.remote_inputs.push(buttons);
+            }
+        }
+        Ok(())
+    }
+
+    fn send(&mut self) -> Result<(), Error> {
+        let start = (self.remote_ack as usize).min(self.local_inputs.len());
+        let end = self.local_inputs.len().min(start + MAX_INPUTS);
+        let (checked, hash) = self.confirmed_hash();
+        let packet = Packet {
+            frame: self.frame,
+            advantage: self.frame_advantage(),
+            ack: self.remote_inputs.len() as u32,
+            start: start as u32,
+            setup: self.setup_hash.unwrap_or_default(),
+            checked,
+            hash,
+            inputs: self.local_inputs[start..end].to_vec(),
+        };
+        self.transport.send(&packet.encode())
+    }
+
+    // The buttons for `frame`, guessing the peer's if they haven't arrived.
+    fn buttons(&self, frame: u32) -> [Buttons; MAX_PLAYERS] {
+        let frame = frame as usize;
+        let remote = match self.remote_inputs.get(frame) {
+            Some(&buttons) => buttons,
+            None => self.remote_inputs.last().cloned().unwrap_or_default(),
+        };
+        let mut buttons = [Buttons::empty(); MAX_PLAYERS];
+        buttons[self.local_player] = self.local_inputs[frame];
+        buttons[1 - self.local_player] = remote;
+        buttons
+    }
+
+    fn simulate<R: Rollback>(&mut self, game: &mut R) {
+        let state = game.save_state();
+        if self.frame > 0 && self.frame.is_multiple_of(SYNC_INTERVAL) {
+            let frame = self.frame;
+            self.hashes.retain(|&(kept, _)| kept < frame);
+            self.hashes.push_back((frame, storage::fnv1a(&state)));
+            while self.hashes.len() > MAX_HASHES {
+                self.hashes.pop_front();
+            }
+        }
+        self.states.push_back((self.frame, state));
+        while self.states.len() > MAX_PREDICTION as usize + 1 {
+            self.states.pop_front();
+        }
+
+        let buttons = self.buttons(self.frame);
+        self.predicted.truncate(self.frame as usize);
+        self.predicted.push(buttons[1 - self.local_player]);
+        self.input.update_players(&buttons);
+        game.advance(&self.input);
+        self.frame += 1;
+    }
+
+    fn roll_back<R: Rollback>(&mut self, game: &mut R, from: u32) -> Result<(), Error> {
+        let position = self
+            .states
+            .iter()
+            .position(|&(frame, _)| frame == from)
+            .ok_or_else(|| err_msg(format!("No state kept for frame {}", from)))?;
+        game.load_state(&self.states[position].1)?;
+        self.states.truncate(position);
+
+        // The tick before still counts for buttons that were just pressed.
+        self.input = Input::new();
+        if from > 0 {
+            let buttons = self.buttons(from - 1);
+            self.input.update_players(&buttons);
+        }
+
+        let target = self.frame;
+        self.frame = from;
+        while self.frame < target {
+            self.simulate(game);
+        }
+        self.rollbacks += 1;
+        Ok(())
+    }
+
+    // Both sides see the other a little behind because of latency, so half
+    // the difference between the two views is how far ahead we really are.
+    // Waiting that out keeps one side from doing all the rolling back.
+    fn sync(&mut self) {
+        if !self.frame.is_multiple_of(SYNC_INTERVAL) {
+            return;
+        }
+        let wait = (self.frame_advantage() - self.remote_advantage) / 2;
+        if wait > 0 {
+            debug!("Waiting {} frames for the peer to catch up", wait);
+            self.wait_frames = wait.min(MAX_SYNC_WAIT) as u32;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use level::LevelTable;
+    use maze::Maze;
+    use multiplayer::{Mode, Party};
+    use world::World;
+
+    fn versus() -> Simulation {
+        let mut world = World::new(LevelTable::arcade(), Maze::arcade(), 7);
+        world.set_party_setup(Some(Party::new(Mode::Versus, 2)));
+        Simulation::new(world)
+    }
+
+    // Changes often enough to make guessing go wrong.
+    fn buttons(player: usize, frame: u32) -> Buttons {
+        if frame < 4 {
+            return if player == 0 { Buttons::START } else { Buttons::empty() };
+        }
+        let dirs = [Buttons::LEFT, Buttons::UP, Buttons::RIGHT, Buttons::DOWN];
+        dirs[((frame / (23 + 17 * player as u32)) as usize + player) % 4]
+    }
+
+    // Returns how often the two sides rolled back.
+    fn play(delay: u32, loss_percent: u32, frames: u32) -> u32 {
+        let mut a = UdpTransport::bind("127.0.0.1:0", "127.0.0.1:9").unwrap();
+        let mut b = UdpTransport::bind("127.0.0.1:0", "127.0.0.1:9").unwrap();
+        a.set_peer(b.local_addr().unwrap());
+        b.set_peer(a.local_addr().unwrap());
+        let mut sessions = [
+            Session::new(LossyTransport::new(a, delay, loss_percent, 1), 0),
+            Session::new(LossyTransport::new(b, delay, loss_percent, 2), 1),
+        ];
+        let mut games = [versus(), versus()];
+
+        let mut ticks = 0;
+        while sessions.iter().any(|session| session.frame() < frames) {
+            for (session, game) in sessions.iter_mut().zip(games.iter_mut()) {
+                if session.frame() < frames {
+                    let player = session.local_player();
+                    let held = buttons(player, session.frame());
+                    session.update(game, held).unwrap();
+                }
+            }
+            ticks += 1;
+            assert!(ticks < frames * 4, "Stuck at {:?}", sessions.iter().map(|s| s.frame()).collect::<Vec<_>>());
+        }
+
+        // Keep exchanging until both have all of the other's buttons.
+        while sessions.iter().any(|session| session.confirmed_frame() < frames) {
+            for (session, game) in sessions.iter_mut().zip(games.iter_mut()) {
+                session.transport.tick();
+                session.poll(game).unwrap();
+                session.send().unwrap();
+            }
+            ticks += 1;
+            assert!(ticks < frames * 8, "Never confirmed");
+        }
+
+        let mut expected = versus();
+        let mut input = Input::new();
+        for frame in 0..frames {
+            let local = |player| match frame.checked_sub(DEFAULT_INPUT_DELAY) {
+                Some(frame) => buttons(player, frame),
+                None => Buttons::empty(),
+            };
+            input.update_players(&[local(0), local(1)]);
+            expected.update(&input);
+        }
+        assert!(expected.world().party().is_some());
+        for game in games.iter() {
+            assert_eq!(game.state_hash(), expected.state_hash());
+        }
+        sessions.iter().map(Session::rollbacks).sum()
+    }
+
+    #[test]
+    fn packets_round_trip() {
+        let packet = Packet {
+            frame: 120,
+            advantage: -3,
+            ack: 117,
+            start: 115,
+            setup: 0xdead_beef,
+            checked: 60,
+            hash: 12345,
+            inputs: vec![Buttons::LEFT, Buttons::UP | Buttons::START],
+        };
+        let data = packet.encode();
+        assert_eq!(Packet::decode(&data).unwrap(), packet);
+
+        let mut corrupt = data.clone();
+        corrupt[HEADER_SIZE] ^= 1;
+        assert!(Packet::decode(&corrupt).is_err());
+        assert!(Packet::decode(&data[..10]).is_err());
+    }
+
+    #[test]
+    fn peers_agree_over_a_clean_connection() {
+        play(0, 0, 600);
+    }
+
+    #[test]
+    fn peers_agree_despite_latency_and_loss() {
+        assert!(play(5, 20, 600) > 0);
+    }
+
+    struct Loopback(VecDeque<Vec<u8>>);
+    impl Transport for Loopback {
+        fn send(&mut self, _packet: &[u8]) -> Result<(), Error> {
+            Ok(())
+        }
+        fn receive(&mut self) -> Result<Option<Vec<u8>>, Error> {
+            Ok(self.0.pop_front())
+        }
+    }
+
+    // What a peer says before it has any input to send.
+    fn hello(setup: u32) -> Packet {
+        Packet {
+            frame: 0,
+            advantage: 0,
+            ack: 0,
+            start: 0,
+            setup,
+            checked: 0,
+            hash: 0,
+            inputs: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn waits_for_a_peer_with_the_same_game() {
+        let mut session = Session::new(Loopback(VecDeque::new()), 0);
+        let mut game = versus();
+        assert!(!session.update(&mut game, Buttons::START).unwrap());
+        assert_eq!(session.frame(), 0);
+
+        let mut other = versus();
+        other.restart(8);
+        session.transport.0.push_back(hello(other.setup_hash()).encode());
+        assert!(session.update(&mut game, Buttons::START).is_err());
+
+        // Same state, but a different level table.
+        let levels = LevelTable::parse("1 cherries 200 80 90 75 50 40 6 5 20 80 10 85").unwrap();
+        let other = Simulation::new(World::new(levels, Maze::arcade(), game.seed()));
+        assert_eq!(other.state_hash(), game.state_hash());
+        let mut session = Session::new(Loopback(VecDeque::new()), 0);
+        session.transport.0.push_back(hello(other.setup_hash()).encode());
+        assert!(session.update(&mut game, Buttons::START).is_err());
+
+        let mut session = Session::new(Loopback(VecDeque::new()), 0);
+        session.transport.0.push_back(hello(game.setup_hash()).encode());
+        assert!(session.update(&mut game, Buttons::START).unwrap());
+    }
+
+    #[test]
+    fn notices_the_peer_drifting_apart() {
+        let mut session = Session::new(Loopback(VecDeque::new()), 0);
+        let mut game = versus();
+        let setup = game.setup_hash();
+        session.transport.0.push_back(hello(setup).encode());
+        for frame in 0..=SYNC_INTERVAL {
+            let mut packet = hello(setup);
+            packet.frame = frame;
+            packet.start = frame;
+            packet.inputs = vec![Buttons::empty()];
+            session.transport.0.push_back(packet.encode());
+            session.update(&mut game, Buttons::empty()).unwrap();
+        }
+        assert_eq!(session.confirmed_hash().0, SYNC_INTERVAL);
+
+        let mut packet = hello(setup);
+        packet.checked = SYNC_INTERVAL;
+        packet.hash = session.confirmed_hash().1 ^ 1;
+        session.transport.0.push_back(packet.encode());
+        assert!(session.update(&mut game, Buttons::empty()).is_err());
+    }
+
+    #[test]
+    fn wrong_guesses_roll_back() {
+        let mut session = Session::new(Loopback(VecDeque::new()), 0);
+        let mut game = versus();
+        let setup = game.setup_hash();
+        session.transport.0.push_back(hello(setup).encode());
+        for _ in 0..MAX_PREDICTION + 2 {
+            session.update(&mut game, Buttons::START).unwrap();
+        }
+        // Can't guess any further.
+        assert_eq!(session.frame(), MAX_PREDICTION);
+
+        let late = Packet {
+            frame: 4,
+            inputs: vec![Buttons::empty(), Buttons::empty(), Buttons::LEFT, Buttons::LEFT],
+            ..hello(setup)
+        };
+        session.transport.0.push_back(late.encode());
+        session.update(&mut game, Buttons::empty()).unwrap();
+        assert_eq!(session.rollbacks(), 1);
+        assert_eq!(session.confirmed_frame(), 4);
+        assert_eq!(session.frame(), MAX_PREDICTION + 1);
+    }
+}
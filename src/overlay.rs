@@ -0,0 +1,245 @@
+use std::time::{Duration, Instant};
+
+use failure::Error;
+
+use canvas::{MAZE_TOP, VIEW_WIDTH};
+use ghost::{self, GhostKind, GhostMode, GhostState, ModeTimer};
+use maze::TILE_SIZE;
+use shapes;
+use world::{World, TICKS_PER_SECOND};
+use {Rect, Renderer, Rgb};
+
+const GRID: Rgb = Rgb(0x30, 0x30, 0x30);
+const TEXT: Rgb = Rgb::WHITE;
+const PANEL: Rgb = Rgb(0, 0, 0);
+
+// A tiny font for debug text, the sheet is missing too many letters. Each
+// glyph is 3x5 pixels, a row of three bits at a time from the top.
+const GLYPH_WIDTH: i32 = 3;
+const GLYPH_HEIGHT: i32 = 5;
+const LINE_HEIGHT: i32 = GLYPH_HEIGHT + 2;
+const GLYPHS: &[(char, u16)] = &[
+    ('0', 0b111_101_101_101_111), ('1', 0b010_110_010_010_111), ('2', 0b111_001_111_100_111),
+    ('3', 0b111_001_111_001_111), ('4', 0b101_101_111_001_001), ('5', 0b111_100_111_001_111),
+    ('6', 0b111_100_111_101_111), ('7', 0b111_001_001_001_001), ('8', 0b111_101_111_101_111),
+    ('9', 0b111_101_111_001_111), ('A', 0b010_101_111_101_101), ('B', 0b110_101_110_101_110),
+    ('C', 0b011_100_100_100_011), ('D', 0b110_101_101_101_110), ('E', 0b111_100_110_100_111),
+    ('F', 0b111_100_110_100_100), ('G', 0b011_100_101_101_011), ('H', 0b101_101_111_101_101),
+    ('I', 0b111_010_010_010_111), ('J', 0b001_001_001_101_010), ('K', 0b101_101_110_101_101),
+    ('L', 0b100_100_100_100_111), ('M', 0b101_111_111_101_101), ('N', 0b110_101_101_101_101),
+    ('O', 0b010_101_101_101_010), ('P', 0b110_101_110_100_100), ('Q', 0b010_101_101_110_011),
+    ('R', 0b110_101_110_101_101), ('S', 0b011_100_010_001_110), ('T', 0b111_010_010_010_010),
+    ('U', 0b101_101_101_101_111), ('V', 0b101_101_101_101_010), ('W', 0b101_101_111_111_101),
+    ('X', 0b101_101_010_101_101), ('Y', 0b101_101_010_010_010), ('Z', 0b111_001_010_100_111),
+    (':', 0b000_010_000_010_000), ('/', 0b001_001_010_100_100), ('.', 0b000_000_000_000_010),
+    ('-', 0b000_000_111_000_000),
+];
+
+fn glyph(c: char) -> Option<u16> {
+    let c = c.to_ascii_uppercase();
+    GLYPHS.iter().find(|&&(glyph, _)| glyph == c).map(|&(_, bits)| bits)
+}
+
+// Adds the pixels of a line of debug text as little squares. Spaces and
+// characters without a glyph are left blank.
+pub fn text(vertices: &mut Vec<f32>, text: &str, x: i32, y: i32) {
+    for (i, c) in text.chars().enumerate() {
+        let bits = match glyph(c) {
+            Some(bits) => bits,
+            None => continue,
+        };
+        let left = x + i as i32 * (GLYPH_WIDTH + 1);
+        for row in 0..GLYPH_HEIGHT {
+            for col in 0..GLYPH_WIDTH {
+                let bit = (GLYPH_HEIGHT - 1 - row) * GLYPH_WIDTH + (GLYPH_WIDTH - 1 - col);
+                if bits & (1 << bit) != 0 {
+                    shapes::rect(vertices, Rect::new(left + col, y + row, 1, 1));
+                }
+            }
+        }
+    }
+}
+
+// The colour of each ghost's body in the arcade.
+fn ghost_color(kind: GhostKind) -> Rgb {
+    match kind {
+        GhostKind::Blinky => Rgb(0xff, 0x00, 0x00),
+        GhostKind::Pinky => Rgb(0xff, 0xb8, 0xff),
+        GhostKind::Inky => Rgb(0x00, 0xff, 0xff),
+        GhostKind::Clyde => Rgb(0xff, 0xb8, 0x51),
+    }
+}
+
+// "SCATTER 6.5" with the seconds left, or just "CHASE" once it lasts
+// forever.
+pub fn mode_text(timer: &ModeTimer) -> String {
+    let mode = match timer.mode() {
+        GhostMode::Scatter => "SCATTER",
+        GhostMode::Chase => "CHASE",
+    };
+    match timer.remaining() {
+        Some(ticks) => format!("{} {:.1}", mode, ticks as f32 / TICKS_PER_SECOND as f32),
+        None => mode.to_string(),
+    }
+}
+
+// Frames drawn over the last whole second, from the wall clock. Only the
+// overlay looks at it, the game itself never does.
+#[derive(Debug, Clone, Default)]
+pub struct FrameRate {
+    since: Option<Instant>,
+    frames: u32,
+    fps: u32,
+}
+
+impl FrameRate {
+    pub fn frame(&mut self, now: Instant) {
+        let since = match self.since {
+            Some(since) => since,
+            None => {
+                self.since = Some(now);
+                return;
+            }
+        };
+        self.frames += 1;
+        if now.duration_since(since) >= Duration::from_secs(1) {
+            self.fps = self.frames;
+            self.frames = 0;
+            self.since = Some(now);
+        }
+    }
+
+    pub fn fps(&self) -> u32 {
+        self.fps
+    }
+}
+
+// What the ghosts are up to, drawn over the game: the tile grid, where each
+// ghost is headed, the scatter and chase timer, the counters that let ghosts
+// out of the house, and the frame rate.
+#[derive(Debug, Clone, Default)]
+pub struct DebugOverlay {
+    visible: bool,
+    frame_rate: FrameRate,
+}
+
+impl DebugOverlay {
+    pub fn new() -> DebugOverlay {
+        DebugOverlay::default()
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    // Starts counting frames afresh when shown, so old ones don't count.
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+        self.frame_rate = FrameRate::default();
+    }
+
+    pub fn render(&mut self, renderer: &mut Renderer, world: &World) -> Result<(), Error> {
+        if !self.visible {
+            return Ok(());
+        }
+        self.frame_rate.frame(Instant::now());
+
+        // All of it is shapes, drawn together in one call.
+        self.grid(renderer, world)?;
+        self.targets(renderer, world)?;
+        self.panel(renderer, world)?;
+        renderer.flush_shapes()
+    }
+
+    fn grid(&self, renderer: &mut Renderer, world: &World) -> Result<(), Error> {
+        let (width, height) = (world.maze().width() * TILE_SIZE, world.maze().height() * TILE_SIZE);
+        let mut vertices = Vec::new();
+        for x in (0..=width).step_by(TILE_SIZE as usize) {
+            shapes::rect(&mut vertices, Rect::new(x, MAZE_TOP, 1, height));
+        }
+        for y in (0..=height).step_by(TILE_SIZE as usize) {
+            shapes::rect(&mut vertices, Rect::new(0, MAZE_TOP + y, width, 1));
+        }
+        renderer.render_triangles(&vertices, GRID)
+    }
+
+    // A line from each ghost to the middle of its target tile, which is
+    // outlined. Ghosts in the house or on their way back have no say in
+    // where they go, so they are left out.
+    fn targets(&self, renderer: &mut Renderer, world: &World) -> Result<(), Error> {
+        for (actor, ghost) in world.ghosts().filter(|&(_, ghost)| ghost.state == GhostState::Active) {
+            let color = ghost_color(ghost.kind);
+            let target = Rect::new(
+                ghost.target.x * TILE_SIZE,
+                ghost.target.y * TILE_SIZE + MAZE_TOP,
+                TILE_SIZE,
+                TILE_SIZE,
+            );
+            let from = (actor.motion.x as f32, (actor.motion.y + MAZE_TOP) as f32);
+            let to = ((target.x + TILE_SIZE / 2) as f32, (target.y + TILE_SIZE / 2) as f32);
+            renderer.render_line(from, to, 1.0, color)?;
+            renderer.render_rect_outline(target, 1, color)?;
+        }
+        Ok(())
+    }
+
+    // Over the scores at the top, which are less use while debugging.
+    fn panel(&self, renderer: &mut Renderer, world: &World) -> Result<(), Error> {
+        let level = world.level();
+        renderer.render_rect(Rect::new(0, 0, VIEW_WIDTH, MAZE_TOP), PANEL)?;
+
+        let mut vertices = Vec::new();
+        text(&mut vertices, &format!("FPS {}", self.frame_rate.fps()), 1, 1);
+        text(&mut vertices, &mode_text(world.mode()), 1, 1 + LINE_HEIGHT);
+        let idle = format!("IDLE {}/{}", world.idle_ticks(), ghost::idle_limit(level));
+        text(&mut vertices, &idle, 1, 1 + 2 * LINE_HEIGHT);
+        text(&mut vertices, "DOTS", VIEW_WIDTH / 2, 1);
+        renderer.render_triangles(&vertices, TEXT)?;
+
+        // Each ghost's pellet counter in its own colour.
+        for (i, (_, ghost)) in world.ghosts().enumerate() {
+            vertices.clear();
+            let counter = format!("{}/{}", ghost.dot_counter, ghost.kind.dot_limit(level));
+            let (col, row) = (i as i32 % 2, i as i32 / 2);
+            text(&mut vertices, &counter, VIEW_WIDTH / 2 + col * 48, 1 + (row + 1) * LINE_HEIGHT);
+            renderer.render_triangles(&vertices, ghost_color(ghost.kind))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_is_drawn_a_pixel_at_a_time() {
+        let mut vertices = Vec::new();
+        text(&mut vertices, "1 ?", 10, 20);
+        // The one has eight pixels, the space and the unknown character none.
+        assert_eq!(vertices.len(), 8 * 6 * shapes::FLOATS_PER_VERTEX);
+        // Its top pixel is in the middle of the first row.
+        assert_eq!(vertices[..2], [11.0, 20.0]);
+        assert!("SCATTERCHASEFPSIDLEDOTS0123456789./".chars().all(|c| glyph(c).is_some()));
+    }
+
+    #[test]
+    fn mode_counts_down_in_seconds() {
+        let mut timer = ModeTimer::new(1);
+        assert_eq!(mode_text(&timer), "SCATTER 7.0");
+        for _ in 0..420 + 30 {
+            timer.tick();
+        }
+        assert_eq!(mode_text(&timer), "CHASE 19.5");
+    }
+
+    #[test]
+    fn frame_rate_is_counted_each_second() {
+        let start = Instant::now();
+        let mut rate = FrameRate::default();
+        for frame in 0..=90u64 {
+            rate.frame(start + Duration::from_millis(frame * 1000 / 60));
+        }
+        assert_eq!(rate.fps(), 60);
+    }
+}
@@ -0,0 +1,239 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+
+use maze::{Direction, Maze, TilePos};
+
+// Paths over the walkable tiles of a maze. Stepping off the left or right
+// edge comes back in on the other side, the way the tunnels work.
+
+// The tile one step from `pos`, wrapping around the sides.
+pub fn neighbor(maze: &Maze, pos: TilePos, dir: Direction) -> TilePos {
+    let next = pos.step(dir);
+    TilePos::new(next.x.rem_euclid(maze.width()), next.y)
+}
+
+pub fn neighbors(maze: &Maze, pos: TilePos) -> impl Iterator<Item = (Direction, TilePos)> + '_ {
+    Direction::ALL
+        .iter()
+        .map(move |&dir| (dir, neighbor(maze, pos, dir)))
+        .filter(move |&(_, next)| maze.is_walkable(next))
+}
+
+fn index(maze: &Maze, pos: TilePos) -> usize {
+    (pos.y * maze.width() + pos.x) as usize
+}
+
+// Steps from the nearest source to every tile, by breadth first search.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DistanceMap {
+    width: i32,
+    height: i32,
+    distances: Vec<Option<u32>>,
+}
+
+impl DistanceMap {
+    pub fn new(maze: &Maze, sources: &[TilePos]) -> DistanceMap {
+        let mut distances = vec![None; (maze.width() * maze.height()) as usize];
+        let mut queue = VecDeque::new();
+        for &source in sources.iter().filter(|&&source| maze.contains(source)) {
+            distances[index(maze, source)] = Some(0);
+            queue.push_back(source);
+        }
+
+        while let Some(pos) = queue.pop_front() {
+            let distance = distances[index(maze, pos)].unwrap() + 1;
+            for (_, next) in neighbors(maze, pos) {
+                let slot = &mut distances[index(maze, next)];
+                if slot.is_none() {
+                    *slot = Some(distance);
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        DistanceMap {
+            width: maze.width(),
+            height: maze.height(),
+            distances,
+        }
+    }
+
+    // None for walls and tiles that can't reach any source.
+    pub fn get(&self, pos: TilePos) -> Option<u32> {
+        if pos.x >= 0 && pos.x < self.width && pos.y >= 0 && pos.y < self.height {
+            self.distances[(pos.y * self.width + pos.x) as usize]
+        } else {
+            None
+        }
+    }
+
+    // The way downhill from `pos` towards the nearest source, preferring
+    // directions in `Direction::ALL` order on ties like the ghosts do.
+    pub fn direction(&self, maze: &Maze, pos: TilePos) -> Option<Direction> {
+        let here = self.get(pos)?;
+        neighbors(maze, pos)
+            .filter_map(|(dir, next)| self.get(next).map(|distance| (distance, dir)))
+            .filter(|&(distance, _)| distance < here)
+            .min_by_key(|&(distance, _)| distance)
+            .map(|(_, dir)| dir)
+    }
+
+    // The tiles from `pos` to the nearest source, both included.
+    pub fn path(&self, maze: &Maze, pos: TilePos) -> Option<Vec<TilePos>> {
+        self.get(pos)?;
+        let mut path = vec![pos];
+        let mut pos = pos;
+        while self.get(pos) != Some(0) {
+            pos = neighbor(maze, pos, self.direction(maze, pos)?);
+            path.push(pos);
+        }
+        Some(path)
+    }
+}
+
+// Steps between two tiles, counting the shorter way round through the sides.
+fn heuristic(maze: &Maze, a: TilePos, b: TilePos) -> u32 {
+    let dx = (a.x - b.x).abs();
+    let dx = dx.min(maze.width() - dx);
+    (dx + (a.y - b.y).abs()) as u32
+}
+
+// A shortest path from `from` to `to`, both included.
+pub fn find_path(maze: &Maze, from: TilePos, to: TilePos) -> Option<Vec<TilePos>> {
+    if !maze.is_walkable(from) || !maze.is_walkable(to) {
+        return None;
+    }
+
+    let len = (maze.width() * maze.height()) as usize;
+    let mut cost = vec![u32::MAX; len];
+    let mut came_from = vec![None; len];
+    let mut open = BinaryHeap::new();
+    cost[index(maze, from)] = 0;
+    // Ties on the estimate go to the tile found last, which keeps paths
+    // straight and the search short. The counter makes the order total, so
+    // the same query always gives the same path.
+    let mut order = 0u32;
+    open.push((Reverse(heuristic(maze, from, to)), order, from.x, from.y));
+
+    while let Some((_, _, x, y)) = open.pop() {
+        let pos = TilePos::new(x, y);
+        if pos == to {
+            let mut path = vec![to];
+            let mut pos = to;
+            while let Some(prev) = came_from[index(maze, pos)] {
+                path.push(prev);
+                pos = prev;
+            }
+            path.reverse();
+            return Some(path);
+        }
+
+        let next_cost = cost[index(maze, pos)] + 1;
+        for (_, next) in neighbors(maze, pos) {
+            let i = index(maze, next);
+            if next_cost < cost[i] {
+                cost[i] = next_cost;
+                came_from[i] = Some(pos);
+                order += 1;
+                open.push((Reverse(next_cost + heuristic(maze, next, to)), order, next.x, next.y));
+            }
+        }
+    }
+    None
+}
+
+// A distance map towards one target, only rebuilt when the target moves to
+// another tile. Asking every frame is cheap.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FlowField {
+    target: Option<TilePos>,
+    map: Option<DistanceMap>,
+}
+
+impl FlowField {
+    pub fn new() -> FlowField {
+        FlowField::default()
+    }
+
+    pub fn update(&mut self, maze: &Maze, target: TilePos) -> &DistanceMap {
+        if self.target != Some(target) || self.map.is_none() {
+            self.target = Some(target);
+            self.map = Some(DistanceMap::new(maze, &[target]));
+        }
+        self.map.as_ref().unwrap()
+    }
+
+    pub fn target(&self) -> Option<TilePos> {
+        self.target
+    }
+
+    pub fn map(&self) -> Option<&DistanceMap> {
+        self.map.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn maze() -> Maze {
+        Maze::parse(
+            "\
+#######
+#.....#
+#.###.#
+  . .
+#######
+",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn distance_map_counts_steps() {
+        let maze = maze();
+        let map = DistanceMap::new(&maze, &[TilePos::new(1, 1)]);
+        assert_eq!(map.get(TilePos::new(1, 1)), Some(0));
+        assert_eq!(map.get(TilePos::new(5, 1)), Some(4));
+        assert_eq!(map.get(TilePos::new(0, 0)), None);
+        // Through the sides is shorter than back round the loop.
+        assert_eq!(map.get(TilePos::new(6, 3)), Some(4));
+        assert_eq!(map.direction(&maze, TilePos::new(6, 3)), Some(Direction::Right));
+    }
+
+    #[test]
+    fn finds_shortest_paths_through_the_tunnel() {
+        let maze = maze();
+        let path = find_path(&maze, TilePos::new(0, 3), TilePos::new(6, 3)).unwrap();
+        assert_eq!(path, vec![TilePos::new(0, 3), TilePos::new(6, 3)]);
+
+        let path = find_path(&maze, TilePos::new(1, 1), TilePos::new(5, 1)).unwrap();
+        assert_eq!(path.len(), 5);
+        assert!(find_path(&maze, TilePos::new(1, 1), TilePos::new(3, 2)).is_none());
+    }
+
+    #[test]
+    fn astar_agrees_with_bfs() {
+        let maze = Maze::arcade();
+        let from = TilePos::new(1, 1);
+        let map = DistanceMap::new(&maze, &[from]);
+        for (pos, _) in maze.tiles().filter(|&(pos, _)| maze.is_walkable(pos)) {
+            let path = find_path(&maze, from, pos);
+            assert_eq!(path.map(|path| path.len() as u32 - 1), map.get(pos), "{:?}", pos);
+        }
+    }
+
+    #[test]
+    fn flow_field_is_cached_per_target() {
+        let maze = maze();
+        let mut field = FlowField::new();
+        let first = field.update(&maze, TilePos::new(1, 1)).clone();
+        assert_eq!(field.update(&maze, TilePos::new(1, 1)), &first);
+        assert_ne!(field.update(&maze, TilePos::new(5, 1)), &first);
+        assert_eq!(field.target(), Some(TilePos::new(5, 1)));
+
+        let path = field.update(&maze, TilePos::new(5, 1)).path(&maze, TilePos::new(1, 1)).unwrap();
+        assert_eq!(path.first(), Some(&TilePos::new(1, 1)));
+        assert_eq!(path.last(), Some(&TilePos::new(5, 1)));
+    }
+}
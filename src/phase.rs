@@ -0,0 +1,739 @@
+use failure::{err_msg, Error};
+
+use autoplay::{Bot, InputSource};
+use canvas::{self, Canvas, Color};
+use event::Event;
+use ghost::GhostKind;
+use high_score::InitialsEntry;
+use maze::{Direction, TILE_SIZE};
+use state::{Decode, Encode, StateReader, StateWriter};
+use world::{World, TICKS_PER_SECOND};
+use {Buttons, Input, Rect, Rgb};
+
+const ROLL_CALL_STEP: u32 = TICKS_PER_SECOND;
+const ROLL_CALL_POINTS: u32 = 5 * ROLL_CALL_STEP;
+const ROLL_CALL_LENGTH: u32 = 10 * ROLL_CALL_STEP;
+
+const READY_TICKS: u32 = 2 * TICKS_PER_SECOND;
+
+const DEMO_TICKS: u32 = 30 * TICKS_PER_SECOND;
+
+const DEATH_FREEZE_TICKS: u32 = TICKS_PER_SECOND;
+const DEATH_ANIMATION_TICKS: u32 = 90;
+
+const CLEAR_FREEZE_TICKS: u32 = TICKS_PER_SECOND;
+const CLEAR_FLASH_TICKS: u32 = 14;
+const CLEAR_FLASHES: u32 = 8;
+
+const INTERMISSION_TICKS: u32 = 6 * TICKS_PER_SECOND;
+
+const GAME_OVER_TICKS: u32 = 3 * TICKS_PER_SECOND;
+
+// Row of the "READY!" and "GAME OVER" text, between the ghost
+// house and Pac-Man.
+const MESSAGE_ROW: i32 = 20;
+
+// START begins a one player game, or the party if one is set up. START2
+// begins a game where two players take turns. Returns false if neither was
+// pressed.
+fn start_game(world: &mut World, input: &Input) -> bool {
+    if input.is_pressed(Buttons::START) {
+        match world.party_setup().cloned() {
+            Some(party) => world.start_party(party),
+            None => world.start_game(1),
+        }
+    } else if input.is_pressed(Buttons::START2) {
+        world.start_game(2);
+    } else {
+        return false;
+    }
+    true
+}
+
+// The title screen: introduces the ghosts one by one, then shows a demo game,
+// until START is pressed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Attract {
+    ticks: u32,
+}
+
+impl Attract {
+    pub fn new() -> Attract {
+        Attract { ticks: 0 }
+    }
+
+    fn update(&mut self, world: &mut World, input: &Input) -> Option<Phase> {
+        if start_game(world, input) {
+            return Some(Phase::Ready(Ready::new()));
+        }
+
+        self.ticks += 1;
+        if self.ticks >= ROLL_CALL_LENGTH {
+            world.new_game();
+            return Some(Phase::Demo(Demo::new()));
+        }
+        None
+    }
+
+    fn render(&self, world: &World, canvas: &mut Canvas) -> Result<(), Error> {
+        canvas.hud(world, true)?;
+
+        let revealed = (self.ticks / ROLL_CALL_STEP) as usize;
+        for (i, &kind) in GhostKind::ALL.iter().enumerate().take(revealed) {
+            let y = (6 + 3 * i as i32) * TILE_SIZE;
+            canvas.sprite(canvas::ghost_sprite(kind, Direction::Right), 4 * TILE_SIZE, y)?;
+        }
+
+        if self.ticks >= ROLL_CALL_POINTS {
+            canvas.text("10 PTS", 12, 25, Color::White)?;
+            canvas.text("50 PTS", 12, 27, Color::White)?;
+        }
+        canvas.text("CREDIT", 2, 35, Color::White)
+    }
+}
+
+// The bot plays until it dies, like the arcade does between roll calls. The
+// demo score is thrown away afterwards.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Demo {
+    ticks: u32,
+}
+
+impl Demo {
+    pub fn new() -> Demo {
+        Demo { ticks: 0 }
+    }
+
+    fn update(&mut self, world: &mut World, input: &Input) -> Option<Phase> {
+        if start_game(world, input) {
+            return Some(Phase::Ready(Ready::new()));
+        }
+
+        self.ticks += 1;
+        let mut demo_input = Input::new();
+        demo_input.update(Bot::default().next(world).unwrap_or_default());
+        world.update(&demo_input);
+
+        let over = world.events().contains(&Event::PacManCaught) || world.events().contains(&Event::LevelCleared);
+        if over || self.ticks >= DEMO_TICKS {
+            world.new_game();
+            return Some(Phase::Attract(Attract::new()));
+        }
+        None
+    }
+
+    fn render(&self, world: &World, canvas: &mut Canvas) -> Result<(), Error> {
+        canvas.hud(world, true)?;
+        canvas.world(world, self.ticks)?;
+        canvas.text("GAME  OVER", 9, MESSAGE_ROW, Color::Red)
+    }
+}
+
+// The pause before play starts or resumes after a death.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Ready {
+    ticks: u32,
+}
+
+impl Ready {
+    pub fn new() -> Ready {
+        Ready { ticks: 0 }
+    }
+
+    fn update(&mut self) -> Option<Phase> {
+        self.ticks += 1;
+        if self.ticks >= READY_TICKS {
+            Some(Phase::Playing(Playing::new()))
+        } else {
+            None
+        }
+    }
+
+    fn render(&self, world: &World, canvas: &mut Canvas) -> Result<(), Error> {
+        canvas.hud(world, true)?;
+        canvas.world(world, 0)?;
+        canvas.text("READY!", 11, MESSAGE_ROW, Color::Yellow)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Playing {
+    ticks: u32,
+}
+
+impl Playing {
+    pub fn new() -> Playing {
+        Playing { ticks: 0 }
+    }
+
+    fn update(&mut self, world: &mut World, input: &Input) -> Option<Phase> {
+        self.ticks += 1;
+        world.update(input);
+
+        if world.events().contains(&Event::LevelCleared) {
+            Some(Phase::LevelComplete(LevelComplete::new()))
+        } else if world.events().contains(&Event::PacManCaught) {
+            Some(Phase::Dying(Dying::new()))
+        } else {
+            None
+        }
+    }
+
+    fn render(&self, world: &World, canvas: &mut Canvas) -> Result<(), Error> {
+        canvas.hud(world, (self.ticks / 16).is_multiple_of(2))?;
+        canvas.world(world, self.ticks)
+    }
+}
+
+// Everything freezes for a moment, then the ghosts vanish and Pac-Man
+// shrivels up.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Dying {
+    ticks: u32,
+}
+
+impl Dying {
+    pub fn new() -> Dying {
+        Dying { ticks: 0 }
+    }
+
+    fn update(&mut self, world: &mut World) -> Option<Phase> {
+        self.ticks += 1;
+        if self.ticks < DEATH_FREEZE_TICKS + DEATH_ANIMATION_TICKS {
+            return None;
+        }
+
+        if world.lose_life() {
+            return Some(Phase::GameOver(GameOver::new()));
+        }
+        if !world.next_player() {
+            world.reset_actors();
+        }
+        Some(Phase::Ready(Ready::new()))
+    }
+
+    fn render(&self, world: &World, canvas: &mut Canvas) -> Result<(), Error> {
+        canvas.hud(world, true)?;
+        if self.ticks < DEATH_FREEZE_TICKS {
+            return canvas.world(world, 0);
+        }
+
+        canvas.maze(world.maze(), world.level_spec().wall_color, true)?;
+
+        // The sheet has no death frames, so Pac-Man opens up and shrinks away.
+        let progress = self.ticks - DEATH_FREEZE_TICKS;
+        let src = canvas::pacman_sprite(Direction::Up, (progress / 15).min(2));
+        let size = 16 - 16 * progress as i32 / DEATH_ANIMATION_TICKS as i32;
+        for (pacman, _) in world.pacmen() {
+            let dst = Rect::new(
+                pacman.motion.x - size / 2,
+                pacman.motion.y - size / 2 + canvas::MAZE_TOP,
+                size,
+                size,
+            );
+            canvas.sprite_scaled(src, dst)?;
+        }
+        Ok(())
+    }
+}
+
+// The maze blinks a few times before the next level or an intermission.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LevelComplete {
+    ticks: u32,
+}
+
+impl LevelComplete {
+    pub fn new() -> LevelComplete {
+        LevelComplete { ticks: 0 }
+    }
+
+    fn update(&mut self, world: &mut World) -> Option<Phase> {
+        self.ticks += 1;
+        if self.ticks < CLEAR_FREEZE_TICKS + CLEAR_FLASHES * CLEAR_FLASH_TICKS {
+            return None;
+        }
+
+        let next_level = world.level() + 1;
+        match Intermission::after_level(world.level()) {
+            Some(number) => Some(Phase::Intermission(Intermission::new(number, next_level))),
+            None => {
+                world.start_level(next_level);
+                Some(Phase::Ready(Ready::new()))
+            }
+        }
+    }
+
+    fn render(&self, world: &World, canvas: &mut Canvas) -> Result<(), Error> {
+        canvas.hud(world, true)?;
+        if self.ticks < CLEAR_FREEZE_TICKS {
+            return canvas.world(world, 0);
+        }
+
+        // The walls flash white, like the arcade's.
+        let flash = (self.ticks - CLEAR_FREEZE_TICKS) / CLEAR_FLASH_TICKS;
+        let color = if flash.is_multiple_of(2) { world.level_spec().wall_color } else { Rgb::WHITE };
+        canvas.maze(world.maze(), color, false)?;
+        for (pacman, _) in world.pacmen() {
+            canvas.entity(pacman, false)?;
+        }
+        Ok(())
+    }
+}
+
+// The cutscenes between levels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Intermission {
+    number: u32,
+    next_level: u32,
+    ticks: u32,
+}
+
+impl Intermission {
+    pub fn new(number: u32, next_level: u32) -> Intermission {
+        Intermission {
+            number,
+            next_level,
+            ticks: 0,
+        }
+    }
+
+    // Cutscene 1 plays after level 2, 2 after level 5 and 3 after levels 9, 13 and 17.
+    pub fn after_level(level: u32) -> Option<u32> {
+        match level {
+            2 => Some(1),
+            5 => Some(2),
+            9 | 13 | 17 => Some(3),
+            _ => None,
+        }
+    }
+
+    pub fn number(&self) -> u32 {
+        self.number
+    }
+
+    fn update(&mut self, world: &mut World) -> Option<Phase> {
+        self.ticks += 1;
+        if self.ticks >= INTERMISSION_TICKS {
+            world.start_level(self.next_level);
+            Some(Phase::Ready(Ready::new()))
+        } else {
+            None
+        }
+    }
+
+    fn render(&self, world: &World, canvas: &mut Canvas) -> Result<(), Error> {
+        canvas.hud(world, true)?;
+
+        // Blinky chases Pac-Man off the left edge, then the chase comes back
+        // the other way: a giant Pac-Man after a blue Blinky, Blinky again,
+        // or Blinky alone depending on the cutscene.
+        let half = INTERMISSION_TICKS / 2;
+        let y = 18 * TILE_SIZE;
+        let frame = self.ticks / 4;
+        if self.ticks < half {
+            let x = canvas::VIEW_WIDTH + 16 - (self.ticks * 5 / 4) as i32;
+            canvas.sprite(canvas::pacman_sprite(Direction::Left, frame), x, y)?;
+            canvas.sprite(canvas::ghost_sprite(GhostKind::Blinky, Direction::Left), x + 32, y)?;
+        } else {
+            let x = (self.ticks - half) as i32 * 5 / 4 - 64;
+            match self.number {
+                1 => {
+                    canvas.sprite(canvas::frightened_sprite(false), x + 48, y)?;
+                    let src = canvas::pacman_sprite(Direction::Right, frame);
+                    canvas.sprite_scaled(src, Rect::new(x - 16, y - 16, 32, 32))?;
+                }
+                2 => {
+                    canvas.sprite(canvas::pacman_sprite(Direction::Right, frame), x + 48, y)?;
+                    canvas.sprite(canvas::ghost_sprite(GhostKind::Blinky, Direction::Right), x, y)?;
+                }
+                _ => {
+                    canvas.sprite(canvas::ghost_sprite(GhostKind::Blinky, Direction::Right), x, y)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Shows "GAME OVER", then lets the player enter their initials if the score
+// made the high score table. The initials only go out as an event, the table
+// itself is kept outside the simulation. In a two player game the other
+// player carries on afterwards if they have lives left.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GameOver {
+    ticks: u32,
+    initials: Option<InitialsEntry>,
+}
+
+impl GameOver {
+    pub fn new() -> GameOver {
+        GameOver {
+            ticks: 0,
+            initials: None,
+        }
+    }
+
+    pub fn initials(&self) -> Option<&InitialsEntry> {
+        self.initials.as_ref()
+    }
+
+    fn next(world: &mut World) -> Phase {
+        if world.next_player() {
+            Phase::Ready(Ready::new())
+        } else {
+            Phase::Attract(Attract::new())
+        }
+    }
+
+    fn update(&mut self, world: &mut World, input: &Input) -> Option<Phase> {
+        let score = world.score().score();
+        let entry = match self.initials {
+            Some(ref mut entry) => entry,
+            None => {
+                self.ticks += 1;
+                if self.ticks < GAME_OVER_TICKS {
+                    return None;
+                }
+                if score < world.qualifying_score() {
+                    return Some(GameOver::next(world));
+                }
+                self.initials = Some(InitialsEntry::new());
+                return None;
+            }
+        };
+
+        if input.is_pressed(Buttons::UP) {
+            entry.next_letter();
+        }
+        if input.is_pressed(Buttons::DOWN) {
+            entry.prev_letter();
+        }
+        if input.is_pressed(Buttons::BACK) {
+            entry.back();
+        }
+        if input.is_pressed(Buttons::START) && entry.confirm() {
+            world.enter_initials(entry.initials());
+            return Some(GameOver::next(world));
+        }
+        None
+    }
+
+    fn render(&self, world: &World, canvas: &mut Canvas) -> Result<(), Error> {
+        canvas.hud(world, true)?;
+        match self.initials {
+            None => {
+                canvas.maze(world.maze(), world.level_spec().wall_color, true)?;
+                canvas.text("GAME  OVER", 9, MESSAGE_ROW, Color::Red)
+            }
+            Some(ref entry) => {
+                canvas.text("HIGH SCORE", 9, 14, Color::Red)?;
+                let initials = entry.initials();
+                for (i, &c) in initials.iter().enumerate() {
+                    let color = if i == entry.cursor() { Color::Yellow } else { Color::White };
+                    canvas.text(&(c as char).to_string(), 12 + 2 * i as i32, 18, color)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+// What the game is doing at the top level. Each phase updates the world the
+// way it needs to and decides which phase comes next.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Phase {
+    Attract(Attract),
+    Demo(Demo),
+    Ready(Ready),
+    Playing(Playing),
+    Dying(Dying),
+    LevelComplete(LevelComplete),
+    Intermission(Intermission),
+    GameOver(GameOver),
+}
+
+impl Phase {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Phase::Attract(_) => "attract",
+            Phase::Demo(_) => "demo",
+            Phase::Ready(_) => "ready",
+            Phase::Playing(_) => "playing",
+            Phase::Dying(_) => "dying",
+            Phase::LevelComplete(_) => "level_complete",
+            Phase::Intermission(_) => "intermission",
+            Phase::GameOver(_) => "game_over",
+        }
+    }
+
+    pub fn update(&mut self, world: &mut World, input: &Input) {
+        let next = match *self {
+            Phase::Attract(ref mut phase) => phase.update(world, input),
+            Phase::Demo(ref mut phase) => phase.update(world, input),
+            Phase::Ready(ref mut phase) => phase.update(),
+            Phase::Playing(ref mut phase) => phase.update(world, input),
+            Phase::Dying(ref mut phase) => phase.update(world),
+            Phase::LevelComplete(ref mut phase) => phase.update(world),
+            Phase::Intermission(ref mut phase) => phase.update(world),
+            Phase::GameOver(ref mut phase) => phase.update(world, input),
+        };
+
+        if let Some(next) = next {
+            debug!("{:?} -> {:?}", self, next);
+            *self = next;
+        }
+    }
+
+    pub fn render(&self, world: &World, canvas: &mut Canvas) -> Result<(), Error> {
+        match *self {
+            Phase::Attract(ref phase) => phase.render(world, canvas),
+            Phase::Demo(ref phase) => phase.render(world, canvas),
+            Phase::Ready(ref phase) => phase.render(world, canvas),
+            Phase::Playing(ref phase) => phase.render(world, canvas),
+            Phase::Dying(ref phase) => phase.render(world, canvas),
+            Phase::LevelComplete(ref phase) => phase.render(world, canvas),
+            Phase::Intermission(ref phase) => phase.render(world, canvas),
+            Phase::GameOver(ref phase) => phase.render(world, canvas),
+        }
+    }
+}
+
+impl Default for Phase {
+    fn default() -> Phase {
+        Phase::Attract(Attract::new())
+    }
+}
+
+impl Encode for Phase {
+    fn encode(&self, w: &mut StateWriter) {
+        match *self {
+            Phase::Attract(ref phase) => {
+                w.write_u8(0);
+                w.write_u32(phase.ticks);
+            }
+            Phase::Ready(ref phase) => {
+                w.write_u8(1);
+                w.write_u32(phase.ticks);
+            }
+            Phase::Playing(ref phase) => {
+                w.write_u8(2);
+                w.write_u32(phase.ticks);
+            }
+            Phase::Dying(ref phase) => {
+                w.write_u8(3);
+                w.write_u32(phase.ticks);
+            }
+            Phase::LevelComplete(ref phase) => {
+                w.write_u8(4);
+                w.write_u32(phase.ticks);
+            }
+            Phase::Intermission(ref phase) => {
+                w.write_u8(5);
+                w.write_u32(phase.ticks);
+                w.write_u32(phase.number);
+                w.write_u32(phase.next_level);
+            }
+            Phase::GameOver(ref phase) => {
+                w.write_u8(6);
+                w.write_u32(phase.ticks);
+                w.write(&phase.initials);
+            }
+            Phase::Demo(ref phase) => {
+                w.write_u8(7);
+                w.write_u32(phase.ticks);
+            }
+        }
+    }
+}
+
+impl Decode for Phase {
+    fn decode(r: &mut StateReader) -> Result<Phase, Error> {
+        let kind = r.read_u8()?;
+        let ticks = r.read_u32()?;
+        Ok(match kind {
+            0 => Phase::Attract(Attract { ticks }),
+            1 => Phase::Ready(Ready { ticks }),
+            2 => Phase::Playing(Playing { ticks }),
+            3 => Phase::Dying(Dying { ticks }),
+            4 => Phase::LevelComplete(LevelComplete { ticks }),
+            5 => Phase::Intermission(Intermission {
+                ticks,
+                number: r.read_u32()?,
+                next_level: r.read_u32()?,
+            }),
+            6 => Phase::GameOver(GameOver {
+                ticks,
+                initials: r.read()?,
+            }),
+            7 => Phase::Demo(Demo { ticks }),
+            _ => return Err(err_msg(format!("Invalid phase {}", kind))),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use level::LevelTable;
+    use maze::Maze;
+
+    fn world() -> World {
+        World::new(LevelTable::arcade(), Maze::arcade(), 1)
+    }
+
+    fn tick(phase: &mut Phase, world: &mut World, input: &mut Input, buttons: Buttons) {
+        input.update(buttons);
+        world.clear_events();
+        phase.update(world, input);
+    }
+
+    fn run_until<F: Fn(&Phase) -> bool>(phase: &mut Phase, world: &mut World, limit: u32, done: F) {
+        let mut input = Input::new();
+        for _ in 0..limit {
+            if done(phase) {
+                return;
+            }
+            tick(phase, world, &mut input, Buttons::empty());
+        }
+        panic!("Still in {:?} after {} ticks", phase, limit);
+    }
+
+    fn start(world: &mut World) -> Phase {
+        let mut phase = Phase::default();
+        let mut input = Input::new();
+        tick(&mut phase, world, &mut input, Buttons::START);
+        phase
+    }
+
+    #[test]
+    fn start_leads_to_ready_then_playing() {
+        let mut world = world();
+        let mut phase = start(&mut world);
+        assert_eq!(phase, Phase::Ready(Ready::new()));
+
+        run_until(&mut phase, &mut world, READY_TICKS + 1, |phase| matches!(*phase, Phase::Playing(_)));
+    }
+
+    #[test]
+    fn attract_ignores_held_start() {
+        let mut world = world();
+        let mut phase = Phase::default();
+        let mut input = Input::new();
+        input.update(Buttons::START);
+        tick(&mut phase, &mut world, &mut input, Buttons::START);
+        assert!(matches!(phase, Phase::Attract(_)));
+    }
+
+    #[test]
+    fn attract_plays_a_demo() {
+        let mut world = world();
+        let mut phase = Phase::default();
+
+        run_until(&mut phase, &mut world, ROLL_CALL_LENGTH + 1, |phase| matches!(*phase, Phase::Demo(_)));
+        let mut input = Input::new();
+        for _ in 0..100 {
+            tick(&mut phase, &mut world, &mut input, Buttons::empty());
+        }
+        assert!(world.score().score() > 0);
+
+        run_until(&mut phase, &mut world, DEMO_TICKS, |phase| matches!(*phase, Phase::Attract(_)));
+        assert_eq!(world.score().score(), 0);
+    }
+
+    #[test]
+    fn idle_player_dies_until_game_over() {
+        let mut world = world();
+        let mut phase = start(&mut world);
+
+        for lives in (0..3).rev() {
+            run_until(&mut phase, &mut world, 10_000, |phase| matches!(*phase, Phase::Dying(_)));
+            run_until(&mut phase, &mut world, 1_000, |phase| !matches!(*phase, Phase::Dying(_)));
+            assert_eq!(world.score().lives(), lives);
+        }
+
+        assert!(matches!(phase, Phase::GameOver(_)));
+        run_until(&mut phase, &mut world, GAME_OVER_TICKS + 1, |phase| matches!(*phase, Phase::GameOver(ref game_over) if game_over.initials().is_some()));
+
+        let mut input = Input::new();
+        for _ in 0..3 {
+            tick(&mut phase, &mut world, &mut input, Buttons::START);
+            tick(&mut phase, &mut world, &mut input, Buttons::empty());
+        }
+        assert!(matches!(phase, Phase::Attract(_)));
+    }
+
+    #[test]
+    fn initials_are_reported_not_recorded() {
+        let mut world = world();
+        let mut phase = Phase::GameOver(GameOver {
+            ticks: GAME_OVER_TICKS,
+            initials: Some(InitialsEntry::new()),
+        });
+        let mut input = Input::new();
+        let mut entered = Vec::new();
+        for &buttons in [Buttons::UP, Buttons::START, Buttons::START, Buttons::START].iter() {
+            tick(&mut phase, &mut world, &mut input, buttons);
+            entered.extend_from_slice(world.events());
+            tick(&mut phase, &mut world, &mut input, Buttons::empty());
+        }
+        assert_eq!(entered, vec![Event::HighScore { score: 0, initials: *b"BAA" }]);
+        assert!(matches!(phase, Phase::Attract(_)));
+    }
+
+    #[test]
+    fn low_scores_skip_the_initials() {
+        let mut world = world();
+        world.set_qualifying_score(u32::MAX);
+        let mut phase = Phase::GameOver(GameOver::new());
+        run_until(&mut phase, &mut world, GAME_OVER_TICKS + 1, |phase| matches!(*phase, Phase::Attract(_)));
+    }
+
+    #[test]
+    fn two_players_take_turns() {
+        let mut world = world();
+        let mut phase = Phase::default();
+        let mut input = Input::new();
+        tick(&mut phase, &mut world, &mut input, Buttons::START2);
+        assert_eq!(world.players(), 2);
+
+        for &(player, lives) in [(1, 2), (0, 2), (1, 1)].iter() {
+            run_until(&mut phase, &mut world, 10_000, |phase| matches!(*phase, Phase::Dying(_)));
+            run_until(&mut phase, &mut world, 1_000, |phase| matches!(*phase, Phase::Ready(_)));
+            assert_eq!(world.player(), player);
+            assert_eq!(world.player_score(1 - player).unwrap().lives(), lives);
+        }
+    }
+
+    #[test]
+    fn cleared_level_flashes_then_advances() {
+        let mut world = world();
+        let mut phase = Phase::Playing(Playing::new());
+        world.clear_maze();
+        tick(&mut phase, &mut world, &mut Input::new(), Buttons::empty());
+        assert_eq!(phase, Phase::LevelComplete(LevelComplete::new()));
+
+        run_until(&mut phase, &mut world, 1_000, |phase| matches!(*phase, Phase::Ready(_)));
+        assert_eq!(world.level(), 2);
+    }
+
+    #[test]
+    fn intermission_after_level_two() {
+        let mut world = world();
+        world.start_level(2);
+        let mut phase = Phase::LevelComplete(LevelComplete::new());
+
+        run_until(&mut phase, &mut world, 1_000, |phase| matches!(*phase, Phase::Intermission(ref intermission) if intermission.number() == 1));
+        run_until(&mut phase, &mut world, INTERMISSION_TICKS + 1, |phase| matches!(*phase, Phase::Ready(_)));
+        assert_eq!(world.level(), 3);
+    }
+
+    #[test]
+    fn intermissions_follow_arcade_levels() {
+        let cutscenes = (1..=21).filter_map(|level| Intermission::after_level(level).map(|n| (level, n)));
+        assert_eq!(
+            cutscenes.collect::<Vec<_>>(),
+            vec![(2, 1), (5, 2), (9, 3), (13, 3), (17, 3)]
+        );
+    }
+}
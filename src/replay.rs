@@ -0,0 +1,459 @@
+use std::path::Path;
+
+use failure::{err_msg, Error};
+use multiplayer::MAX_PLAYERS;
+use storage;
+use Buttons;
+
+pub const FILE_NAME: &str = "replay.pmr";
+
+const MAGIC: &[u8; 4] = b"PMRP";
+const VERSION: u16 = 4;
+const HEADER_SIZE: usize = 14;
+const CHECKSUM_SIZE: usize = 4;
+
+// The seed and the buttons held on every tick since the game started. Since
+// the simulation is deterministic that is all it takes to reproduce a game,
+// as long as it's the same game: the recording starts from a save state and
+// notes the `setup_hash` of the game it was recorded in, which covers the
+// maze and level table the save state leaves out.
+//
+// On disk the inputs are run length encoded, buttons rarely change from one
+// tick to the next:
+//
+//   magic "PMRP", version u16, seed u32, ticks u32
+//   save state length u32 and bytes, empty for a fresh game (version 2)
+//   setup hash u32, 0 without a save state (version 4)
+//   players u8, 1 unless several played at once (version 3)
+//   runs of (buttons u8 for each player, length as LEB128)
+//   FNV-1a checksum u32
+//
+// All integers are little endian.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Replay {
+    seed: u32,
+    start: Option<Vec<u8>>,
+    setup_hash: Option<u32>,
+    // The buttons of `players` players for each tick, one tick after the
+    // other. Only grows past one player once someone else presses a button.
+    players: usize,
+    inputs: Vec<Buttons>,
+}
+
+impl Replay {
+    pub fn new(seed: u32) -> Replay {
+        Replay {
+            seed,
+            start: None,
+            setup_hash: None,
+            players: 1,
+            inputs: Vec::new(),
+        }
+    }
+
+    // `setup_hash` is that of the game with `state` loaded.
+    pub fn from_state(seed: u32, state: Vec<u8>, setup_hash: u32) -> Replay {
+        Replay {
+            seed,
+            start: Some(state),
+            setup_hash: Some(setup_hash),
+            players: 1,
+            inputs: Vec::new(),
+        }
+    }
+
+    // Builds a replay from a hand written script. Each line holds a number of
+    // ticks and the buttons held for them, joined by '+' or "none". Other
+    // players' buttons follow in columns starting with their number, the
+    // first column is player one's unless it says otherwise:
+    //
+    //   # Start a game and head left for two seconds.
+    //   1 start
+    //   120 left
+    //   30 up+left
+    //
+    //   # In a party game, the second player heads right while the first
+    //   # waits. start2 starts a game for two players taking turns.
+    //   1 start
+    //   60 none p2:right
+    pub fn parse_script(seed: u32, source: &str) -> Result<Replay, Error> {
+        let mut replay = Replay::new(seed);
+        for (i, line) in source.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let error = |message: &str| err_msg(format!("Line {}: {}", i + 1, message));
+            let mut parts = line.split_whitespace();
+            let ticks = parts
+                .next()
+                .and_then(|ticks| ticks.parse::<u32>().ok())
+                .ok_or_else(|| error("Expected a tick count"))?;
+
+            let mut held = [Buttons::empty(); MAX_PLAYERS];
+            let mut seen = [false; MAX_PLAYERS];
+            for column in parts {
+                let (player, buttons) = match column.find(':') {
+                    Some(colon) if column.starts_with('p') => {
+                        let player = column[1..colon]
+                            .parse::<usize>()
+                            .ok()
+                            .filter(|player| (1..=MAX_PLAYERS).contains(player))
+                            .ok_or_else(|| error(&format!("Unknown player {:?}", &column[..colon])))?;
+                        (player - 1, &column[colon + 1..])
+                    }
+                    _ => (0, column),
+                };
+                if seen[player] {
+                    return Err(error(&format!("Player {} has two columns", player + 1)));
+                }
+                seen[player] = true;
+
+                if buttons != "none" {
+                    for name in buttons.split('+') {
+                        held[player].insert(match name {
+                            "up" => Buttons::UP,
+                            "down" => Buttons::DOWN,
+                            "left" => Buttons::LEFT,
+                            "right" => Buttons::RIGHT,
+                            "start" => Buttons::START,
+                            "start2" => Buttons::START2,
+                            "back" => Buttons::BACK,
+                            _ => return Err(error(&format!("Unknown button {:?}", name))),
+                        });
+                    }
+                }
+            }
+            if !seen.contains(&true) {
+                return Err(error("Expected buttons"));
+            }
+
+            for _ in 0..ticks {
+                replay.push_players(&held);
+            }
+        }
+        Ok(replay)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Replay, Error> {
+        let path = path.as_ref();
+        let data = storage::read(path)?;
+        Replay::decode(&data).map_err(|e| err_msg(format!("{}: {}", path.display(), e)))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        storage::write(path, &self.encode())
+    }
+
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    // The save state to load after restarting with `seed`, if any.
+    pub fn start_state(&self) -> Option<&[u8]> {
+        self.start.as_ref().map(|state| &state[..])
+    }
+
+    // What the game's `setup_hash` has to be once the start state is loaded
+    // for the replay to play out the same. None for scripts and replays from
+    // before it was recorded, which are played regardless.
+    pub fn setup_hash(&self) -> Option<u32> {
+        self.setup_hash
+    }
+
+    pub fn len(&self) -> usize {
+        self.inputs.len() / self.players
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    pub fn players(&self) -> usize {
+        self.players
+    }
+
+    pub fn push(&mut self, buttons: Buttons) {
+        self.push_players(&[buttons]);
+    }
+
+    pub fn push_players(&mut self, buttons: &[Buttons]) {
+        let players = buttons.iter().rposition(|held| !held.is_empty()).map_or(1, |last| last + 1);
+        if players > self.players {
+            self.widen(players.min(MAX_PLAYERS));
+        }
+        for player in 0..self.players {
+            self.inputs.push(buttons.get(player).cloned().unwrap_or_default());
+        }
+    }
+
+    // The first player's buttons.
+    pub fn get(&self, tick: usize) -> Option<Buttons> {
+        self.inputs.get(tick * self.players).cloned()
+    }
+
+    pub fn get_players(&self, tick: usize) -> Option<[Buttons; MAX_PLAYERS]> {
+        let held = self.inputs.get(tick * self.players..(tick + 1) * self.players)?;
+        let mut buttons = [Buttons::empty(); MAX_PLAYERS];
+        buttons[..self.players].copy_from_slice(held);
+        Some(buttons)
+    }
+
+    pub fn truncate(&mut self, ticks: usize) {
+        self.inputs.truncate(ticks * self.players);
+    }
+
+    fn widen(&mut self, players: usize) {
+        let mut inputs = Vec::with_capacity(self.len() * players);
+        for tick in self.inputs.chunks(self.players) {
+            inputs.extend_from_slice(tick);
+            inputs.extend((tick.len()..players).map(|_| Buttons::empty()));
+        }
+        self.players = players;
+        self.inputs = inputs;
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(HEADER_SIZE + CHECKSUM_SIZE);
+        data.extend_from_slice(MAGIC);
+        data.extend_from_slice(&VERSION.to_le_bytes());
+        data.extend_from_slice(&self.seed.to_le_bytes());
+        data.extend_from_slice(&(self.len() as u32).to_le_bytes());
+        let start = self.start_state().unwrap_or(&[]);
+        data.extend_from_slice(&(start.len() as u32).to_le_bytes());
+        data.extend_from_slice(start);
+        data.extend_from_slice(&self.setup_hash.unwrap_or(0).to_le_bytes());
+        data.push(self.players as u8);
+
+        let mut inputs = self.inputs.chunks(self.players).peekable();
+        while let Some(buttons) = inputs.next() {
+            let mut length = 1u32;
+            while inputs.peek() == Some(&buttons) {
+                inputs.next();
+                length += 1;
+            }
+            data.extend(buttons.iter().map(|held| held.bits()));
+            write_varint(&mut data, length);
+        }
+
+        let checksum = storage::fnv1a(&data);
+        data.extend_from_slice(&checksum.to_le_bytes());
+        data
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Replay, Error> {
+        if data.len() < HEADER_SIZE + CHECKSUM_SIZE {
+            return Err(err_msg("File is truncated"));
+        }
+
+        let (body, checksum) = data.split_at(data.len() - CHECKSUM_SIZE);
+        let checksum = u32::from_le_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]);
+        if storage::fnv1a(body) != checksum {
+            return Err(err_msg("Checksum mismatch"));
+        }
+
+        if &body[0..4] != MAGIC {
+            return Err(err_msg("Invalid magic"));
+        }
+
+        let version = u16::from_le_bytes([body[4], body[5]]);
+        if version == 0 || version > VERSION {
+            return Err(err_msg(format!("Unsupported version {}", version)));
+        }
+
+        let seed = u32::from_le_bytes([body[6], body[7], body[8], body[9]]);
+        let ticks = u32::from_le_bytes([body[10], body[11], body[12], body[13]]) as usize;
+
+        let mut runs = &body[HEADER_SIZE..];
+        let mut start = None;
+        if version >= 2 {
+            if runs.len() < 4 {
+                return Err(err_msg("File is truncated"));
+            }
+            let len = u32::from_le_bytes([runs[0], runs[1], runs[2], runs[3]]) as usize;
+            if runs.len() < 4 + len {
+                return Err(err_msg("File is truncated"));
+            }
+            if len > 0 {
+                start = Some(runs[4..4 + len].to_vec());
+            }
+            runs = &runs[4 + len..];
+        }
+
+        let mut setup_hash = None;
+        if version >= 4 {
+            if runs.len() < 4 {
+                return Err(err_msg("File is truncated"));
+            }
+            let hash = u32::from_le_bytes([runs[0], runs[1], runs[2], runs[3]]);
+            setup_hash = start.as_ref().map(|_| hash);
+            runs = &runs[4..];
+        }
+
+        let mut players = 1;
+        if version >= 3 {
+            let (&count, rest) = runs.split_first().ok_or_else(|| err_msg("File is truncated"))?;
+            players = count as usize;
+            if players == 0 || players > MAX_PLAYERS {
+                return Err(err_msg(format!("Invalid player count {}", players)));
+            }
+            runs = rest;
+        }
+
+        let mut inputs = Vec::new();
+        while !runs.is_empty() {
+            if runs.len() < players {
+                return Err(err_msg("File is truncated"));
+            }
+            let (bits, rest) = runs.split_at(players);
+            let (length, rest) = read_varint(rest)?;
+            if length == 0 || inputs.len() / players + length as usize > ticks {
+                return Err(err_msg("Runs don't match tick count"));
+            }
+            for _ in 0..length {
+                inputs.extend(bits.iter().map(|&bits| Buttons::from_bits(bits)));
+            }
+            runs = rest;
+        }
+
+        if inputs.len() != ticks * players {
+            return Err(err_msg("Runs don't match tick count"));
+        }
+
+        Ok(Replay {
+            seed,
+            start,
+            setup_hash,
+            players,
+            inputs,
+        })
+    }
+}
+
+// LEB128, also used by the spectator stream.
+pub fn write_varint(data: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        data.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    data.push(value as u8);
+}
+
+pub fn read_varint(data: &[u8]) -> Result<(u32, &[u8]), Error> {
+    let mut value = 0u32;
+    for (i, &byte) in data.iter().enumerate().take(5) {
+        value |= u32::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok((value, &data[i + 1..]));
+        }
+    }
+    Err(err_msg("Invalid run length"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replay() -> Replay {
+        let mut replay = Replay::new(1234);
+        for tick in 0..1000 {
+            replay.push(match tick / 100 {
+                0 => Buttons::empty(),
+                1 => Buttons::START,
+                n if n % 2 == 0 => Buttons::LEFT,
+                _ => Buttons::UP | Buttons::LEFT,
+            });
+        }
+        replay
+    }
+
+    #[test]
+    fn round_trips_through_file_format() {
+        let replay = replay();
+        assert_eq!(Replay::decode(&replay.encode()).unwrap(), replay);
+        assert_eq!(Replay::decode(&Replay::new(7).encode()).unwrap(), Replay::new(7));
+
+        let mut from_state = Replay::from_state(7, vec![1, 2, 3], 0xdead_beef);
+        from_state.push(Buttons::DOWN);
+        let decoded = Replay::decode(&from_state.encode()).unwrap();
+        assert_eq!(decoded, from_state);
+        assert_eq!(decoded.setup_hash(), Some(0xdead_beef));
+    }
+
+    #[test]
+    fn grows_to_fit_more_players() {
+        let mut replay = replay();
+        replay.push_players(&[Buttons::UP, Buttons::empty(), Buttons::LEFT]);
+        assert_eq!(replay.players(), 3);
+        assert_eq!(replay.len(), 1001);
+        assert_eq!(replay.get(150), Some(Buttons::START));
+        assert_eq!(
+            replay.get_players(1000),
+            Some([Buttons::UP, Buttons::empty(), Buttons::LEFT, Buttons::empty()])
+        );
+
+        let decoded = Replay::decode(&replay.encode()).unwrap();
+        assert_eq!(decoded, replay);
+        replay.truncate(10);
+        assert_eq!(replay.len(), 10);
+    }
+
+    #[test]
+    fn parses_scripts() {
+        let replay = Replay::parse_script(3, "# comment\n1 start\n\n2 up+left # turn\n1 none\n").unwrap();
+        assert_eq!(replay.seed(), 3);
+        assert_eq!(
+            replay.inputs,
+            vec![Buttons::START, Buttons::UP | Buttons::LEFT, Buttons::UP | Buttons::LEFT, Buttons::empty()]
+        );
+
+        assert!(Replay::parse_script(0, "1 jump").is_err());
+        assert!(Replay::parse_script(0, "left").is_err());
+    }
+
+    #[test]
+    fn parses_scripts_for_more_players() {
+        let replay = Replay::parse_script(0, "1 start2\n2 left p3:up+right\n1 p2:down\n").unwrap();
+        assert_eq!(replay.players(), 3);
+        assert_eq!(replay.len(), 4);
+        assert_eq!(replay.get(0), Some(Buttons::START2));
+        assert_eq!(
+            replay.get_players(2),
+            Some([Buttons::LEFT, Buttons::empty(), Buttons::UP | Buttons::RIGHT, Buttons::empty()])
+        );
+        assert_eq!(
+            replay.get_players(3),
+            Some([Buttons::empty(), Buttons::DOWN, Buttons::empty(), Buttons::empty()])
+        );
+
+        assert!(Replay::parse_script(0, "1 p5:up").is_err());
+        assert!(Replay::parse_script(0, "1 p0:up").is_err());
+        assert!(Replay::parse_script(0, "1 up p1:down").is_err());
+    }
+
+    #[test]
+    fn runs_are_compact() {
+        let data = replay().encode();
+        assert_eq!(data.len(), HEADER_SIZE + 4 + 4 + 1 + 10 * 2 + CHECKSUM_SIZE);
+    }
+
+    #[test]
+    fn rejects_corrupt_data() {
+        let data = replay().encode();
+
+        let mut flipped = data.clone();
+        flipped[HEADER_SIZE] ^= 0xff;
+        assert!(Replay::decode(&flipped).is_err());
+
+        assert!(Replay::decode(&data[..data.len() - 1]).is_err());
+        assert!(Replay::decode(b"garbage").is_err());
+
+        // A tick count the runs don't back up.
+        let mut body = data[..data.len() - CHECKSUM_SIZE].to_vec();
+        body[10..14].copy_from_slice(&u32::MAX.to_le_bytes());
+        let checksum = storage::fnv1a(&body);
+        body.extend_from_slice(&checksum.to_le_bytes());
+        assert!(Replay::decode(&body).is_err());
+    }
+}
@@ -0,0 +1,50 @@
+use failure::{err_msg, Error};
+use state::{Decode, Encode, StateReader, StateWriter};
+
+// xorshift32, small and good enough for picking ghost directions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rng {
+    state: u32,
+}
+
+impl Rng {
+    pub fn new(seed: u32) -> Rng {
+        // Zero is a fixed point of xorshift.
+        Rng {
+            state: if seed == 0 { 0x9e37_79b9 } else { seed },
+        }
+    }
+
+    // Each level replays the same sequence, which is what makes patterns work.
+    pub fn for_level(seed: u32, level: u32) -> Rng {
+        Rng::new(seed ^ level.wrapping_mul(0x9e37_79b9))
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        x
+    }
+
+    pub fn below(&mut self, n: u32) -> u32 {
+        self.next_u32() % n
+    }
+}
+
+impl Encode for Rng {
+    fn encode(&self, w: &mut StateWriter) {
+        w.write_u32(self.state);
+    }
+}
+
+impl Decode for Rng {
+    fn decode(r: &mut StateReader) -> Result<Rng, Error> {
+        match r.read_u32()? {
+            0 => Err(err_msg("Invalid random state")),
+            state => Ok(Rng { state }),
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use event::Event;
+use failure::Error;
+use level::Fruit;
+use state::{Encode, StateReader, StateWriter};
+
+pub const PELLET_POINTS: u32 = 10;
+pub const ENERGIZER_POINTS: u32 = 50;
+pub const GHOST_POINTS: [u32; 4] = [200, 400, 800, 1600];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScoreConfig {
+    pub lives: u32,
+    pub extra_life_score: Option<u32>,
+}
+
+impl Default for ScoreConfig {
+    fn default() -> ScoreConfig {
+        ScoreConfig {
+            lives: 3,
+            extra_life_score: Some(10_000),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Score {
+    config: ScoreConfig,
+    score: u32,
+    lives: u32,
+    extra_life_awarded: bool,
+    ghosts_eaten: usize,
+}
+
+impl Score {
+    pub fn new(config: ScoreConfig) -> Score {
+        Score {
+            config,
+            score: 0,
+            lives: config.lives,
+            extra_life_awarded: false,
+            ghosts_eaten: 0,
+        }
+    }
+
+    pub fn score(&self) -> u32 {
+        self.score
+    }
+
+    pub fn lives(&self) -> u32 {
+        self.lives
+    }
+
+    pub fn is_game_over(&self) -> bool {
+        self.lives == 0
+    }
+
+    pub fn eat_pellet(&mut self, events: &mut Vec<Event>) {
+        events.push(Event::PelletEaten {
+            points: PELLET_POINTS,
+        });
+        self.add(PELLET_POINTS, events);
+    }
+
+    // Eating an energizer starts a new fright, so the ghost chain restarts at 200.
+    pub fn eat_energizer(&mut self, events: &mut Vec<Event>) {
+        self.ghosts_eaten = 0;
+        events.push(Event::EnergizerEaten {
+            points: ENERGIZER_POINTS,
+        });
+        self.add(ENERGIZER_POINTS, events);
+    }
+
+    pub fn eat_ghost(&mut self, events: &mut Vec<Event>) -> u32 {
+        let index = self.ghosts_eaten.min(GHOST_POINTS.len() - 1);
+        let points = GHOST_POINTS[index];
+        self.ghosts_eaten += 1;
+        events.push(Event::GhostEaten { points });
+        self.add(points, events);
+        points
+    }
+
+    pub fn eat_fruit(&mut self, fruit: Fruit, points: u32, events: &mut Vec<Event>) {
+        events.push(Event::FruitEaten { fruit, points });
+        self.add(points, events);
+    }
+
+    pub fn end_fright(&mut self) {
+        self.ghosts_eaten = 0;
+    }
+
+    pub fn lose_life(&mut self, events: &mut Vec<Event>) {
+        if self.lives == 0 {
+            return;
+        }
+
+        self.lives -= 1;
+        self.ghosts_eaten = 0;
+        events.push(Event::LifeLost { lives: self.lives });
+        if self.lives == 0 {
+            events.push(Event::GameOver);
+        }
+    }
+
+    // The config isn't part of a save state, it stays as it is.
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), Error> {
+        self.score = r.read_u32()?;
+        self.lives = r.read_u32()?;
+        self.extra_life_awarded = r.read_bool()?;
+        self.ghosts_eaten = r.read_u32()? as usize;
+        Ok(())
+    }
+
+    fn add(&mut self, points: u32, events: &mut Vec<Event>) {
+        self.score += points;
+
+        if let Some(extra_life_score) = self.config.extra_life_score {
+            if !self.extra_life_awarded && self.score >= extra_life_score {
+                self.extra_life_awarded = true;
+                self.lives += 1;
+                events.push(Event::ExtraLife { lives: self.lives });
+            }
+        }
+    }
+}
+
+impl Encode for Score {
+    fn encode(&self, w: &mut StateWriter) {
+        w.write_u32(self.score);
+        w.write_u32(self.lives);
+        w.write_bool(self.extra_life_awarded);
+        w.write_u32(self.ghosts_eaten as u32);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score() -> Score {
+        Score::new(ScoreConfig::default())
+    }
+
+    #[test]
+    fn pellets_and_energizers() {
+        let mut score = score();
+        let mut events = Vec::new();
+
+        score.eat_pellet(&mut events);
+        score.eat_energizer(&mut events);
+
+        assert_eq!(score.score(), 60);
+        assert_eq!(
+            events,
+            vec![
+                Event::PelletEaten { points: 10 },
+                Event::EnergizerEaten { points: 50 },
+            ]
+        );
+    }
+
+    #[test]
+    fn consecutive_ghosts_double() {
+        let mut score = score();
+        let mut events = Vec::new();
+
+        score.eat_energizer(&mut events);
+        let points = (0..5).map(|_| score.eat_ghost(&mut events)).collect::<Vec<_>>();
+
+        assert_eq!(points, vec![200, 400, 800, 1600, 1600]);
+        assert_eq!(score.score(), 50 + 200 + 400 + 800 + 1600 + 1600);
+    }
+
+    #[test]
+    fn ghost_chain_restarts_with_new_fright() {
+        let mut score = score();
+        let mut events = Vec::new();
+
+        score.eat_energizer(&mut events);
+        score.eat_ghost(&mut events);
+        score.eat_ghost(&mut events);
+        score.eat_energizer(&mut events);
+        assert_eq!(score.eat_ghost(&mut events), 200);
+
+        score.end_fright();
+        assert_eq!(score.eat_ghost(&mut events), 200);
+    }
+
+    #[test]
+    fn fruit_points_come_from_caller() {
+        let mut score = score();
+        let mut events = Vec::new();
+
+        score.eat_fruit(Fruit::Melon, 1000, &mut events);
+
+        assert_eq!(score.score(), 1000);
+        assert_eq!(
+            events,
+            vec![Event::FruitEaten {
+                fruit: Fruit::Melon,
+                points: 1000,
+            }]
+        );
+    }
+
+    #[test]
+    fn extra_life_awarded_once() {
+        let mut score = Score::new(ScoreConfig {
+            lives: 3,
+            extra_life_score: Some(1000),
+        });
+        let mut events = Vec::new();
+
+        score.eat_fruit(Fruit::Apple, 700, &mut events);
+        assert_eq!(score.lives(), 3);
+
+        score.eat_fruit(Fruit::Peach, 500, &mut events);
+        assert_eq!(score.lives(), 4);
+        assert_eq!(events.last(), Some(&Event::ExtraLife { lives: 4 }));
+
+        score.eat_fruit(Fruit::Key, 5000, &mut events);
+        assert_eq!(score.lives(), 4);
+    }
+
+    #[test]
+    fn extra_life_can_be_disabled() {
+        let mut score = Score::new(ScoreConfig {
+            lives: 3,
+            extra_life_score: None,
+        });
+        let mut events = Vec::new();
+
+        score.eat_fruit(Fruit::Key, 50_000, &mut events);
+
+        assert_eq!(score.lives(), 3);
+    }
+
+    #[test]
+    fn game_over_after_last_life() {
+        let mut score = score();
+        let mut events = Vec::new();
+
+        score.lose_life(&mut events);
+        score.lose_life(&mut events);
+        assert!(!score.is_game_over());
+
+        score.lose_life(&mut events);
+        assert!(score.is_game_over());
+        assert_eq!(
+            events,
+            vec![
+                Event::LifeLost { lives: 2 },
+                Event::LifeLost { lives: 1 },
+                Event::LifeLost { lives: 0 },
+                Event::GameOver,
+            ]
+        );
+
+        score.lose_life(&mut events);
+        assert_eq!(events.len(), 4);
+    }
+}
@@ -0,0 +1,151 @@
+use std::f32::consts::PI;
+
+use Rect;
+
+// Shapes are drawn as plain triangles, x and y in view pixels for each
+// corner, three corners to a triangle. These add a shape's triangles to the
+// end of `vertices` so several can go to the renderer together.
+
+pub const FLOATS_PER_VERTEX: usize = 2;
+
+fn quad(vertices: &mut Vec<f32>, corners: [(f32, f32); 4]) {
+    for &index in &[0, 1, 2, 0, 2, 3] {
+        let (x, y) = corners[index];
+        vertices.push(x);
+        vertices.push(y);
+    }
+}
+
+pub fn rect(vertices: &mut Vec<f32>, rect: Rect) {
+    let (left, top) = (rect.x as f32, rect.y as f32);
+    let (right, bottom) = ((rect.x + rect.w) as f32, (rect.y + rect.h) as f32);
+    quad(vertices, [(left, top), (right, top), (right, bottom), (left, bottom)]);
+}
+
+// The border is inside the rect, so an outline is exactly as big as the
+// same rect filled.
+pub fn rect_outline(vertices: &mut Vec<f32>, r: Rect, thickness: i32) {
+    let t = thickness.min(r.w / 2).min(r.h / 2).max(1);
+    rect(vertices, Rect::new(r.x, r.y, r.w, t));
+    rect(vertices, Rect::new(r.x, r.y + r.h - t, r.w, t));
+    rect(vertices, Rect::new(r.x, r.y + t, t, r.h - 2 * t));
+    rect(vertices, Rect::new(r.x + r.w - t, r.y + t, t, r.h - 2 * t));
+}
+
+// Lines have square ends that reach half their thickness past the points,
+// so lines meeting at a point leave no gap in the corner.
+pub fn line(vertices: &mut Vec<f32>, from: (f32, f32), to: (f32, f32), thickness: f32) {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let length = (dx * dx + dy * dy).sqrt();
+    let half = thickness / 2.0;
+    // A line that goes nowhere is a dot.
+    let (ux, uy) = if length > 0.0 { (dx / length * half, dy / length * half) } else { (half, 0.0) };
+    let (nx, ny) = (-uy, ux);
+    let (start, end) = ((from.0 - ux, from.1 - uy), (to.0 + ux, to.1 + uy));
+    quad(
+        vertices,
+        [
+            (start.0 + nx, start.1 + ny),
+            (end.0 + nx, end.1 + ny),
+            (end.0 - nx, end.1 - ny),
+            (start.0 - nx, start.1 - ny),
+        ],
+    );
+}
+
+pub fn polyline(vertices: &mut Vec<f32>, points: &[(f32, f32)], thickness: f32) {
+    for pair in points.windows(2) {
+        line(vertices, pair[0], pair[1], thickness);
+    }
+}
+
+// Enough sides that a circle looks round at the size of the playfield,
+// without many more for small ones.
+fn sides(radius: f32) -> usize {
+    ((radius * 2.0).ceil() as usize).clamp(8, 64)
+}
+
+fn around(center: (f32, f32), radius: f32, side: usize, sides: usize) -> (f32, f32) {
+    let angle = side as f32 / sides as f32 * 2.0 * PI;
+    (center.0 + radius * angle.cos(), center.1 + radius * angle.sin())
+}
+
+pub fn circle(vertices: &mut Vec<f32>, center: (f32, f32), radius: f32) {
+    let sides = sides(radius);
+    for side in 0..sides {
+        let (a, b) = (around(center, radius, side, sides), around(center, radius, side + 1, sides));
+        vertices.extend_from_slice(&[center.0, center.1, a.0, a.1, b.0, b.1]);
+    }
+}
+
+// Like the rect outline, the border is inside the circle.
+pub fn circle_outline(vertices: &mut Vec<f32>, center: (f32, f32), radius: f32, thickness: f32) {
+    let sides = sides(radius);
+    let inner = (radius - thickness).max(0.0);
+    for side in 0..sides {
+        quad(
+            vertices,
+            [
+                around(center, radius, side, sides),
+                around(center, radius, side + 1, sides),
+                around(center, inner, side + 1, sides),
+                around(center, inner, side, sides),
+            ],
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn corners(vertices: &[f32]) -> Vec<(f32, f32)> {
+        vertices.chunks(FLOATS_PER_VERTEX).map(|v| (v[0], v[1])).collect()
+    }
+
+    #[test]
+    fn rects_are_two_triangles() {
+        let mut vertices = Vec::new();
+        rect(&mut vertices, Rect::new(1, 2, 3, 4));
+        assert_eq!(
+            corners(&vertices),
+            vec![(1.0, 2.0), (4.0, 2.0), (4.0, 6.0), (1.0, 2.0), (4.0, 6.0), (1.0, 6.0)]
+        );
+
+        // Four sides that don't overlap, so the outline covers as much as
+        // its border does.
+        vertices.clear();
+        rect_outline(&mut vertices, Rect::new(0, 0, 10, 8), 2);
+        let area: f32 = vertices
+            .chunks(6 * FLOATS_PER_VERTEX)
+            .map(|quad| (quad[2] - quad[0]) * (quad[5] - quad[1]))
+            .sum();
+        assert_eq!(area, 80.0 - 6.0 * 4.0);
+    }
+
+    #[test]
+    fn lines_are_as_thick_as_asked() {
+        let mut vertices = Vec::new();
+        line(&mut vertices, (2.0, 2.0), (10.0, 2.0), 2.0);
+        assert_eq!(corners(&vertices)[..3], [(1.0, 3.0), (11.0, 3.0), (11.0, 1.0)]);
+
+        vertices.clear();
+        polyline(&mut vertices, &[(0.0, 0.0), (4.0, 0.0), (4.0, 4.0), (0.0, 4.0)], 1.0);
+        assert_eq!(vertices.len(), 3 * 6 * FLOATS_PER_VERTEX);
+        vertices.clear();
+        polyline(&mut vertices, &[(0.0, 0.0)], 1.0);
+        assert!(vertices.is_empty());
+    }
+
+    #[test]
+    fn circles_stay_within_their_radius() {
+        let mut vertices = Vec::new();
+        circle(&mut vertices, (8.0, 8.0), 5.0);
+        circle_outline(&mut vertices, (8.0, 8.0), 5.0, 1.0);
+        for (x, y) in corners(&vertices) {
+            let distance = ((x - 8.0).powi(2) + (y - 8.0).powi(2)).sqrt();
+            assert!(distance <= 5.0 + 1e-4, "{} {}", x, y);
+        }
+        assert_eq!(vertices.len() % (3 * FLOATS_PER_VERTEX), 0);
+    }
+}
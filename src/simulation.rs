@@ -0,0 +1,115 @@
+use failure::Error;
+
+use level::LevelTable;
+use maze::Maze;
+use phase::Phase;
+use state::{self, Encode, StateWriter};
+use storage;
+use world::World;
+use Input;
+
+// The whole game without any rendering: the world and the phase driving it.
+// `PacMan` draws one of these, the headless tools just run it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Simulation {
+    world: World,
+    phase: Phase,
+}
+
+impl Simulation {
+    pub fn new(world: World) -> Simulation {
+        Simulation {
+            world,
+            phase: Phase::default(),
+        }
+    }
+
+    // The builtin levels and maze.
+    pub fn arcade(seed: u32) -> Simulation {
+        Simulation::new(World::new(LevelTable::arcade(), Maze::arcade(), seed))
+    }
+
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    pub fn phase(&self) -> &Phase {
+        &self.phase
+    }
+
+    pub fn seed(&self) -> u32 {
+        self.world.seed()
+    }
+
+    pub fn restart(&mut self, seed: u32) {
+        self.world.restart(seed);
+        self.phase = Phase::default();
+    }
+
+    pub fn update(&mut self, input: &Input) {
+        self.world.clear_events();
+        self.phase.update(&mut self.world, input);
+    }
+
+    pub fn save_state(&self) -> Vec<u8> {
+        state::encode(|w| w.write(self))
+    }
+
+    // Leaves the simulation untouched if the state can't be loaded.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), Error> {
+        let mut r = state::decode(data)?;
+        let mut world = self.world.clone();
+        world.load_state(&mut r)?;
+        let phase = r.read()?;
+        r.finish()?;
+
+        self.world = world;
+        self.phase = phase;
+        Ok(())
+    }
+
+    // Equal for equal states, for comparing runs across machines.
+    pub fn state_hash(&self) -> u32 {
+        storage::fnv1a(&self.save_state())
+    }
+
+    // Like `state_hash`, but also covers the setup outside the save state,
+    // so it's only equal for games that will play out the same.
+    pub fn setup_hash(&self) -> u32 {
+        storage::fnv1a(&state::encode(|w| {
+            w.write(self);
+            self.world.encode_setup(w);
+        }))
+    }
+}
+
+impl Encode for Simulation {
+    fn encode(&self, w: &mut StateWriter) {
+        w.write(&self.world);
+        w.write(&self.phase);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Buttons;
+
+    #[test]
+    fn failed_load_leaves_state_alone() {
+        let mut simulation = Simulation::arcade(1);
+        let mut input = Input::new();
+        input.update(Buttons::START);
+        simulation.update(&input);
+
+        let before = simulation.clone();
+        let mut data = simulation.save_state();
+        data.truncate(data.len() / 2);
+        assert!(simulation.load_state(&data).is_err());
+        assert_eq!(simulation, before);
+
+        let mut other = Simulation::arcade(1);
+        other.load_state(&before.save_state()).unwrap();
+        assert_eq!(other.state_hash(), before.state_hash());
+    }
+}
@@ -0,0 +1,266 @@
+use failure::{err_msg, Error};
+use maze::{Direction, TilePos};
+use storage;
+use Buttons;
+
+// Save states are a header, the fields of the game in a fixed order and a
+// checksum:
+//
+//   magic "PMSS", version u16
+//   body, written by the `Encode` impls
+//   FNV-1a checksum u32
+//
+// All integers are little endian. Bump VERSION whenever the body changes,
+// old states are rejected rather than misread.
+const MAGIC: &[u8; 4] = b"PMSS";
+pub const VERSION: u16 = 9;
+const HEADER_SIZE: usize = 6;
+const CHECKSUM_SIZE: usize = 4;
+
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_i32(&mut self, value: i32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_u8(value as u8);
+    }
+
+    pub fn write<T: Encode + ?Sized>(&mut self, value: &T) {
+        value.encode(self);
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.data.len() < len {
+            return Err(err_msg("State is truncated"));
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, Error> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, Error> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    pub fn read_i32(&mut self) -> Result<i32, Error> {
+        Ok(self.read_u32()? as i32)
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, Error> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            value => Err(err_msg(format!("Invalid bool {}", value))),
+        }
+    }
+
+    pub fn read<T: Decode>(&mut self) -> Result<T, Error> {
+        T::decode(self)
+    }
+
+    // Fails if anything was left unread, which means the layout doesn't match.
+    pub fn finish(self) -> Result<(), Error> {
+        if self.data.is_empty() {
+            Ok(())
+        } else {
+            Err(err_msg(format!("{} bytes left over", self.data.len())))
+        }
+    }
+}
+
+pub trait Encode {
+    fn encode(&self, w: &mut StateWriter);
+}
+
+pub trait Decode: Sized {
+    fn decode(r: &mut StateReader) -> Result<Self, Error>;
+}
+
+pub fn encode<F: FnOnce(&mut StateWriter)>(f: F) -> Vec<u8> {
+    let mut w = StateWriter { data: Vec::new() };
+    w.data.extend_from_slice(MAGIC);
+    w.write_u16(VERSION);
+    f(&mut w);
+    let checksum = storage::fnv1a(&w.data);
+    w.write_u32(checksum);
+    w.data
+}
+
+// Checks the header and checksum and returns a reader over the body.
+pub fn decode(data: &[u8]) -> Result<StateReader<'_>, Error> {
+    if data.len() < HEADER_SIZE + CHECKSUM_SIZE {
+        return Err(err_msg("State is truncated"));
+    }
+
+    let (body, checksum) = data.split_at(data.len() - CHECKSUM_SIZE);
+    let checksum = u32::from_le_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]);
+    if storage::fnv1a(body) != checksum {
+        return Err(err_msg("Checksum mismatch"));
+    }
+
+    if &body[0..4] != MAGIC {
+        return Err(err_msg("Invalid magic"));
+    }
+
+    let version = u16::from_le_bytes([body[4], body[5]]);
+    if version != VERSION {
+        return Err(err_msg(format!("Unsupported version {}", version)));
+    }
+
+    Ok(StateReader {
+        data: &body[HEADER_SIZE..],
+    })
+}
+
+impl Encode for u32 {
+    fn encode(&self, w: &mut StateWriter) {
+        w.write_u32(*self);
+    }
+}
+
+impl Decode for u32 {
+    fn decode(r: &mut StateReader) -> Result<u32, Error> {
+        r.read_u32()
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode(&self, w: &mut StateWriter) {
+        match *self {
+            Some(ref value) => {
+                w.write_bool(true);
+                value.encode(w);
+            }
+            None => w.write_bool(false),
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode(r: &mut StateReader) -> Result<Option<T>, Error> {
+        if r.read_bool()? {
+            Ok(Some(T::decode(r)?))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl Encode for Direction {
+    fn encode(&self, w: &mut StateWriter) {
+        w.write_u8(match *self {
+            Direction::Up => 0,
+            Direction::Left => 1,
+            Direction::Down => 2,
+            Direction::Right => 3,
+        });
+    }
+}
+
+impl Decode for Direction {
+    fn decode(r: &mut StateReader) -> Result<Direction, Error> {
+        match r.read_u8()? {
+            0 => Ok(Direction::Up),
+            1 => Ok(Direction::Left),
+            2 => Ok(Direction::Down),
+            3 => Ok(Direction::Right),
+            value => Err(err_msg(format!("Invalid direction {}", value))),
+        }
+    }
+}
+
+impl Encode for TilePos {
+    fn encode(&self, w: &mut StateWriter) {
+        w.write_i32(self.x);
+        w.write_i32(self.y);
+    }
+}
+
+impl Decode for TilePos {
+    fn decode(r: &mut StateReader) -> Result<TilePos, Error> {
+        Ok(TilePos::new(r.read_i32()?, r.read_i32()?))
+    }
+}
+
+impl Encode for Buttons {
+    fn encode(&self, w: &mut StateWriter) {
+        w.write_u8(self.bits());
+    }
+}
+
+impl Decode for Buttons {
+    fn decode(r: &mut StateReader) -> Result<Buttons, Error> {
+        Ok(Buttons::from_bits(r.read_u8()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_values() {
+        let data = encode(|w| {
+            w.write(&Some(Direction::Right));
+            w.write(&TilePos::new(-3, 31));
+            w.write_bool(true);
+            w.write(&None::<u32>);
+        });
+
+        let mut r = decode(&data).unwrap();
+        assert_eq!(r.read::<Option<Direction>>().unwrap(), Some(Direction::Right));
+        assert_eq!(r.read::<TilePos>().unwrap(), TilePos::new(-3, 31));
+        assert!(r.read_bool().unwrap());
+        assert_eq!(r.read::<Option<u32>>().unwrap(), None);
+        r.finish().unwrap();
+    }
+
+    #[test]
+    fn rejects_corrupt_data() {
+        let data = encode(|w| w.write_u32(42));
+
+        let mut flipped = data.clone();
+        flipped[HEADER_SIZE] ^= 0xff;
+        assert!(decode(&flipped).is_err());
+
+        assert!(decode(&data[..data.len() - 1]).is_err());
+        assert!(decode(b"garbage").is_err());
+
+        let mut r = decode(&data).unwrap();
+        assert!(r.read_u16().is_ok());
+        assert!(r.finish().is_err());
+    }
+}
@@ -0,0 +1,60 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+use bridge;
+use failure::Error;
+
+// The host decides where persistent files live. Without one we fall back to
+// the XDG data directory on Linux.
+pub fn data_dir() -> Option<PathBuf> {
+    bridge::get_data_path().or_else(xdg_data_dir)
+}
+
+#[cfg(target_os = "linux")]
+fn xdg_data_dir() -> Option<PathBuf> {
+    env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share")))
+        .map(|path| path.join("pacman"))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn xdg_data_dir() -> Option<PathBuf> {
+    None
+}
+
+pub fn read<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, Error> {
+    let mut buf = Vec::new();
+    File::open(path)?.read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+// Writes to a temporary file first so a crash never leaves a half written file behind.
+pub fn write<P: AsRef<Path>>(path: P, data: &[u8]) -> Result<(), Error> {
+    let path = path.as_ref();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+
+    {
+        let mut file = File::create(&tmp)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+    }
+    fs::rename(&tmp, path)?;
+
+    Ok(())
+}
+
+pub fn fnv1a(data: &[u8]) -> u32 {
+    data.iter().fold(0x811c_9dc5, |hash, &byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    })
+}
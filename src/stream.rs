@@ -0,0 +1,453 @@
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+
+use failure::{err_msg, Error};
+
+use maze::MAX_SIZE;
+use multiplayer::MAX_PLAYERS;
+use replay::{read_varint, write_varint};
+
+// Live games for spectators. A game serving a stream sends its save state
+// after every update to everyone watching, as the bytes that changed since the
+// tick before. Viewers load each state and draw it, they never simulate, so
+// they see exactly what the player sees.
+//
+// Over TCP the stream starts with a header:
+//
+//   magic "PMST", version u16
+//
+// followed by messages:
+//
+//   length u32 of the rest of the message
+//   kind u8, 0 for a keyframe and 1 for a delta
+//   tick u32, counting states from when the stream started
+//   a keyframe holds the whole save state
+//   a delta holds the length of the new state as LEB128, then runs of
+//   (unchanged bytes as LEB128, changed bytes as LEB128, the changed bytes)
+//   against the state before, up to the end of the message
+//
+// Every viewer gets a keyframe first and only deltas after that. All other
+// integers are little endian.
+const MAGIC: &[u8; 4] = b"PMST";
+const VERSION: u16 = 1;
+const HEADER_SIZE: usize = 6;
+
+const KEYFRAME: u8 = 0;
+const DELTA: u8 = 1;
+
+// Unchanged bytes shorter than this between two changes are sent anyway, a
+// new run would take about as much.
+const MIN_GAP: usize = 3;
+
+// Save states are about 2 KiB for the arcade maze, but a state holds a maze
+// for every player and mazes go up to MAX_SIZE square at two bytes a tile.
+// Anything longer than that and some room for the rest is not a stream of
+// ours and would only make viewers buffer whatever they are sent.
+const MAX_MESSAGE_SIZE: usize = MAX_PLAYERS * (MAX_SIZE * MAX_SIZE * 2) as usize + (1 << 16);
+
+// Viewers that fall this far behind are dropped rather than slowing the game.
+const MAX_PENDING: usize = 1 << 20;
+
+// The changes that turn `old` into `new`.
+pub fn diff(old: &[u8], new: &[u8]) -> Vec<u8> {
+    let mut delta = Vec::new();
+    write_varint(&mut delta, new.len() as u32);
+
+    let changed = |i: usize| old.get(i) != Some(&new[i]);
+    let mut done = 0;
+    let mut i = 0;
+    while i < new.len() {
+        if !changed(i) {
+            i += 1;
+            continue;
+        }
+        // Extend the run until there is a long enough stretch of equal bytes.
+        let start = i;
+        let mut end = i + 1;
+        while end < new.len() && (end..(end + MIN_GAP).min(new.len())).any(changed) {
+            end += 1;
+        }
+        write_varint(&mut delta, (start - done) as u32);
+        write_varint(&mut delta, (end - start) as u32);
+        delta.extend_from_slice(&new[start..end]);
+        done = end;
+        i = end;
+    }
+    delta
+}
+
+// Applies changes made by `diff` to the state they were made against.
+pub fn patch(old: &[u8], delta: &[u8]) -> Result<Vec<u8>, Error> {
+    let (len, mut runs) = read_varint(delta)?;
+    let mut state = old.to_vec();
+    state.resize(len as usize, 0);
+
+    let mut pos = 0;
+    while !runs.is_empty() {
+        let (skip, rest) = read_varint(runs)?;
+        let (count, rest) = read_varint(rest)?;
+        let (skip, count) = (skip as usize, count as usize);
+        if pos + skip + count > state.len() || rest.len() < count {
+            return Err(err_msg("Delta runs past the end of the state"));
+        }
+        pos += skip;
+        state[pos..pos + count].copy_from_slice(&rest[..count]);
+        pos += count;
+        runs = &rest[count..];
+    }
+    // Bytes past the end of the old state can't have stayed the same.
+    if state.len() > old.len() && pos < state.len() {
+        return Err(err_msg("Delta leaves new bytes unset"));
+    }
+    Ok(state)
+}
+
+fn message(kind: u8, tick: u32, body: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(9 + body.len());
+    data.extend_from_slice(&(5 + body.len() as u32).to_le_bytes());
+    data.push(kind);
+    data.extend_from_slice(&tick.to_le_bytes());
+    data.extend_from_slice(body);
+    data
+}
+
+struct Viewer {
+    stream: TcpStream,
+    addr: SocketAddr,
+    pending: Vec<u8>,
+}
+
+impl Viewer {
+    // Sends what the socket takes without waiting. False once the viewer
+    // is gone or too far behind.
+    fn flush(&mut self) -> bool {
+        while !self.pending.is_empty() {
+            match self.stream.write(&self.pending) {
+                Ok(0) => return false,
+                Ok(n) => {
+                    self.pending.drain(..n);
+                }
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => {
+                    info!("Viewer {} left: {}", self.addr, e);
+                    return false;
+                }
+            }
+        }
+        if self.pending.len() > MAX_PENDING {
+            warn!("Dropping viewer {}, too far behind", self.addr);
+            return false;
+        }
+        true
+    }
+}
+
+// Sends the game to anyone who connects.
+pub struct StreamServer {
+    listener: TcpListener,
+    viewers: Vec<Viewer>,
+    tick: u32,
+    last: Option<Vec<u8>>,
+    oversized: bool,
+}
+
+impl StreamServer {
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> Result<StreamServer, Error> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(StreamServer {
+            listener,
+            viewers: Vec::new(),
+            tick: 0,
+            last: None,
+            oversized: false,
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    pub fn viewers(&self) -> usize {
+        self.viewers.len()
+    }
+
+    // Sends the state after an update.
+    pub fn publish(&mut self, state: &[u8]) {
+        let delta = match self.last {
+            Some(ref last) => message(DELTA, self.tick, &diff(last, state)),
+            None => Vec::new(),
+        };
+        // Viewers would drop the stream over it, so a state that doesn't fit
+        // in a message is skipped and the next one is sent against the last
+        // one that did.
+        if 5 + state.len() > MAX_MESSAGE_SIZE || delta.len() > 4 + MAX_MESSAGE_SIZE {
+            if !self.oversized {
+                warn!("Not streaming a save state of {} bytes, it's too long", state.len());
+                self.oversized = true;
+            }
+            return;
+        }
+        self.oversized = false;
+        for viewer in self.viewers.iter_mut() {
+            viewer.pending.extend_from_slice(&delta);
+        }
+
+        loop {
+            match self.listener.accept() {
+                Ok((stream, addr)) => {
+                    if let Err(e) = stream.set_nonblocking(true).and_then(|_| stream.set_nodelay(true)) {
+                        warn!("Ignoring viewer {}: {}", addr, e);
+                        continue;
+                    }
+                    info!("Viewer {} joined", addr);
+                    let mut pending = MAGIC.to_vec();
+                    pending.extend_from_slice(&VERSION.to_le_bytes());
+                    pending.extend(message(KEYFRAME, self.tick, state));
+                    self.viewers.push(Viewer { stream, addr, pending });
+                }
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    warn!("Failed to accept a viewer: {}", e);
+                    break;
+                }
+            }
+        }
+
+        self.viewers.retain_mut(Viewer::flush);
+        self.last = Some(state.to_vec());
+        self.tick += 1;
+    }
+}
+
+// Turns the bytes of a stream back into save states. Bytes can be fed in
+// pieces of any size as they arrive.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StreamReader {
+    buffer: Vec<u8>,
+    has_header: bool,
+    tick: Option<u32>,
+    state: Vec<u8>,
+}
+
+impl StreamReader {
+    pub fn new() -> StreamReader {
+        StreamReader::default()
+    }
+
+    pub fn feed(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    // The tick of the current state, None before the first keyframe.
+    pub fn tick(&self) -> Option<u32> {
+        self.tick
+    }
+
+    pub fn state(&self) -> Option<&[u8]> {
+        self.tick.map(|_| &self.state[..])
+    }
+
+    // Moves on to the next state if all of it has arrived and returns its tick.
+    pub fn advance(&mut self) -> Result<Option<u32>, Error> {
+        if !self.has_header {
+            if self.buffer.len() < HEADER_SIZE {
+                return Ok(None);
+            }
+            if &self.buffer[0..4] != MAGIC {
+                return Err(err_msg("Invalid magic"));
+            }
+            let version = u16::from_le_bytes([self.buffer[4], self.buffer[5]]);
+            if version != VERSION {
+                return Err(err_msg(format!("Unsupported version {}", version)));
+            }
+            self.buffer.drain(..HEADER_SIZE);
+            self.has_header = true;
+        }
+
+        if self.buffer.len() < 4 {
+            return Ok(None);
+        }
+        let len = u32::from_le_bytes([self.buffer[0], self.buffer[1], self.buffer[2], self.buffer[3]]) as usize;
+        if len < 5 {
+            return Err(err_msg(format!("Message of {} bytes is too short", len)));
+        }
+        if len > MAX_MESSAGE_SIZE {
+            return Err(err_msg(format!("Message of {} bytes is too long", len)));
+        }
+        if self.buffer.len() < 4 + len {
+            return Ok(None);
+        }
+
+        let body = &self.buffer[4..4 + len];
+        let tick = u32::from_le_bytes([body[1], body[2], body[3], body[4]]);
+        self.state = match (body[0], self.tick) {
+            (KEYFRAME, _) => body[5..].to_vec(),
+            (DELTA, Some(_)) => patch(&self.state, &body[5..])?,
+            (DELTA, None) => return Err(err_msg("Delta before the first keyframe")),
+            (kind, _) => return Err(err_msg(format!("Unknown message kind {}", kind))),
+        };
+        self.tick = Some(tick);
+        self.buffer.drain(..4 + len);
+        Ok(Some(tick))
+    }
+}
+
+// Watches a stream over TCP without ever waiting on it.
+pub struct StreamClient {
+    stream: TcpStream,
+    reader: StreamReader,
+}
+
+impl StreamClient {
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<StreamClient, Error> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nonblocking(true)?;
+        Ok(StreamClient {
+            stream,
+            reader: StreamReader::new(),
+        })
+    }
+
+    pub fn tick(&self) -> Option<u32> {
+        self.reader.tick()
+    }
+
+    pub fn state(&self) -> Option<&[u8]> {
+        self.reader.state()
+    }
+
+    // Catches up with everything that arrived. Returns whether there is a
+    // new state.
+    pub fn poll(&mut self) -> Result<bool, Error> {
+        let mut buffer = [0; 4096];
+        loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => return Err(err_msg("The stream ended")),
+                Ok(n) => self.reader.feed(&buffer[..n]),
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let mut changed = false;
+        while self.reader.advance()?.is_some() {
+            changed = true;
+        }
+        Ok(changed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use level::LevelTable;
+    use maze::Maze;
+    use simulation::Simulation;
+    use std::thread;
+    use std::time::Duration;
+    use world::World;
+    use {Buttons, Input};
+
+    #[test]
+    fn patch_undoes_diff() {
+        let old = vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
+        for new in [
+            vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10],
+            vec![1, 0, 3, 4, 5, 6, 7, 8, 0, 10],
+            vec![1, 2, 3],
+            vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12],
+            vec![],
+        ]
+        .iter()
+        {
+            let delta = diff(&old, new);
+            assert_eq!(&patch(&old, &delta).unwrap(), new);
+        }
+        assert_eq!(diff(&old, &old), vec![10]);
+        assert!(patch(&old, &[10, 9, 5, 0]).is_err());
+    }
+
+    #[test]
+    fn a_tick_changes_little() {
+        let mut simulation = Simulation::arcade(1);
+        let mut input = Input::new();
+        input.update(Buttons::START);
+        for _ in 0..300 {
+            simulation.update(&input);
+            input.update(Buttons::LEFT);
+        }
+        let before = simulation.save_state();
+        simulation.update(&input);
+        let after = simulation.save_state();
+        let delta = diff(&before, &after);
+        assert!(delta.len() * 10 < after.len(), "{} of {} bytes", delta.len(), after.len());
+    }
+
+    #[test]
+    fn reader_takes_bytes_in_any_pieces() {
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&VERSION.to_le_bytes());
+        data.extend(message(KEYFRAME, 7, &[1, 2, 3]));
+        data.extend(message(DELTA, 8, &diff(&[1, 2, 3], &[1, 5, 3, 4])));
+
+        let mut reader = StreamReader::new();
+        let mut ticks = Vec::new();
+        for byte in data {
+            reader.feed(&[byte]);
+            while let Some(tick) = reader.advance().unwrap() {
+                ticks.push(tick);
+            }
+        }
+        assert_eq!(ticks, vec![7, 8]);
+        assert_eq!(reader.state(), Some(&[1, 5, 3, 4][..]));
+    }
+
+    #[test]
+    fn reader_rejects_huge_messages() {
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&VERSION.to_le_bytes());
+        data.extend_from_slice(&u32::MAX.to_le_bytes());
+
+        let mut reader = StreamReader::new();
+        reader.feed(&data);
+        assert!(reader.advance().is_err());
+    }
+
+    #[test]
+    fn the_largest_state_fits_in_a_message() {
+        let mut world = World::new(LevelTable::arcade(), Maze::filled(MAX_SIZE, MAX_SIZE), 1);
+        world.start_game(MAX_PLAYERS);
+        let state = Simulation::new(world).save_state();
+        assert!(5 + state.len() <= MAX_MESSAGE_SIZE, "{} bytes", state.len());
+    }
+
+    #[test]
+    fn viewers_see_the_game() {
+        let mut server = StreamServer::bind("127.0.0.1:0").unwrap();
+        let mut client = StreamClient::connect(server.local_addr().unwrap()).unwrap();
+
+        let mut simulation = Simulation::arcade(3);
+        let mut input = Input::new();
+        input.update(Buttons::START);
+        for tick in 0..200 {
+            simulation.update(&input);
+            input.update(if (tick / 40) % 2 == 0 { Buttons::LEFT } else { Buttons::UP });
+            server.publish(&simulation.save_state());
+        }
+        assert_eq!(server.viewers(), 1);
+
+        for _ in 0..500 {
+            client.poll().unwrap();
+            if client.tick() == Some(199) {
+                break;
+            }
+            thread::sleep(Duration::from_millis(2));
+        }
+        let mut viewed = Simulation::arcade(0);
+        viewed.load_state(client.state().unwrap()).unwrap();
+        assert_eq!(viewed.state_hash(), simulation.state_hash());
+    }
+}
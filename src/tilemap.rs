@@ -0,0 +1,169 @@
+use std::ops::Range;
+
+use Rect;
+
+// x, y, u, v for each corner of a tile.
+pub const FLOATS_PER_TILE: usize = 16;
+pub const INDICES_PER_TILE: usize = 6;
+
+// The vertices of a grid of tiles drawn from an atlas, kept so a renderer
+// can upload them once and then only the tiles that changed. Positions are
+// in pixels from the grid's top left and texture coordinates in atlas
+// pixels. Empty tiles collapse to a point and draw nothing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tilemap {
+    width: i32,
+    height: i32,
+    tile_size: i32,
+    tiles: Vec<Option<Rect>>,
+    vertices: Vec<f32>,
+    dirty: Vec<bool>,
+}
+
+impl Tilemap {
+    pub fn new(width: i32, height: i32, tile_size: i32) -> Tilemap {
+        let len = (width.max(0) * height.max(0)) as usize;
+        Tilemap {
+            width,
+            height,
+            tile_size,
+            tiles: vec![None; len],
+            vertices: vec![0.0; len * FLOATS_PER_TILE],
+            dirty: vec![false; len],
+        }
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    pub fn len(&self) -> usize {
+        self.tiles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if x >= 0 && x < self.width && y >= 0 && y < self.height {
+            Some((y * self.width + x) as usize)
+        } else {
+            None
+        }
+    }
+
+    pub fn get(&self, x: i32, y: i32) -> Option<Rect> {
+        self.index(x, y).and_then(|index| self.tiles[index])
+    }
+
+    // Only marks the tile dirty if it actually changes.
+    pub fn set(&mut self, x: i32, y: i32, src: Option<Rect>) {
+        let index = match self.index(x, y) {
+            Some(index) => index,
+            None => return,
+        };
+        if self.tiles[index] == src {
+            return;
+        }
+        self.tiles[index] = src;
+        self.dirty[index] = true;
+
+        let corners = match src {
+            Some(src) => {
+                let (left, top) = ((x * self.tile_size) as f32, (y * self.tile_size) as f32);
+                let (right, bottom) = (left + self.tile_size as f32, top + self.tile_size as f32);
+                let (u0, v0) = (src.x as f32, src.y as f32);
+                let (u1, v1) = ((src.x + src.w) as f32, (src.y + src.h) as f32);
+                [left, top, u0, v0, right, top, u1, v0, right, bottom, u1, v1, left, bottom, u0, v1]
+            }
+            None => [0.0; FLOATS_PER_TILE],
+        };
+        self.vertices[index * FLOATS_PER_TILE..(index + 1) * FLOATS_PER_TILE].copy_from_slice(&corners);
+    }
+
+    pub fn vertices(&self) -> &[f32] {
+        &self.vertices
+    }
+
+    // Two triangles per tile, the same for every grid of this size.
+    pub fn indices(&self) -> Vec<u32> {
+        (0..self.len() as u32)
+            .flat_map(|tile| {
+                let first = tile * 4;
+                vec![first, first + 1, first + 2, first, first + 2, first + 3]
+            })
+            .collect()
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty.contains(&true)
+    }
+
+    // The tiles changed since last time, as runs of neighbouring tile
+    // indices, so each run can be uploaded in one go.
+    pub fn take_dirty(&mut self) -> Vec<Range<usize>> {
+        let mut runs: Vec<Range<usize>> = Vec::new();
+        for (index, dirty) in self.dirty.iter_mut().enumerate() {
+            if !*dirty {
+                continue;
+            }
+            *dirty = false;
+            match runs.last_mut() {
+                Some(run) if run.end == index => run.end += 1,
+                _ => runs.push(index..index + 1),
+            }
+        }
+        runs
+    }
+
+    // Everything is uploaded at once when the buffer is made.
+    pub fn clear_dirty(&mut self) {
+        for dirty in self.dirty.iter_mut() {
+            *dirty = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiles_become_quads() {
+        let mut map = Tilemap::new(3, 2, 8);
+        map.set(1, 1, Some(Rect::new(16, 0, 8, 8)));
+        let index = 4 * FLOATS_PER_TILE;
+        assert_eq!(
+            map.vertices()[index..index + FLOATS_PER_TILE],
+            [8.0, 8.0, 16.0, 0.0, 16.0, 8.0, 24.0, 0.0, 16.0, 16.0, 24.0, 8.0, 8.0, 16.0, 16.0, 8.0]
+        );
+        assert_eq!(map.indices()[4 * INDICES_PER_TILE..5 * INDICES_PER_TILE], [16, 17, 18, 16, 18, 19]);
+
+        map.set(1, 1, None);
+        assert!(map.vertices().iter().all(|&v| v == 0.0));
+        assert_eq!(map.get(1, 1), None);
+    }
+
+    #[test]
+    fn only_changed_tiles_are_dirty() {
+        let mut map = Tilemap::new(4, 4, 8);
+        let src = Some(Rect::new(0, 0, 8, 8));
+        for &(x, y) in &[(0, 0), (1, 0), (2, 0), (3, 2), (9, 9)] {
+            map.set(x, y, src);
+        }
+        map.set(0, 3, None);
+        assert_eq!(map.take_dirty(), vec![0..3, 11..12]);
+        assert!(!map.is_dirty());
+
+        // Setting a tile to what it already is changes nothing.
+        map.set(1, 0, src);
+        assert!(map.take_dirty().is_empty());
+        map.set(1, 0, None);
+        assert_eq!(map.take_dirty(), vec![1..2]);
+    }
+}
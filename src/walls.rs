@@ -0,0 +1,166 @@
+use maze::{Maze, Tile, TilePos, TILE_SIZE};
+use Rect;
+
+// Wall tiles are drawn from an atlas with one tile for every combination of
+// open neighbours, sixteen to a row. Lines run along the sides of a wall
+// that face open tiles, two of them, rounded where they turn a corner.
+pub const ATLAS_COLUMNS: i32 = 16;
+pub const ATLAS_SIZE: i32 = ATLAS_COLUMNS * TILE_SIZE;
+
+// How far in from the open side each line is, in pixels.
+const LINES: [i32; 2] = [1, 3];
+const HALF: f32 = TILE_SIZE as f32 / 2.0;
+
+// Neighbours in mask bit order, clockwise from straight up.
+const NEIGHBOURS: [(i32, i32); 8] = [(0, -1), (1, -1), (1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1)];
+
+fn is_open(maze: &Maze, pos: TilePos) -> bool {
+    // The house walls end at the door, they don't run across it.
+    maze.get(pos) != Tile::Wall
+}
+
+// Which neighbours of a wall are open, one bit each in `NEIGHBOURS` order.
+// The sides of the maze wrap around like the tunnels do and everything above
+// and below it is wall.
+pub fn mask(maze: &Maze, pos: TilePos) -> u8 {
+    NEIGHBOURS.iter().enumerate().fold(0, |mask, (bit, &(dx, dy))| {
+        if is_open(maze, TilePos::new(pos.x + dx, pos.y + dy)) {
+            mask | 1 << bit
+        } else {
+            mask
+        }
+    })
+}
+
+// Where the tile for a mask is in the atlas.
+pub fn atlas_tile(mask: u8) -> Rect {
+    let (col, row) = (mask as i32 % ATLAS_COLUMNS, mask as i32 / ATLAS_COLUMNS);
+    Rect::new(col * TILE_SIZE, row * TILE_SIZE, TILE_SIZE, TILE_SIZE)
+}
+
+fn is_set(mask: u8, dx: i32, dy: i32) -> bool {
+    let bit = NEIGHBOURS.iter().position(|&offset| offset == (dx, dy)).unwrap();
+    mask & (1 << bit) != 0
+}
+
+// Each quarter of a tile only looks at the three neighbours next to it. `u`
+// and `v` are how far a pixel is from the tile's side and top or bottom
+// edge, towards the middle.
+fn distance(mask: u8, dx: i32, dy: i32, u: f32, v: f32) -> Option<f32> {
+    let side = is_set(mask, dx, 0);
+    let end = is_set(mask, 0, dy);
+    match (side, end, is_set(mask, dx, dy)) {
+        // The outside of a corner, the lines bend around a quarter of the
+        // tile.
+        (true, true, _) => Some(HALF - ((HALF - u).powi(2) + (HALF - v).powi(2)).sqrt()),
+        (true, false, _) => Some(u),
+        (false, true, _) => Some(v),
+        // The inside of a corner, around the open tile touching this one's
+        // corner.
+        (false, false, true) => Some((u * u + v * v).sqrt()),
+        (false, false, false) => None,
+    }
+}
+
+fn is_line(mask: u8, x: i32, y: i32) -> bool {
+    let (dx, u) = if x < TILE_SIZE / 2 { (-1, x) } else { (1, TILE_SIZE - 1 - x) };
+    let (dy, v) = if y < TILE_SIZE / 2 { (-1, y) } else { (1, TILE_SIZE - 1 - y) };
+    distance(mask, dx, dy, u as f32 + 0.5, v as f32 + 0.5)
+        .is_some_and(|d| d >= 0.0 && LINES.contains(&(d as i32)))
+}
+
+// RGBA pixels of the whole atlas, white lines on black. The renderer tints
+// them with the wall colour.
+pub fn atlas() -> Vec<u8> {
+    let mut pixels = vec![0; (ATLAS_SIZE * ATLAS_SIZE * 4) as usize];
+    for mask in 0..=255 {
+        let tile = atlas_tile(mask);
+        for y in 0..TILE_SIZE {
+            for x in 0..TILE_SIZE {
+                if is_line(mask, x, y) {
+                    let index = (((tile.y + y) * ATLAS_SIZE + tile.x + x) * 4) as usize;
+                    pixels[index..index + 4].copy_from_slice(&[255, 255, 255, 255]);
+                }
+            }
+        }
+    }
+    pixels
+}
+
+// The wall tiles of a maze and the atlas tile to draw each with. Walls
+// without an open neighbour are left out, there is nothing to draw.
+pub fn tiles(maze: &Maze) -> impl Iterator<Item = (TilePos, Rect)> + '_ {
+    maze.tiles()
+        .filter(|&(_, tile)| tile == Tile::Wall)
+        .map(move |(pos, _)| (pos, mask(maze, pos)))
+        .filter(|&(_, mask)| mask != 0)
+        .map(|(pos, mask)| (pos, atlas_tile(mask)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(mask: u8) -> Vec<String> {
+        (0..TILE_SIZE)
+            .map(|y| (0..TILE_SIZE).map(|x| if is_line(mask, x, y) { '#' } else { '.' }).collect())
+            .collect()
+    }
+
+    #[test]
+    fn masks_follow_the_neighbours() {
+        let maze = Maze::parse("###\n#  \n# #\n").unwrap();
+        assert_eq!(mask(&maze, TilePos::new(1, 0)), 0b0001_1000);
+        assert_eq!(mask(&maze, TilePos::new(2, 0)), 0b0011_0000);
+        // Above the maze is wall, the sides wrap.
+        assert_eq!(mask(&maze, TilePos::new(0, 0)), 0b0010_1000);
+        assert_eq!(mask(&maze, TilePos::new(0, 2)), 0b1000_0110);
+        assert!(is_set(0b0000_0100, 1, 0) && !is_set(0b0000_0100, 0, 1));
+    }
+
+    #[test]
+    fn straight_walls_have_two_lines() {
+        let below = 1 << 4 | 1 << 3 | 1 << 5;
+        let expected = [".", ".", ".", ".", "#", ".", "#", "."];
+        for (row, line) in rows(below).iter().zip(expected.iter()) {
+            assert_eq!(*row, line.repeat(TILE_SIZE as usize));
+        }
+    }
+
+    #[test]
+    fn corners_are_rounded() {
+        // Open above and to the left: the outside of a corner.
+        let outside = rows(1 << 0 | 1 << 6 | 1 << 7);
+        assert_eq!(outside[0], "........");
+        assert_eq!(outside[1], "..######");
+        assert_eq!(outside[2], ".##.....");
+        assert_eq!(outside[3], ".#.#####");
+        assert_eq!(outside[7], ".#.#....");
+        // Open only diagonally up and left: the inside of one.
+        let inside = rows(1 << 7);
+        assert_eq!(inside[0], ".#.#....");
+        assert_eq!(inside[1], "#..#....");
+        assert_eq!(inside[2], "..#.....");
+        assert_eq!(inside[3], "##......");
+        assert_eq!(inside[7], "........");
+    }
+
+    #[test]
+    fn lines_meet_between_tiles() {
+        // Along the top of the arcade maze every tile has the same lines,
+        // and they carry on out of the corner at the end.
+        let maze = Maze::arcade();
+        let wall = atlas_tile(mask(&maze, TilePos::new(5, 0)));
+        assert_eq!(wall, atlas_tile(mask(&maze, TilePos::new(6, 0))));
+        let corner = rows(mask(&maze, TilePos::new(0, 0)));
+        let next = rows(mask(&maze, TilePos::new(1, 0)));
+        for y in 0..TILE_SIZE as usize {
+            assert_eq!(corner[y].ends_with('#'), next[y].starts_with('#'), "row {}", y);
+        }
+        assert!(next[4].starts_with('#') && next[6].starts_with('#'));
+
+        // Nothing is drawn for walls surrounded by walls.
+        let solid = Maze::parse("###\n###\n###\n").unwrap();
+        assert_eq!(tiles(&solid).count(), 0);
+    }
+}
@@ -0,0 +1,1303 @@
+use actor::{Actor, ActorKind, Actors, Handle, Motion};
+use collision::{self, Collider, CollisionMode};
+use event::Event;
+use failure::{err_msg, Error};
+use fruit::BonusFruit;
+use ghost::{self, Ghost, GhostKind, GhostMode, GhostState, ModeTimer};
+use level::{LevelSpec, LevelTable};
+use mazegen;
+use maze::{Direction, Maze, Tile, Zone};
+use multiplayer::{Party, Role, CATCH_POINTS, MAX_PLAYERS};
+use rng::Rng;
+use score::{Score, ScoreConfig};
+use state::{Decode, Encode, StateReader, StateWriter};
+use Input;
+
+pub const TICKS_PER_SECOND: u32 = 60;
+
+// The seed used by a normal game. Any other seed gives different frightened
+// ghost patterns.
+pub const DEFAULT_SEED: u32 = 0;
+
+// Speeds are percentages where 100% moves 1.25 pixels per tick, so one
+// pixel costs 80 units of accumulated speed.
+const SPEED_UNIT: u32 = 80;
+const HOUSE_SPEED: u32 = 50;
+const EYES_SPEED: u32 = 160;
+
+// Pac-Man stops for a tick after every pellet and three after an energizer.
+const PELLET_STALL: u32 = 1;
+const ENERGIZER_STALL: u32 = 3;
+
+// How long everything stops while the points for an eaten ghost are shown.
+const GHOST_EATEN_FREEZE: u32 = TICKS_PER_SECOND;
+
+// Frightened ghosts alternate between blue and white while flashing.
+pub const FLASH_TICKS: u32 = 14;
+
+// Where the Pac-Men of a co-op party start, relative to the usual spot.
+const PARTY_START_OFFSETS: [i32; MAX_PLAYERS] = [0, -32, 32, -64];
+
+// Returns how many pixels to move this tick and keeps the remainder.
+pub fn steps(progress: &mut u32, speed: u32) -> u32 {
+    *progress += speed;
+    let steps = *progress / SPEED_UNIT;
+    *progress %= SPEED_UNIT;
+    steps
+}
+
+// A Pac-Man's own state. Where it is and which way it's headed is kept by
+// the actor it belongs to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Player {
+    pub next_dir: Option<Direction>,
+    pub moving: bool,
+    // Pixels travelled, drives the chomping animation.
+    pub distance: u32,
+    stall: u32,
+}
+
+impl Player {
+    // The given Pac-Man of a co-op party at its start position, ready to
+    // spawn. The only one outside of a party is number 0.
+    pub fn actor(index: usize, (x, y): (i32, i32)) -> Actor {
+        let player = Player {
+            next_dir: None,
+            moving: true,
+            distance: 0,
+            stall: 0,
+        };
+        Actor::new(ActorKind::PacMan(player), x + PARTY_START_OFFSETS[index % MAX_PLAYERS], y)
+    }
+
+    fn advance(&mut self, motion: &mut Motion, maze: &Maze) {
+        if self.stall > 0 {
+            self.stall -= 1;
+            return;
+        }
+
+        for _ in 0..motion.steps() {
+            // Reversing is always allowed, turning only on a tile center.
+            if let Some(next_dir) = self.next_dir {
+                if next_dir == motion.dir.opposite()
+                    || (motion.at_tile_center() && maze.is_walkable(motion.tile().step(next_dir)))
+                {
+                    motion.dir = next_dir;
+                    self.next_dir = None;
+                }
+            }
+
+            if motion.at_tile_center() && !maze.is_walkable(motion.tile().step(motion.dir)) {
+                self.moving = false;
+                return;
+            }
+
+            let (dx, dy) = motion.dir.delta();
+            motion.x = maze.wrap_x(motion.x + dx);
+            motion.y += dy;
+            self.distance += 1;
+            self.moving = true;
+        }
+    }
+}
+
+// What a player keeps while another one has their turn in a two player
+// game: their own maze, level, score and lives.
+#[derive(Debug, Clone, PartialEq)]
+struct Turn {
+    player: usize,
+    level: u32,
+    maze: Maze,
+    score: Score,
+    fruit: BonusFruit,
+}
+
+impl Encode for Turn {
+    fn encode(&self, w: &mut StateWriter) {
+        w.write_u8(self.player as u8);
+        w.write_u32(self.level);
+        w.write(&self.maze);
+        w.write(&self.score);
+        w.write(&self.fruit);
+    }
+}
+
+// Everything that is simulated. The world knows nothing about textures or
+// the renderer so it can run headless, and it never looks at the clock: the
+// same seed and inputs always give the same state.
+#[derive(Debug, Clone, PartialEq)]
+pub struct World {
+    levels: LevelTable,
+    // The player whose turn it is, the others wait in `turns`.
+    player: usize,
+    turns: Vec<Turn>,
+    level: u32,
+    level_spec: LevelSpec,
+    layout: Maze,
+    maze: Maze,
+    score: Score,
+    events: Vec<Event>,
+    // The lowest score that gets to enter initials. Taken from the high
+    // score table when the game is set up and kept in the save state, so
+    // that replays and the netplay peer decide game over the same way
+    // whatever their own table holds.
+    qualifying_score: u32,
+    // Where the Pac-Men and ghosts are in `actors`, in the order they are
+    // numbered. Just the one Pac-Man outside of a co-op party.
+    pacmen: Vec<Handle>,
+    ghosts: Vec<Handle>,
+    mode: ModeTimer,
+    fright_ticks: u32,
+    freeze_ticks: u32,
+    idle_ticks: u32,
+    actors: Actors,
+    fruit: BonusFruit,
+    party: Option<Party>,
+    party_setup: Option<Party>,
+    // Every level has a maze of its own instead of `layout`.
+    endless: bool,
+    collision_mode: CollisionMode,
+    seed: u32,
+    rng: Rng,
+}
+
+impl World {
+    pub fn new(levels: LevelTable, layout: Maze, seed: u32) -> World {
+        let level_spec = levels.get(1).clone();
+        let mut world = World {
+            levels,
+            player: 0,
+            turns: Vec::new(),
+            level: 1,
+            level_spec,
+            maze: layout.clone(),
+            layout,
+            score: Score::new(ScoreConfig::default()),
+            events: Vec::new(),
+            qualifying_score: 1,
+            pacmen: Vec::new(),
+            ghosts: Vec::new(),
+            mode: ModeTimer::new(1),
+            fright_ticks: 0,
+            freeze_ticks: 0,
+            idle_ticks: 0,
+            actors: Actors::new(),
+            fruit: BonusFruit::new(),
+            party: None,
+            party_setup: None,
+            endless: false,
+            collision_mode: CollisionMode::default(),
+            seed,
+            rng: Rng::for_level(seed, 1),
+        };
+        world.start_level(1);
+        world
+    }
+
+    // Back to how `new` left things, with a different seed.
+    pub fn restart(&mut self, seed: u32) {
+        self.seed = seed;
+        self.events.clear();
+        self.new_game();
+    }
+
+    pub fn new_game(&mut self) {
+        self.start_game(1);
+    }
+
+    // Players take turns, each with a maze of their own.
+    pub fn start_game(&mut self, players: usize) {
+        self.party = None;
+        self.score = Score::new(ScoreConfig::default());
+        self.start_level(1);
+        self.player = 0;
+        self.turns = (1..players)
+            .map(|player| Turn {
+                player,
+                level: self.level,
+                maze: self.maze.clone(),
+                score: self.score.clone(),
+                fruit: self.fruit.clone(),
+            })
+            .collect();
+    }
+
+    // Everyone plays at once, sharing the score and lives.
+    pub fn start_party(&mut self, mut party: Party) {
+        self.start_game(1);
+        party.reset();
+        self.party = Some(party);
+        self.reset_actors();
+    }
+
+    pub fn party(&self) -> Option<&Party> {
+        self.party.as_ref()
+    }
+
+    // The party START plays instead of a one player game. Not part of the
+    // save state, like the level table.
+    pub fn party_setup(&self) -> Option<&Party> {
+        self.party_setup.as_ref()
+    }
+
+    pub fn set_party_setup(&mut self, party: Option<Party>) {
+        self.party_setup = party;
+    }
+
+    pub fn is_endless(&self) -> bool {
+        self.endless
+    }
+
+    // Like the party setup, not part of the save state. Takes effect from
+    // the next level.
+    pub fn set_endless(&mut self, endless: bool) {
+        self.endless = endless;
+    }
+
+    // After a death, hands over to the next player who still has lives left.
+    // Returns false when there is nobody else to hand over to.
+    pub fn next_player(&mut self) -> bool {
+        let player = self.player;
+        let next = self
+            .turns
+            .iter()
+            .enumerate()
+            .filter(|&(_, turn)| !turn.score.is_game_over())
+            .min_by_key(|&(_, turn)| (turn.player < player, turn.player))
+            .map(|(index, _)| index);
+        let index = match next {
+            Some(index) => index,
+            None => return false,
+        };
+
+        let turn = self.turns.remove(index);
+        self.turns.push(Turn {
+            player: self.player,
+            level: self.level,
+            maze: self.maze.clone(),
+            score: self.score.clone(),
+            fruit: self.fruit.clone(),
+        });
+        self.turns.sort_by_key(|turn| turn.player);
+
+        self.player = turn.player;
+        self.level = turn.level;
+        self.level_spec = self.levels.get(turn.level).clone();
+        self.maze = turn.maze;
+        self.score = turn.score;
+        self.fruit = turn.fruit;
+        self.reset_actors();
+        info!("Player {} is up on level {}", self.player + 1, self.level);
+        true
+    }
+
+    pub fn start_level(&mut self, level: u32) {
+        self.level = level;
+        self.level_spec = self.levels.get(level).clone();
+        self.maze = if self.endless {
+            mazegen::generate(Rng::for_level(self.seed, level).next_u32())
+        } else {
+            self.layout.clone()
+        };
+        self.reset_actors();
+        self.fruit = BonusFruit::new();
+        info!("Starting level {}: {:?}", self.level, self.level_spec);
+    }
+
+    // Puts everyone back at their start positions, after a death or on a new
+    // level. Like the arcade, the random sequence starts over as well.
+    pub fn reset_actors(&mut self) {
+        self.rng = Rng::for_level(self.seed, self.level);
+        let pacmen = self.party.as_ref().map_or(1, Party::pacmen);
+        let (start, house) = (self.maze.start_position(), self.maze.house());
+        self.actors.clear();
+        self.pacmen = (0..pacmen)
+            .map(|index| self.actors.spawn(Player::actor(index, start)))
+            .collect();
+        self.ghosts = GhostKind::ALL
+            .iter()
+            .map(|&kind| self.actors.spawn(Ghost::actor(kind, house)))
+            .collect();
+        self.mode = ModeTimer::new(self.level);
+        self.fright_ticks = 0;
+        self.freeze_ticks = 0;
+        self.idle_ticks = 0;
+        self.score.end_fright();
+    }
+
+    // Starts at 0 for player one.
+    pub fn player(&self) -> usize {
+        self.player
+    }
+
+    pub fn players(&self) -> usize {
+        self.turns.len() + 1
+    }
+
+    // The score of any player, whether it's their turn or not.
+    pub fn player_score(&self, player: usize) -> Option<&Score> {
+        if player == self.player {
+            return Some(&self.score);
+        }
+        self.turns.iter().find(|turn| turn.player == player).map(|turn| &turn.score)
+    }
+
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    pub fn level(&self) -> u32 {
+        self.level
+    }
+
+    pub fn levels(&self) -> &LevelTable {
+        &self.levels
+    }
+
+    pub fn level_spec(&self) -> &LevelSpec {
+        &self.level_spec
+    }
+
+    pub fn maze(&self) -> &Maze {
+        &self.maze
+    }
+
+    pub fn score(&self) -> &Score {
+        &self.score
+    }
+
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    // The first Pac-Man, the only one outside of a co-op party.
+    pub fn pacman(&self) -> &Actor {
+        pacman(&self.actors, self.pacmen[0]).0
+    }
+
+    pub fn pacmen(&self) -> impl Iterator<Item = (&Actor, &Player)> {
+        self.pacmen.iter().map(move |&handle| pacman(&self.actors, handle))
+    }
+
+    // Blinky, Pinky, Inky and Clyde.
+    pub fn ghosts(&self) -> impl Iterator<Item = (&Actor, &Ghost)> {
+        self.ghosts.iter().map(move |&handle| ghost(&self.actors, handle))
+    }
+
+    pub fn mode(&self) -> &ModeTimer {
+        &self.mode
+    }
+
+    pub fn fright_ticks(&self) -> u32 {
+        self.fright_ticks
+    }
+
+    // Ticks since Pac-Man last ate a pellet, for releasing ghosts.
+    pub fn idle_ticks(&self) -> u32 {
+        self.idle_ticks
+    }
+
+    // True while frightened ghosts should be drawn white.
+    pub fn is_fright_flashing(&self) -> bool {
+        self.fright_ticks <= self.level_spec.fright_flashes * 2 * FLASH_TICKS
+            && (self.fright_ticks / FLASH_TICKS) % 2 == 1
+    }
+
+    pub fn collision_mode(&self) -> CollisionMode {
+        self.collision_mode
+    }
+
+    // Not part of the save state, like the level table.
+    pub fn set_collision_mode(&mut self, mode: CollisionMode) {
+        self.collision_mode = mode;
+    }
+
+    // What the save state leaves out but two games still have to share to
+    // play out the same: the level table, maze layout and the setup.
+    pub fn encode_setup(&self, w: &mut StateWriter) {
+        w.write(&self.levels);
+        w.write(&self.layout);
+        w.write(&self.party_setup);
+        w.write_bool(self.endless);
+        w.write_u8(match self.collision_mode {
+            CollisionMode::Tile => 0,
+            CollisionMode::Hitbox => 1,
+        });
+    }
+
+    pub fn actors(&self) -> &Actors {
+        &self.actors
+    }
+
+    pub fn fruit(&self) -> &BonusFruit {
+        &self.fruit
+    }
+
+    // True while everything stops to show the points for an eaten ghost.
+    pub fn is_frozen(&self) -> bool {
+        self.freeze_ticks > 0
+    }
+
+    // Restores everything `encode` wrote. The level table and maze layout
+    // aren't part of the state and stay as they are. Everything
+    // is read before anything is changed, so the world is left as it was if
+    // the state turns out to be bad.
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), Error> {
+        let level = r.read_u32()?;
+        if level == 0 {
+            return Err(err_msg("Invalid level 0"));
+        }
+        let maze: Maze = r.read()?;
+        let size = if self.endless {
+            (mazegen::WIDTH, mazegen::HEIGHT)
+        } else {
+            (self.layout.width(), self.layout.height())
+        };
+        if (maze.width(), maze.height()) != size {
+            return Err(err_msg("Maze size doesn't match"));
+        }
+
+        let player = r.read_u8()? as usize;
+        let count = r.read_u8()? as usize;
+        if count >= MAX_PLAYERS {
+            return Err(err_msg(format!("Invalid turn count {}", count)));
+        }
+        let mut turns = Vec::new();
+        for _ in 0..count {
+            let player = r.read_u8()? as usize;
+            let level = r.read_u32()?;
+            if level == 0 {
+                return Err(err_msg("Invalid level 0"));
+            }
+            let maze: Maze = r.read()?;
+            if (maze.width(), maze.height()) != size {
+                return Err(err_msg("Turn maze size doesn't match"));
+            }
+            let mut score = self.score.clone();
+            score.load_state(r)?;
+            turns.push(Turn {
+                player,
+                level,
+                maze,
+                score,
+                fruit: r.read()?,
+            });
+        }
+
+        // The player up and those waiting for a turn are 0..players, once each.
+        let mut numbers: Vec<usize> = turns.iter().map(|turn| turn.player).collect();
+        numbers.push(player);
+        numbers.sort();
+        if numbers.iter().enumerate().any(|(i, &number)| i != number) {
+            return Err(err_msg(format!("Invalid player {}", player)));
+        }
+
+        let mut score = self.score.clone();
+        score.load_state(r)?;
+        let count = r.read_u8()? as usize;
+        if count == 0 || count > MAX_PLAYERS {
+            return Err(err_msg(format!("Invalid Pac-Man count {}", count)));
+        }
+        let pacmen: Vec<Handle> = (0..count).map(|_| r.read()).collect::<Result<_, _>>()?;
+
+        let count = r.read_u8()? as usize;
+        if count != GhostKind::ALL.len() {
+            return Err(err_msg(format!("Invalid ghost count {}", count)));
+        }
+        let ghosts: Vec<Handle> = (0..count).map(|_| r.read()).collect::<Result<_, _>>()?;
+
+        let mut mode = ModeTimer::new(level);
+        mode.load_state(r)?;
+        let fright_ticks = r.read_u32()?;
+        let freeze_ticks = r.read_u32()?;
+        let idle_ticks = r.read_u32()?;
+        let actors: Actors = r.read()?;
+        // Everything else takes these handles on trust.
+        if pacmen.iter().any(|&handle| actors.get(handle).and_then(Actor::as_pacman).is_none()) {
+            return Err(err_msg("Pac-Man handle doesn't point at a Pac-Man"));
+        }
+        if ghosts.iter().any(|&handle| actors.get(handle).and_then(Actor::as_ghost).is_none()) {
+            return Err(err_msg("Ghost handle doesn't point at a ghost"));
+        }
+        let fruit = r.read()?;
+        let party = r.read()?;
+        let qualifying_score = r.read_u32()?;
+        let seed = r.read_u32()?;
+        let rng = r.read()?;
+
+        self.level = level;
+        self.level_spec = self.levels.get(level).clone();
+        self.maze = maze;
+        self.player = player;
+        self.turns = turns;
+        self.score = score;
+        self.pacmen = pacmen;
+        self.ghosts = ghosts;
+        self.mode = mode;
+        self.fright_ticks = fright_ticks;
+        self.freeze_ticks = freeze_ticks;
+        self.idle_ticks = idle_ticks;
+        self.actors = actors;
+        self.fruit = fruit;
+        self.party = party;
+        self.qualifying_score = qualifying_score;
+        self.seed = seed;
+        self.rng = rng;
+        self.events.clear();
+        Ok(())
+    }
+
+    #[cfg(test)]
+    pub fn clear_maze(&mut self) {
+        let tiles = self.maze.tiles().map(|(pos, _)| pos).collect::<Vec<_>>();
+        for pos in tiles {
+            self.maze.eat(pos);
+        }
+    }
+
+    pub fn clear_events(&mut self) {
+        self.events.clear();
+    }
+
+    // Returns true when the last life was lost.
+    pub fn lose_life(&mut self) -> bool {
+        self.score.lose_life(&mut self.events);
+        self.score.is_game_over()
+    }
+
+    pub fn qualifying_score(&self) -> u32 {
+        self.qualifying_score
+    }
+
+    pub fn set_qualifying_score(&mut self, score: u32) {
+        self.qualifying_score = score;
+    }
+
+    // Reports initials entered for the score of the game that just ended.
+    // The world only passes them on, whoever keeps the high score table
+    // puts them on it.
+    pub fn enter_initials(&mut self, initials: [u8; 3]) {
+        self.events.push(Event::HighScore {
+            score: self.score.score(),
+            initials,
+        });
+    }
+
+    // Advances the simulation by one tick. What happened is reported through
+    // `events`, which the caller clears.
+    pub fn update(&mut self, input: &Input) {
+        self.tick(input);
+        let eaten_by = self.fruit.update(
+            &self.level_spec,
+            self.maze.house(),
+            &mut self.actors,
+            &mut self.score,
+            &mut self.rng,
+            &mut self.events,
+        );
+        if let Some(pacman) = eaten_by {
+            self.credit(Role::PacMan(pacman), self.level_spec.fruit_points);
+        }
+    }
+
+    fn tick(&mut self, input: &Input) {
+        if self.freeze_ticks > 0 {
+            // Only the points on show count down, the fruit's time waits.
+            self.actors.tick_if(|actor| matches!(actor.kind, ActorKind::Points(_)));
+            self.freeze_ticks -= 1;
+            return;
+        }
+        self.actors.tick();
+
+        self.update_timers();
+
+        self.steer(input);
+        let speed = if self.fright_ticks > 0 {
+            self.level_spec.pacman_fright_speed
+        } else {
+            self.level_spec.pacman_speed
+        };
+        for &handle in self.pacmen.iter() {
+            let (motion, pacman) = pacman_mut(&mut self.actors, handle);
+            motion.speed = speed;
+            pacman.advance(motion, &self.maze);
+        }
+
+        let ate_pellet = self.eat();
+        if self.maze.pellets_left() == 0 {
+            self.events.push(Event::LevelCleared);
+            return;
+        }
+        self.release_ghosts(ate_pellet);
+
+        if self.collide() {
+            return;
+        }
+        self.move_ghosts();
+        self.collide();
+    }
+
+    // Hands each player's input to whatever they control. Outside of a party
+    // that's just the one Pac-Man.
+    fn steer(&mut self, input: &Input) {
+        let party = match self.party {
+            Some(ref party) => party,
+            None => {
+                if let Some(dir) = input.direction() {
+                    pacman_mut(&mut self.actors, self.pacmen[0]).1.next_dir = Some(dir);
+                }
+                return;
+            }
+        };
+
+        for player in 0..party.players() {
+            let dir = match input.player_direction(player) {
+                Some(dir) => dir,
+                None => continue,
+            };
+            match party.role(player) {
+                Role::PacMan(index) => {
+                    if let Some(&handle) = self.pacmen.get(index) {
+                        pacman_mut(&mut self.actors, handle).1.next_dir = Some(dir);
+                    }
+                }
+                Role::Ghost(kind) => {
+                    for &handle in self.ghosts.iter() {
+                        let (_, ghost) = ghost_mut(&mut self.actors, handle);
+                        if ghost.kind == kind {
+                            ghost.steer = Some(dir);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Adds to the points of the player in that role, if there is a party.
+    fn credit(&mut self, role: Role, points: u32) {
+        if let Some(ref mut party) = self.party {
+            if let Some(player) = party.player_for(role) {
+                party.credit(player, points);
+            }
+        }
+    }
+
+    fn update_timers(&mut self) {
+        if self.fright_ticks > 0 {
+            self.fright_ticks -= 1;
+            if self.fright_ticks == 0 {
+                self.end_fright();
+            }
+        } else if self.mode.tick() {
+            for &handle in self.ghosts.iter() {
+                let (motion, ghost) = ghost_mut(&mut self.actors, handle);
+                ghost.reverse(motion);
+            }
+        }
+    }
+
+    fn end_fright(&mut self) {
+        for &handle in self.ghosts.iter() {
+            ghost_mut(&mut self.actors, handle).1.frightened = false;
+        }
+        self.score.end_fright();
+    }
+
+    fn eat(&mut self) -> bool {
+        let mut ate = false;
+        for index in 0..self.pacmen.len() {
+            let before = self.score.score();
+            if self.eat_with(index) {
+                ate = true;
+                let points = self.score.score() - before;
+                self.credit(Role::PacMan(index), points);
+            }
+        }
+        ate
+    }
+
+    fn eat_with(&mut self, index: usize) -> bool {
+        let handle = self.pacmen[index];
+        match self.maze.eat(pacman(&self.actors, handle).0.tile()) {
+            Tile::Pellet => {
+                self.score.eat_pellet(&mut self.events);
+                pacman_mut(&mut self.actors, handle).1.stall = PELLET_STALL;
+                true
+            }
+            Tile::Energizer => {
+                self.score.eat_energizer(&mut self.events);
+                pacman_mut(&mut self.actors, handle).1.stall = ENERGIZER_STALL;
+                self.fright_ticks = self.level_spec.fright_seconds * TICKS_PER_SECOND;
+                let frightened = self.fright_ticks > 0;
+                for &handle in self.ghosts.iter() {
+                    let (motion, ghost) = ghost_mut(&mut self.actors, handle);
+                    // Ghosts turn around even on levels where they no longer turn blue.
+                    ghost.reverse(motion);
+                    if ghost.state != GhostState::Eaten && ghost.state != GhostState::EnteringHouse {
+                        ghost.frightened = frightened;
+                    }
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Ghosts leave the house one at a time, either once their pellet counter
+    // reaches the limit or when Pac-Man hasn't eaten anything for a while.
+    fn release_ghosts(&mut self, ate_pellet: bool) {
+        if ate_pellet {
+            self.idle_ticks = 0;
+        } else {
+            self.idle_ticks += 1;
+        }
+
+        let level = self.level;
+        let idle = self.idle_ticks >= ghost::idle_limit(level);
+        let waiting = self
+            .ghosts
+            .iter()
+            .find(|&&handle| ghost(&self.actors, handle).1.state == GhostState::InHouse);
+        if let Some(&handle) = waiting {
+            let (_, ghost) = ghost_mut(&mut self.actors, handle);
+            if ate_pellet {
+                ghost.dot_counter += 1;
+            }
+            if idle || ghost.dot_counter >= ghost.kind.dot_limit(level) {
+                ghost.state = GhostState::LeavingHouse;
+                self.idle_ticks = 0;
+            }
+        }
+    }
+
+    fn move_ghosts(&mut self) {
+        let blinky_tile = ghost(&self.actors, self.ghosts[0]).0.tile();
+        let mode = self.mode.mode();
+        let elroy_speed = self.elroy_speed();
+
+        for &handle in self.ghosts.iter() {
+            let (actor, ghost) = ghost(&self.actors, handle);
+            let tile = actor.tile();
+            let elroy = ghost.kind == GhostKind::Blinky && elroy_speed.is_some();
+            let target = if mode == GhostMode::Chase || elroy {
+                // With more than one Pac-Man each ghost goes after the closest.
+                let pacman = self
+                    .pacmen
+                    .iter()
+                    .map(|&handle| pacman(&self.actors, handle).0)
+                    .min_by_key(|pacman| pacman.tile().distance_squared(tile))
+                    .unwrap();
+                ghost.chase_target(tile, pacman.tile(), pacman.motion.dir, blinky_tile)
+            } else {
+                ghost.kind.scatter_target()
+            };
+
+            let speed = match ghost.state {
+                GhostState::InHouse | GhostState::LeavingHouse => HOUSE_SPEED,
+                GhostState::Eaten | GhostState::EnteringHouse => EYES_SPEED,
+                GhostState::Active if self.maze.zone(tile) == Zone::Tunnel => self.level_spec.ghost_tunnel_speed,
+                GhostState::Active if ghost.frightened => self.level_spec.ghost_fright_speed,
+                GhostState::Active => match elroy_speed {
+                    Some(speed) if elroy => speed,
+                    _ => self.level_spec.ghost_speed,
+                },
+            };
+
+            let (motion, ghost) = ghost_mut(&mut self.actors, handle);
+            ghost.target = target;
+            motion.speed = speed;
+            ghost.advance(motion, &self.maze, &mut self.rng);
+        }
+    }
+
+    // Blinky speeds up as the maze empties.
+    fn elroy_speed(&self) -> Option<u32> {
+        let pellets_left = self.maze.pellets_left();
+        if pellets_left <= self.level_spec.elroy2_dots_left {
+            Some(self.level_spec.elroy2_speed)
+        } else if pellets_left <= self.level_spec.elroy1_dots_left {
+            Some(self.level_spec.elroy1_speed)
+        } else {
+            None
+        }
+    }
+
+    // Reports everything each Pac-Man touches, then deals with the ghosts.
+    // Returns true when something happened that stops this tick.
+    fn collide(&mut self) -> bool {
+        for index in 0..self.pacmen.len() {
+            if self.collide_with(index) {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn collide_with(&mut self, pacman: usize) -> bool {
+        let body = self.actors.get(self.pacmen[pacman]).unwrap().body();
+        // Ghosts are dealt with below, Pac-Men don't get in each other's way.
+        let others = self.actors.iter().filter(|&(_, actor)| !actor.kind.is_character());
+        for (handle, actor) in others {
+            if collision::touches(self.collision_mode, &body, &actor.body()) {
+                self.events.push(Event::Collision {
+                    pacman,
+                    with: Collider::Actor(handle),
+                });
+            }
+        }
+
+        let mode = self.collision_mode;
+        let touched = self.ghosts().position(|(actor, ghost)| {
+            ghost.state == GhostState::Active && collision::touches(mode, &body, &actor.body())
+        });
+        let index = match touched {
+            Some(index) => index,
+            None => return false,
+        };
+
+        self.events.push(Event::Collision {
+            pacman,
+            with: Collider::Ghost(index),
+        });
+        let (motion, ghost) = ghost_mut(&mut self.actors, self.ghosts[index]);
+        if ghost.frightened {
+            ghost.eaten();
+            let (x, y) = (motion.x, motion.y);
+            let points = self.score.eat_ghost(&mut self.events);
+            self.actors.spawn(Actor::new(ActorKind::Points(points), x, y).with_lifetime(GHOST_EATEN_FREEZE));
+            self.freeze_ticks = GHOST_EATEN_FREEZE;
+            self.credit(Role::PacMan(pacman), points);
+        } else {
+            let kind = ghost.kind;
+            self.events.push(Event::PacManCaught);
+            self.credit(Role::Ghost(kind), CATCH_POINTS);
+        }
+        true
+    }
+}
+
+// Where the handles in `World` point, which `load_state` checks.
+fn pacman(actors: &Actors, handle: Handle) -> (&Actor, &Player) {
+    let actor = actors.get(handle).expect("Pac-Man despawned");
+    (actor, actor.as_pacman().expect("Not a Pac-Man"))
+}
+
+fn pacman_mut(actors: &mut Actors, handle: Handle) -> (&mut Motion, &mut Player) {
+    actors
+        .get_mut(handle)
+        .and_then(Actor::as_pacman_mut)
+        .expect("Not a Pac-Man")
+}
+
+fn ghost(actors: &Actors, handle: Handle) -> (&Actor, &Ghost) {
+    let actor = actors.get(handle).expect("Ghost despawned");
+    (actor, actor.as_ghost().expect("Not a ghost"))
+}
+
+fn ghost_mut(actors: &mut Actors, handle: Handle) -> (&mut Motion, &mut Ghost) {
+    actors.get_mut(handle).and_then(Actor::as_ghost_mut).expect("Not a ghost")
+}
+
+impl Encode for Player {
+    fn encode(&self, w: &mut StateWriter) {
+        w.write(&self.next_dir);
+        w.write_bool(self.moving);
+        w.write_u32(self.distance);
+        w.write_u32(self.stall);
+    }
+}
+
+impl Decode for Player {
+    fn decode(r: &mut StateReader) -> Result<Player, Error> {
+        Ok(Player {
+            next_dir: r.read()?,
+            moving: r.read_bool()?,
+            distance: r.read_u32()?,
+            stall: r.read_u32()?,
+        })
+    }
+}
+
+impl Encode for World {
+    fn encode(&self, w: &mut StateWriter) {
+        w.write_u32(self.level);
+        w.write(&self.maze);
+        w.write_u8(self.player as u8);
+        w.write_u8(self.turns.len() as u8);
+        for turn in self.turns.iter() {
+            w.write(turn);
+        }
+        w.write(&self.score);
+        w.write_u8(self.pacmen.len() as u8);
+        for pacman in self.pacmen.iter() {
+            w.write(pacman);
+        }
+        w.write_u8(self.ghosts.len() as u8);
+        for ghost in self.ghosts.iter() {
+            w.write(ghost);
+        }
+        w.write(&self.mode);
+        w.write_u32(self.fright_ticks);
+        w.write_u32(self.freeze_ticks);
+        w.write_u32(self.idle_ticks);
+        w.write(&self.actors);
+        w.write(&self.fruit);
+        w.write(&self.party);
+        w.write_u32(self.qualifying_score);
+        w.write_u32(self.seed);
+        w.write(&self.rng);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use autoplay::{Bot, InputSource};
+    use level::Fruit;
+    use maze::{TilePos, TILE_SIZE};
+    use multiplayer::Mode;
+    use Buttons;
+
+    fn world(seed: u32) -> World {
+        World::new(LevelTable::arcade(), Maze::arcade(), seed)
+    }
+
+    fn pacman_at(world: &mut World, index: usize) -> &mut Motion {
+        pacman_mut(&mut world.actors, world.pacmen[index]).0
+    }
+
+    fn ghost_at(world: &mut World, index: usize) -> &mut Motion {
+        ghost_mut(&mut world.actors, world.ghosts[index]).0
+    }
+
+    // Wanders around the maze, changing direction every so often.
+    fn play(world: &mut World, ticks: u32) {
+        let directions = [Buttons::UP, Buttons::LEFT, Buttons::DOWN, Buttons::RIGHT];
+        let mut input = Input::new();
+        for tick in 0..ticks {
+            input.update(directions[(tick / 45 % 4) as usize]);
+            world.clear_events();
+            world.update(&input);
+            if world.events().contains(&Event::PacManCaught) {
+                world.reset_actors();
+            }
+        }
+    }
+
+    #[test]
+    fn same_inputs_give_identical_state() {
+        let mut a = world(42);
+        let mut b = world(42);
+        play(&mut a, 5_000);
+        play(&mut b, 5_000);
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn save_state_round_trips() {
+        let mut a = world(7);
+        play(&mut a, 3_000);
+        let data = ::state::encode(|w| w.write(&a));
+
+        let mut b = world(7);
+        let mut r = ::state::decode(&data).unwrap();
+        b.load_state(&mut r).unwrap();
+        r.finish().unwrap();
+        assert_eq!(a, b);
+
+        // And they keep going the same way.
+        play(&mut a, 3_000);
+        play(&mut b, 3_000);
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn bad_state_changes_nothing() {
+        let mut a = world(7);
+        play(&mut a, 1_000);
+        let before = a.clone();
+
+        // A new level and maze, but the state ends before the score.
+        let data = ::state::encode(|w| {
+            w.write_u32(a.level() + 1);
+            w.write(&a.layout);
+            w.write_u8(0);
+            w.write_u8(0);
+        });
+        assert!(a.load_state(&mut ::state::decode(&data).unwrap()).is_err());
+        assert_eq!(a, before);
+    }
+
+    #[test]
+    fn handles_have_to_match_their_actors() {
+        let mut a = world(7);
+        let before = a.clone();
+
+        let mut b = a.clone();
+        b.pacmen[0] = b.ghosts[0];
+        let data = ::state::encode(|w| w.write(&b));
+        assert!(a.load_state(&mut ::state::decode(&data).unwrap()).is_err());
+
+        let mut b = a.clone();
+        b.actors.despawn(b.ghosts[3]);
+        let data = ::state::encode(|w| w.write(&b));
+        assert!(a.load_state(&mut ::state::decode(&data).unwrap()).is_err());
+        assert_eq!(a, before);
+    }
+
+    #[test]
+    fn players_and_turn_mazes_are_checked() {
+        let mut a = world(7);
+        let before = a.clone();
+        let turn = |player, maze: &Maze| Turn {
+            player,
+            level: 1,
+            maze: maze.clone(),
+            score: before.score.clone(),
+            fruit: before.fruit.clone(),
+        };
+
+        let mut b = a.clone();
+        b.player = 1;
+        let data = ::state::encode(|w| w.write(&b));
+        assert!(a.load_state(&mut ::state::decode(&data).unwrap()).is_err());
+
+        let mut b = a.clone();
+        b.turns.push(turn(0, &a.maze));
+        let data = ::state::encode(|w| w.write(&b));
+        assert!(a.load_state(&mut ::state::decode(&data).unwrap()).is_err());
+
+        let mut b = a.clone();
+        b.turns.push(turn(1, &Maze::parse("#.#\n").unwrap()));
+        let data = ::state::encode(|w| w.write(&b));
+        assert!(a.load_state(&mut ::state::decode(&data).unwrap()).is_err());
+
+        let mut b = a.clone();
+        b.turns.push(turn(1, &a.maze));
+        let data = ::state::encode(|w| w.write(&b));
+        assert!(a.clone().load_state(&mut ::state::decode(&data).unwrap()).is_ok());
+        assert_eq!(a, before);
+    }
+
+    #[test]
+    fn touching_a_ghost_is_reported() {
+        let mut world = world(1);
+        let Motion { x, y, .. } = world.pacman().motion;
+        ghost_at(&mut world, 0).x = x + 8;
+        ghost_at(&mut world, 0).y = y;
+        world.update(&Input::new());
+        assert!(!world.events().contains(&Event::PacManCaught));
+
+        world.set_collision_mode(CollisionMode::Hitbox);
+        let Motion { x, y, .. } = world.pacman().motion;
+        ghost_at(&mut world, 0).x = x + 4;
+        ghost_at(&mut world, 0).y = y;
+        world.update(&Input::new());
+        assert_eq!(
+            world.events()[..2],
+            [
+                Event::Collision {
+                    pacman: 0,
+                    with: Collider::Ghost(0)
+                },
+                Event::PacManCaught
+            ]
+        );
+    }
+
+    #[test]
+    fn pacman_wraps_through_tunnels() {
+        let mut world = world(1);
+        pacman_at(&mut world, 0).x = 4;
+        pacman_at(&mut world, 0).y = 14 * TILE_SIZE + 4;
+        pacman_at(&mut world, 0).dir = Direction::Left;
+        for _ in 0..10 {
+            world.update(&Input::new());
+        }
+        let x = world.pacman().motion.x;
+        assert!(x > 200, "Pac-Man at {}", x);
+    }
+
+    #[test]
+    fn ghosts_slow_down_in_tunnels() {
+        let mut tunnel = world(1);
+        ghost_at(&mut tunnel, 0).x = 2 * TILE_SIZE + 4;
+        ghost_at(&mut tunnel, 0).y = 14 * TILE_SIZE + 4;
+        ghost_at(&mut tunnel, 0).dir = Direction::Right;
+
+        let mut normal = world(1);
+        ghost_at(&mut normal, 0).x = 2 * TILE_SIZE + 4;
+        ghost_at(&mut normal, 0).y = 5 * TILE_SIZE + 4;
+        ghost_at(&mut normal, 0).dir = Direction::Right;
+
+        for _ in 0..20 {
+            tunnel.update(&Input::new());
+            normal.update(&Input::new());
+        }
+        assert!(ghost_at(&mut tunnel, 0).x < ghost_at(&mut normal, 0).x);
+    }
+
+    #[test]
+    fn ghosts_dont_turn_up_in_red_zones() {
+        let maze = Maze::parse("#####\n## ##\n#___#\n#####\n").unwrap();
+        let mut actor = Ghost::actor(GhostKind::Blinky, Maze::arcade().house());
+        let (motion, ghost) = actor.as_ghost_mut().unwrap();
+        ghost.state = GhostState::Active;
+        motion.x = 2 * TILE_SIZE + 4;
+        motion.y = 2 * TILE_SIZE + 4;
+        motion.dir = Direction::Right;
+        motion.speed = 80;
+        ghost.target = TilePos::new(2, 0);
+        ghost.advance(motion, &maze, &mut Rng::new(1));
+        assert_eq!(motion.dir, Direction::Right);
+
+        let maze = Maze::parse("#####\n## ##\n#   #\n#####\n").unwrap();
+        motion.x = 2 * TILE_SIZE + 4;
+        motion.dir = Direction::Right;
+        ghost.advance(motion, &maze, &mut Rng::new(1));
+        assert_eq!(motion.dir, Direction::Up);
+    }
+
+    #[test]
+    fn fruit_comes_out_below_the_ghost_house() {
+        let mut world = world(1);
+        let mut bot = Bot::default();
+        let mut input = Input::new();
+        while world.fruit().handle(world.actors()).is_none() {
+            assert!(world.fruit().dots() < 70);
+            input.update(bot.next(&world).unwrap());
+            world.clear_events();
+            world.update(&input);
+            if world.events().contains(&Event::PacManCaught) {
+                world.reset_actors();
+            }
+        }
+        assert_eq!(world.fruit().dots(), 70);
+
+        let handle = world.fruit().handle(world.actors()).unwrap();
+        let Motion { x, y, .. } = world.actors().get(handle).unwrap().motion;
+        pacman_at(&mut world, 0).x = x;
+        pacman_at(&mut world, 0).y = y;
+        pacman_mut(&mut world.actors, world.pacmen[0]).1.stall = 1;
+        world.clear_events();
+        world.update(&Input::new());
+        assert!(world.events().contains(&Event::FruitEaten {
+            fruit: Fruit::Cherries,
+            points: 100
+        }));
+        assert!(world.fruit().handle(world.actors()).is_none());
+    }
+
+    #[test]
+    fn fruit_waits_while_frozen() {
+        let mut world = world(1);
+        world.new_game();
+        let fruit = world.actors.spawn(Actor::new(ActorKind::Fruit(Fruit::Cherries), 0, 0).with_lifetime(5));
+        let points = world.actors.spawn(Actor::new(ActorKind::Points(200), 0, 0).with_lifetime(5));
+        world.freeze_ticks = 3;
+        for _ in 0..3 {
+            world.update(&Input::new());
+        }
+        assert_eq!(world.actors().get(fruit).unwrap().lifetime, Some(5));
+        assert_eq!(world.actors().get(points).unwrap().lifetime, Some(2));
+    }
+
+    #[test]
+    fn players_keep_their_own_maze() {
+        let mut world = world(1);
+        world.start_game(2);
+        assert_eq!(world.players(), 2);
+        play(&mut world, 600);
+        let pellets = world.maze().pellets_left();
+        let score = world.score().score();
+        assert!(score > 0);
+
+        assert!(world.next_player());
+        assert_eq!(world.player(), 1);
+        assert_eq!(world.maze(), &world.layout);
+        assert_eq!(world.score().score(), 0);
+        assert_eq!(world.player_score(0).unwrap().score(), score);
+
+        assert!(world.next_player());
+        assert_eq!(world.player(), 0);
+        assert_eq!(world.maze().pellets_left(), pellets);
+        assert_eq!(world.score().score(), score);
+
+        // Nobody to hand over to once the other player is out.
+        world.next_player();
+        while !world.lose_life() {}
+        assert!(world.next_player());
+        assert!(!world.next_player());
+        assert_eq!(world.player(), 0);
+    }
+
+    #[test]
+    fn endless_levels_get_new_mazes() {
+        let mut a = world(3);
+        a.set_endless(true);
+        a.new_game();
+        assert!(!a.maze().same_walls(&a.layout));
+        assert_eq!(a.maze().check(), Vec::new());
+        let first = a.maze().clone();
+        a.start_level(2);
+        assert!(!a.maze().same_walls(&first));
+
+        play(&mut a, 1_000);
+        a.clear_events();
+        let data = ::state::encode(|w| w.write(&a));
+        let mut b = world(3);
+        b.set_endless(true);
+        b.load_state(&mut ::state::decode(&data).unwrap()).unwrap();
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn coop_players_score_their_own_pellets() {
+        let mut world = world(1);
+        world.start_party(Party::new(Mode::Coop, 2));
+        assert_eq!(world.pacmen().count(), 2);
+
+        let mut input = Input::new();
+        for _ in 0..120 {
+            input.update_players(&[Buttons::LEFT, Buttons::RIGHT]);
+            world.update(&input);
+        }
+        let party = world.party().unwrap();
+        assert!(party.points(0) > 0 && party.points(1) > 0);
+        assert_eq!(party.points(0) + party.points(1), world.score().score());
+    }
+
+    #[test]
+    fn versus_players_steer_ghosts() {
+        let mut world = world(1);
+        world.start_party(Party::new(Mode::Versus, 2));
+        assert_eq!(world.pacmen().count(), 1);
+
+        // Left to itself Blinky would carry on right towards its corner.
+        ghost_at(&mut world, 0).x = 6 * TILE_SIZE + 2;
+        ghost_at(&mut world, 0).y = 5 * TILE_SIZE + 4;
+        ghost_at(&mut world, 0).dir = Direction::Right;
+        let mut input = Input::new();
+        for _ in 0..10 {
+            input.update_players(&[Buttons::empty(), Buttons::DOWN]);
+            world.update(&input);
+        }
+        assert_eq!(ghost_at(&mut world, 0).dir, Direction::Down);
+
+        let Motion { x, y, .. } = world.pacman().motion;
+        ghost_at(&mut world, 0).x = x;
+        ghost_at(&mut world, 0).y = y;
+        world.clear_events();
+        world.update(&Input::new());
+        assert!(world.events().contains(&Event::PacManCaught));
+        assert_eq!(world.party().unwrap().points(1), CATCH_POINTS);
+    }
+
+    #[test]
+    fn random_sequence_restarts_each_level() {
+        let mut world = world(42);
+        play(&mut world, 1_000);
+        world.start_level(2);
+        assert_eq!(world.rng, Rng::for_level(42, 2));
+    }
+}